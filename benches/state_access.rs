@@ -0,0 +1,275 @@
+//! Compares the zero-copy Pod views of the large accounts and of escrows with unpacking and
+//! repacking them, for the accesses the processor makes. Both have to leave the same bytes.
+//!
+//! cargo bench --bench state_access
+
+use solana_escrow::state::{
+    Escrow, Market, MarketEntry, Milestone, MilestoneEscrow, MilestoneStatus, MintPolicy,
+    PodEscrow, PodMarket, PodMilestoneEscrow, PodMintPolicy, PolicyMode, Refill, LEGACY_ESCROW_LEN,
+    MAX_MARKET_ENTRIES, MAX_MILESTONES, MAX_POLICY_MINTS,
+};
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const ITERATIONS: u32 = 100_000;
+
+/// Average time of one call of f, which starts from a fresh copy of the account data
+fn measure<F: FnMut(&mut [u8])>(data: &[u8], mut f: F) -> Duration {
+    let mut scratch = data.to_vec();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        scratch.copy_from_slice(data);
+        f(black_box(&mut scratch));
+    }
+    start.elapsed() / ITERATIONS
+}
+
+/// Runs both accesses once to check they agree, then prints their timings
+fn compare<P: FnMut(&mut [u8]), Z: FnMut(&mut [u8])>(
+    name: &str,
+    data: &[u8],
+    mut pack: P,
+    mut pod: Z,
+) {
+    let mut packed = data.to_vec();
+    pack(&mut packed);
+    let mut zero_copy = data.to_vec();
+    pod(&mut zero_copy);
+    assert!(
+        packed == zero_copy,
+        "{}: Pack and Pod wrote different data",
+        name
+    );
+
+    let pack_time = measure(data, pack);
+    let pod_time = measure(data, pod);
+    println!(
+        "{:<28} {:>10?} {:>10?} {:>8.1}x",
+        name,
+        pack_time,
+        pod_time,
+        pack_time.as_secs_f64() / pod_time.as_secs_f64()
+    );
+}
+
+/// Market one entry short of full, with prices getting worse by the entry
+fn market_data() -> Vec<u8> {
+    let market = Market {
+        is_initialized: true,
+        mint0: Pubkey::new_unique(),
+        mint1: Pubkey::new_unique(),
+        entries: (0..MAX_MARKET_ENTRIES as u64 - 1)
+            .map(|i| MarketEntry {
+                escrow_pubkey: Pubkey::new_unique(),
+                amount0: 100,
+                amount1: 200 + i,
+            })
+            .collect(),
+    };
+    let mut data = vec![0; Market::LEN];
+    Market::pack(market, &mut data).unwrap();
+    data
+}
+
+fn mint_policy_data() -> Vec<u8> {
+    let mint_policy = MintPolicy {
+        is_initialized: true,
+        mode: PolicyMode::Allowlist,
+        mints: (0..MAX_POLICY_MINTS - 1)
+            .map(|_| Pubkey::new_unique())
+            .collect(),
+    };
+    let mut data = vec![0; MintPolicy::LEN];
+    MintPolicy::pack(mint_policy, &mut data).unwrap();
+    data
+}
+
+fn milestone_escrow_data() -> Vec<u8> {
+    let mut milestones = [Milestone::default(); MAX_MILESTONES];
+    for (i, milestone) in milestones.iter_mut().enumerate() {
+        milestone.amount = 100 + i as u64;
+    }
+    let escrow = MilestoneEscrow {
+        is_initialized: true,
+        payer_pubkey: Pubkey::new_unique(),
+        tmp_token0_pubkey: Pubkey::new_unique(),
+        payer_token0_pubkey: Pubkey::new_unique(),
+        payee_token0_pubkey: Pubkey::new_unique(),
+        milestone_count: MAX_MILESTONES as u8,
+        milestones,
+    };
+    let mut data = vec![0; MilestoneEscrow::LEN];
+    MilestoneEscrow::pack(escrow, &mut data).unwrap();
+    data
+}
+
+/// A listed escrow that expires, standing order adds the refill. The legacy layout only holds
+/// an escrow that is neither
+fn escrow_data(standing_order: bool, legacy: bool) -> Vec<u8> {
+    let escrow = Escrow {
+        is_initialized: true,
+        maker_pubkey: Pubkey::new_unique(),
+        tmp_token0_pubkey: Pubkey::new_unique(),
+        maker_token1_pubkey: Pubkey::new_unique(),
+        maker_token1_expected_amount: 200,
+        market_pubkey: if legacy {
+            None
+        } else {
+            Some(Pubkey::new_unique())
+        },
+        expires_at: if legacy { None } else { Some(1_700_000_000) },
+        refill: if standing_order {
+            Some(Refill {
+                funding_pubkey: Pubkey::new_unique(),
+                tranche_amount: 100,
+                remaining_amount: 1_000,
+            })
+        } else {
+            None
+        },
+        ..Escrow::default()
+    };
+    if legacy {
+        let mut data = vec![0; LEGACY_ESCROW_LEN];
+        Escrow::pack_legacy(escrow, &mut data).unwrap();
+        data
+    } else {
+        let mut data = vec![0; escrow.get_packed_len()];
+        Escrow::pack(escrow, &mut data).unwrap();
+        data
+    }
+}
+
+fn main() {
+    println!(
+        "{:<28} {:>10} {:>10} {:>9}",
+        "access", "Pack", "Pod", "speedup"
+    );
+
+    // listing an escrow in the middle of the order book and delisting one, as InitEscrow and
+    // Exchange or CancelEscrow do
+    let data = market_data();
+    let listed = MarketEntry {
+        escrow_pubkey: Pubkey::new_unique(),
+        amount0: 100,
+        amount1: 215,
+    };
+    let delisted = Market::unpack(&data).unwrap().entries[10].escrow_pubkey;
+    compare(
+        "market insert",
+        &data,
+        |data| {
+            let mut market = Market::unpack(data).unwrap();
+            market.insert(listed).unwrap();
+            Market::pack(market, data).unwrap();
+        },
+        |data| {
+            PodMarket::load_mut(data).unwrap().insert(listed).unwrap();
+        },
+    );
+    compare(
+        "market remove",
+        &data,
+        |data| {
+            let mut market = Market::unpack(data).unwrap();
+            assert!(market.remove(&delisted));
+            Market::pack(market, data).unwrap();
+        },
+        |data| {
+            assert!(PodMarket::load_mut(data).unwrap().remove(&delisted));
+        },
+    );
+
+    // checking a mint that is listed last, as InitEscrow does for every mint it trades
+    let data = mint_policy_data();
+    let allowed =
+        Pubkey::new_from_array(*PodMintPolicy::load(&data).unwrap().mints().last().unwrap());
+    compare(
+        "mint policy allows",
+        &data,
+        |data| assert!(MintPolicy::unpack(data).unwrap().allows(&allowed)),
+        |data| assert!(PodMintPolicy::load(data).unwrap().allows(&allowed)),
+    );
+    let added = Pubkey::new_unique();
+    compare(
+        "mint policy add",
+        &data,
+        |data| {
+            let mut mint_policy = MintPolicy::unpack(data).unwrap();
+            mint_policy.mints.push(added);
+            MintPolicy::pack(mint_policy, data).unwrap();
+        },
+        |data| PodMintPolicy::load_mut(data).unwrap().push(&added).unwrap(),
+    );
+    compare(
+        "mint policy remove",
+        &data,
+        |data| {
+            let mut mint_policy = MintPolicy::unpack(data).unwrap();
+            mint_policy.mints.remove(0);
+            MintPolicy::pack(mint_policy, data).unwrap();
+        },
+        |data| PodMintPolicy::load_mut(data).unwrap().remove(0),
+    );
+
+    // approving a milestone as ApproveMilestone does
+    let data = milestone_escrow_data();
+    compare(
+        "milestone approve",
+        &data,
+        |data| {
+            let mut escrow = MilestoneEscrow::unpack(data).unwrap();
+            escrow.milestones[3].status = MilestoneStatus::Approved;
+            assert!(!escrow.is_settled());
+            MilestoneEscrow::pack(escrow, data).unwrap();
+        },
+        |data| {
+            let escrow = PodMilestoneEscrow::load_mut(data).unwrap();
+            escrow.milestones[3].status = MilestoneStatus::Approved as u8;
+            assert!(!escrow.is_settled());
+        },
+    );
+
+    // checking an escrow before settling it, as BatchExchange and Match do
+    for (name, legacy) in [("escrow check", false), ("legacy escrow check", true)].iter() {
+        let data = escrow_data(false, *legacy);
+        compare(
+            name,
+            &data,
+            |data| {
+                let escrow = Escrow::unpack(data).unwrap();
+                assert!(escrow.is_plain() && escrow.market_pubkey.is_some() != *legacy);
+                black_box(escrow.maker_token1_expected_amount);
+            },
+            |data| {
+                let escrow = PodEscrow::load(data).unwrap();
+                assert!(escrow.is_plain() && escrow.market_pubkey.is_some() != *legacy);
+                black_box(u64::from(escrow.header.maker_token1_expected_amount));
+            },
+        );
+    }
+
+    // a standing order pulling its next tranche, as Exchange does
+    let data = escrow_data(true, false);
+    compare(
+        "standing order refill",
+        &data,
+        |data| {
+            let mut escrow = Escrow::unpack(data).unwrap();
+            let refill = escrow.refill.unwrap();
+            escrow.refill = Some(Refill {
+                remaining_amount: refill.remaining_amount - refill.tranche_amount,
+                ..refill
+            });
+            Escrow::pack(escrow, data).unwrap();
+        },
+        |data| {
+            let refill = PodEscrow::load_refill_mut(data).unwrap().unwrap();
+            refill.remaining_amount =
+                (u64::from(refill.remaining_amount) - u64::from(refill.tranche_amount)).into();
+        },
+    );
+}
@@ -18,6 +18,93 @@ pub enum EscrowError {
     /// Amount Overflow
     #[error("Amount Overflow")]
     AmountOverflow,
+    /// Preimage does not hash to the hashlock
+    #[error("Invalid Preimage")]
+    InvalidPreimage,
+    /// Timeout of a time-locked escrow has passed
+    #[error("Timeout Expired")]
+    TimeoutExpired,
+    /// Timeout of a time-locked escrow has not passed yet
+    #[error("Timeout Not Expired")]
+    TimeoutNotExpired,
+    /// Escrow is disputed and can only be resolved by the arbiter
+    #[error("Dispute Active")]
+    DisputeActive,
+    /// Escrow is not disputed
+    #[error("No Dispute")]
+    NoDispute,
+    /// Vault does not hold enough tokens
+    #[error("Insufficient Vault Balance")]
+    InsufficientVaultBalance,
+    /// Milestone does not exist or is already approved
+    #[error("Invalid Milestone")]
+    InvalidMilestone,
+    /// Vesting schedule is not start <= cliff <= end with start < end
+    #[error("Invalid Vesting Schedule")]
+    InvalidVestingSchedule,
+    /// Vesting cannot be revoked by the funder
+    #[error("Not Revocable")]
+    NotRevocable,
+    /// Total amount paid exceeds the taker's cap
+    #[error("Spending Cap Exceeded")]
+    SpendingCapExceeded,
+    /// Escrow uses a feature this instruction does not support
+    #[error("Unsupported Escrow Feature")]
+    UnsupportedEscrowFeature,
+    /// Escrows do not offer what the other one asks for
+    #[error("Escrows Not Crossed")]
+    EscrowsNotCrossed,
+    /// Market is full and the escrow's price is not better than its worst entry
+    #[error("Market Full")]
+    MarketFull,
+    /// Referral fee is above the protocol config's cap
+    #[error("Referral Fee Too High")]
+    ReferralFeeTooHigh,
+    /// Mint charges a transfer fee, so vaults and recipients would not receive the escrowed amounts
+    #[error("Transfer Fee Mint")]
+    TransferFeeMint,
+    /// Mint's tokens cannot be transferred
+    #[error("Non Transferable Mint")]
+    NonTransferableMint,
+    /// Mint has a permanent delegate or transfer hook
+    #[error("Unsupported Mint Extension")]
+    UnsupportedMintExtension,
+    /// Token account only accepts transfers with a memo
+    #[error("Memo Transfer Required")]
+    MemoTransferRequired,
+    /// Wrapping or unwrapping SOL needs a classic token account of the native mint
+    #[error("Not Native Mint")]
+    NotNativeMint,
+    /// Maker revoked, changed or reduced the PDA's delegation of a non-custodial escrow
+    #[error("Delegation Revoked")]
+    DelegationRevoked,
+    /// Price feed's price is older than the escrow's max_age
+    #[error("Stale Price")]
+    StalePrice,
+    /// Price feed's confidence interval is wider than the escrow's max_confidence_bps
+    #[error("Price Uncertain")]
+    PriceUncertain,
+    /// Price feed is malformed, its price not positive or the quoted amount out of range
+    #[error("Invalid Price")]
+    InvalidPrice,
+    /// Oracle quoted less token1 than the maker's minimum
+    #[error("Price Below Minimum")]
+    PriceBelowMinimum,
+    /// Admin paused creating and taking escrows
+    #[error("Program Paused")]
+    ProgramPaused,
+    /// Mint is not on the mint policy's allowlist or is on its blocklist
+    #[error("Mint Not Allowed")]
+    MintNotAllowed,
+    /// Escrow requires the taker to hold an attestation by its issuer and none was passed
+    #[error("Attestation Required")]
+    AttestationRequired,
+    /// Taker's attestation expired
+    #[error("Attestation Expired")]
+    AttestationExpired,
+    /// Config can only be created by the program's upgrade authority
+    #[error("Not Upgrade Authority")]
+    NotUpgradeAuthority,
 }
 
 impl From<EscrowError> for ProgramError {
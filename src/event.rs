@@ -0,0 +1,32 @@
+//! Events the program logs for indexers, each one `msg!` line starting with the event's name
+
+use solana_program::{msg, pubkey::Pubkey};
+
+/// A settled `Exchange`, amount1 is what the maker received on the token1 leg and
+/// referral_fee what the taker paid the referrer on top of it
+pub struct TradeEvent {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub amount0: u64,
+    pub amount1: u64,
+    pub referrer_token1: Option<Pubkey>,
+    pub referral_fee: u64,
+}
+
+impl TradeEvent {
+    pub fn emit(&self) {
+        msg!(
+            "TradeEvent escrow={} maker={} taker={} amount0={} amount1={} referrer_token1={} referral_fee={}",
+            self.escrow,
+            self.maker,
+            self.taker,
+            self.amount0,
+            self.amount1,
+            self.referrer_token1
+                .map(|referrer_token1| referrer_token1.to_string())
+                .unwrap_or_else(|| "none".to_string()),
+            self.referral_fee
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Machine-readable description of the Borsh layouts of the escrow account and the
+//! instructions, generated from their schemas so other tools can decode them without copying
+//! offsets. idl/solana_escrow.json is its output, tests/idl.rs keeps it up to date.
+
+use crate::{
+    instruction::{EscrowInstruction, BORSH_INSTRUCTION_PREFIX},
+    state::{AccountKind, Escrow},
+};
+use borsh::schema::{BorshSchema, Definition, Fields};
+use std::collections::{BTreeMap, HashMap};
+
+/// Generates the IDL as pretty-printed JSON
+pub fn idl_json() -> String {
+    let mut definitions = HashMap::new();
+    EscrowInstruction::add_definitions_recursively(&mut definitions);
+    Escrow::add_definitions_recursively(&mut definitions);
+    // sorted, so the output only changes with the types
+    let definitions = definitions.into_iter().collect::<BTreeMap<_, _>>();
+
+    let mut json = String::from("{\n");
+    json.push_str(&format!("  \"name\": {},\n", quote(env!("CARGO_PKG_NAME"))));
+    json.push_str(&format!(
+        "  \"version\": {},\n",
+        quote(env!("CARGO_PKG_VERSION"))
+    ));
+    json.push_str(&format!(
+        "  \"instruction\": {{ \"prefix\": {}, \"type\": {} }},\n",
+        BORSH_INSTRUCTION_PREFIX,
+        quote(&EscrowInstruction::declaration())
+    ));
+    json.push_str(&format!(
+        "  \"accounts\": [\n    {{ \"name\": \"Escrow\", \"discriminator\": {}, \"type\": {} }}\n  ],\n",
+        AccountKind::BorshEscrow as u8,
+        quote(&Escrow::declaration())
+    ));
+    json.push_str("  \"types\": {\n");
+    let types = definitions
+        .iter()
+        .map(|(declaration, definition)| {
+            format!(
+                "    {}: {}",
+                quote(declaration),
+                definition_json(definition)
+            )
+        })
+        .collect::<Vec<_>>();
+    json.push_str(&types.join(",\n"));
+    json.push_str("\n  }\n}\n");
+    json
+}
+
+fn definition_json(definition: &Definition) -> String {
+    match definition {
+        Definition::Array { length, elements } => format!(
+            "{{ \"kind\": \"array\", \"length\": {}, \"elements\": {} }}",
+            length,
+            quote(elements)
+        ),
+        Definition::Sequence { elements } => format!(
+            "{{ \"kind\": \"sequence\", \"elements\": {} }}",
+            quote(elements)
+        ),
+        Definition::Tuple { elements } => format!(
+            "{{ \"kind\": \"tuple\", \"elements\": [{}] }}",
+            elements
+                .iter()
+                .map(|element| quote(element))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        // variants are serialized as their index in a u8, followed by their type
+        Definition::Enum { variants } => format!(
+            "{{ \"kind\": \"enum\", \"variants\": [\n{}\n    ] }}",
+            variants
+                .iter()
+                .enumerate()
+                .map(|(index, (name, declaration))| format!(
+                    "      {{ \"index\": {}, \"name\": {}, \"type\": {} }}",
+                    index,
+                    quote(name),
+                    quote(declaration)
+                ))
+                .collect::<Vec<_>>()
+                .join(",\n")
+        ),
+        Definition::Struct { fields } => {
+            let fields = match fields {
+                Fields::NamedFields(fields) => fields
+                    .iter()
+                    .map(|(name, declaration)| {
+                        format!(
+                            "      {{ \"name\": {}, \"type\": {} }}",
+                            quote(name),
+                            quote(declaration)
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+                Fields::UnnamedFields(fields) => fields
+                    .iter()
+                    .map(|declaration| format!("      {{ \"type\": {} }}", quote(declaration)))
+                    .collect::<Vec<_>>(),
+                Fields::Empty => Vec::new(),
+            };
+            if fields.is_empty() {
+                "{ \"kind\": \"struct\", \"fields\": [] }".to_string()
+            } else {
+                format!(
+                    "{{ \"kind\": \"struct\", \"fields\": [\n{}\n    ] }}",
+                    fields.join(",\n")
+                )
+            }
+        }
+    }
+}
+
+/// JSON string of a declaration or name, which are Rust identifiers and types
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
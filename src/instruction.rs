@@ -1,61 +1,790 @@
 use solana_program::{
+    bpf_loader_upgradeable,
     instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvar,
+    system_program, sysvar,
 };
 use std::convert::TryInto;
 use std::mem::size_of;
 
 use crate::error::EscrowError::*;
+use crate::state::{
+    Attestation, Config, HashKind, Market, MintPolicy, OptionKind, OracleQuote, PolicyMode,
+    BPS_DENOMINATOR, MAX_BASKET_VAULTS, MAX_EXTRA_ASKS, MAX_MILESTONES,
+};
+use crate::token;
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+
+/// First byte of Borsh serialized instructions, followed by the serialized EscrowInstruction.
+/// Instructions of the legacy layout start with their tag instead, which never gets this high
+pub const BORSH_INSTRUCTION_PREFIX: u8 = 0xff;
+
+pub use self::schema::EscrowInstruction;
+
+// the schema derive declares a struct per variant next to the enum whose fields are never read,
+// the enum is kept on its own so the allow doesn't cover anything else
+#[allow(dead_code)]
+mod schema {
+    use super::*;
 
-pub enum EscrowInstruction {
-    /// Starts the trade by creating and populating an escrow account and transferring ownership of the given temp token account to the PDA
-    ///
-    ///
-    /// Accounts expected:
-    ///
-    /// 0. `[signer] maker` The account of the person initializing the escrow (maker)
-    /// 1. `[writable] tmp_token0` Temporary token account that should be created prior to this instruction and owned by the initializer
-    /// 2. `[] maker_token1` The maker's token account for the token they will receive should the trade go through
-    /// 3. `[writable] escrow_info_account` The escrow account, it will hold all necessary info about the trade.
-    /// 4. `[] rent` The rent sysvar
-    /// 5. `[] token_program` The token program
-    InitEscrow {
-        /// the amount the maker expects to receive of token1
-        amount: u64,
-    },
-    /// Accepts a trade
-    ///
-    ///
-    /// Accounts expected:
-    ///
-    /// 0. `[signer] taker` The account of the person taking the trade (taker)
-    /// 1. `[writable] taker_token1` The taker's token account for the token they send
-    /// 2. `[writable] taker_token0` The taker's token account for the token they will receive should the trade go through
-    /// 3. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
-    /// 4. `[writable] maker` The maker's main account to send their rent fees to
-    /// 5. `[writable] maker_token1` The maker's token account that will receive tokens
-    /// 6. `[writable] escrow_info_account` The escrow account holding the escrow info
-    /// 7. `[] token_program` The token program
-    /// 8. `[] pda_account` The PDA account
-    Exchange {
-        /// the amount the taker expects to receive of token0 in exhange for the amount in of token0 in pda_tmp_token0
-        amount: u64,
-    },
+    /// Variants are serialized with their index, which is also the tag of InitEscrow and Exchange
+    /// in the legacy layout
+    #[derive(BorshSerialize, BorshDeserialize, BorshSchema)]
+    pub enum EscrowInstruction {
+        /// Starts the trade by creating and populating an escrow account and transferring ownership of the given temp token account to the PDA
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] maker` The account of the person initializing the escrow (maker), writable with a wrap_amount.
+        ///    Or the spl-token multisig owning the maker's token accounts, which can't wrap SOL
+        /// 1. `[writable] tmp_token0` Temporary token account that should be created prior to this instruction and owned by the initializer.
+        ///    With a delegated_amount the maker's regular token account instead, which the PDA is approved to take it from
+        /// 2. `[] maker_token1` The maker's token account for the token they will receive should the trade go through,
+        ///    writable with unwrap_token1. With pay_to_ata the mint of that token instead
+        /// 3. `[writable] escrow_info_account` The escrow account, it will hold all necessary info about the trade.
+        /// 4. `[] rent` The rent sysvar
+        /// 5. `[] token_program` The token program, classic or Token-2022, that owns all of the escrow's token accounts
+        /// 6. `[] config` The protocol config PDA, no escrow can be created while it is paused
+        /// 7. `[] mint_policy` The mint policy PDA every deposited and asked mint must be allowed by
+        /// 8. `[signer]` Only if maker is an spl-token multisig instead of a signing wallet, m of its signers
+        /// 9. `[writable]` basket_len further temporary token accounts deposited together with tmp_token0, owned by the initializer
+        /// 10. `[]` For each extra ask amount, the maker's token account that will receive it, its mint is the asked token
+        /// 11. `[] mint0`, `[] mint1` Only with Token-2022, the mints of tmp_token0 and maker_token1.
+        ///     Token-2022 escrows can't have basket vaults or extra asks, nor mints with transfer fees,
+        ///     permanent delegates, transfer hooks or non-transferable tokens
+        /// 12. `[] system_program` Only with a wrap_amount
+        /// 13. `[writable] market` Only with list_in_market, the market of tmp_token0's and maker_token1's mints
+        /// 14. `[writable] funding` Only with a refill_amount, the maker's token account of token0 the PDA is
+        ///     approved to refill tmp_token0 from
+        InitEscrow {
+            /// the amount the maker expects to receive of token1, the least they accept with an oracle_quote
+            amount: u64,
+            /// number of additional deposit vaults, at most MAX_BASKET_VAULTS
+            basket_len: u8,
+            /// amounts the maker expects to receive on top of token1, at most MAX_EXTRA_ASKS
+            extra_ask_amounts: Vec<u64>,
+            /// whether to list the escrow in its market, only plain escrows can be listed
+            list_in_market: bool,
+            /// unix timestamp from which on the escrow can no longer be taken, packed as 0 if none
+            expires_at: Option<i64>,
+            /// lamports moved from the maker into tmp_token0, a classic native mint account, before
+            /// it is handed over, so the maker can offer SOL
+            wrap_amount: u64,
+            /// whether maker_token1 is a classic native mint account of the maker that is handed over
+            /// to the PDA and closed to the maker on exchange, so the maker receives SOL.
+            /// Such escrows are not plain
+            unwrap_token1: bool,
+            /// whether the escrow pays the maker's associated token account of the mint passed in place
+            /// of maker_token1, which need not exist before the exchange. Can't be combined with unwrap_token1
+            pay_to_ata: bool,
+            /// amount of tmp_token0 the PDA is approved for instead of taking over tmp_token0, which
+            /// stays the maker's until the exchange pulls it. Packed as 0 if none. Such escrows are not
+            /// plain and can't have basket vaults or wrap SOL
+            delegated_amount: Option<u64>,
+            /// price feed and spread the ask for token1 is quoted with on exchange instead of amount,
+            /// packed as zeros if none, omitted from the data if there is no refill_amount either.
+            /// Such escrows are not plain
+            oracle_quote: Option<OracleQuote>,
+            /// the most the escrow pulls from the funding account, one tranche of tmp_token0's initial
+            /// balance after each exchange, to stay open as a standing order. Packed as 0 if none,
+            /// omitted from the data if there is no required_issuer either. Such escrows are not plain
+            /// and can't have basket vaults, a delegation or unwrap token1
+            refill_amount: Option<u64>,
+            /// issuer the taker needs a valid attestation of to take the escrow, omitted from the data
+            /// if none. Such escrows are not plain
+            required_issuer: Option<Pubkey>,
+        },
+        /// Accepts a trade
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] taker` The account of the person taking the trade (taker), writable with wrap_token1 or unwrap_token0.
+        ///    Or the spl-token multisig owning the taker's token accounts, which can't wrap or unwrap SOL
+        /// 1. `[writable] taker_token1` The taker's token account for the token they send
+        /// 2. `[writable] taker_token0` The taker's token account for the token they will receive should the trade go through
+        /// 3. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close,
+        ///    for a non-custodial escrow the maker's token account the PDA takes the delegated amount from
+        /// 4. `[writable] maker` The maker's main account to send their rent fees to
+        /// 5. `[writable] maker_token1` The maker's token account that will receive tokens, closed to the maker if the escrow unwraps token1
+        /// 6. `[writable] escrow_info_account` The escrow account holding the escrow info
+        /// 7. `[] token_program` The token program
+        /// 8. `[] pda_account` The PDA account
+        /// 9. `[] config` The protocol config PDA, no escrow can be taken while it is paused
+        /// 10. `[signer]` Only if taker is an spl-token multisig instead of a signing wallet, m of its signers
+        /// 11. `[] attestation` Only if the escrow requires one, the taker's attestation PDA by the
+        ///     escrow's required issuer
+        /// 12. For each basket vault stored in the escrow info, in order:
+        ///    * `[writable]` The PDA's basket vault to get tokens from and eventually close
+        ///    * `[writable]` The taker's token account of the basket vault's mint
+        /// 13. For each extra ask stored in the escrow info, in order:
+        ///    * `[writable]` The taker's token account of the ask's mint to pay from
+        ///    * `[writable]` The maker's token account receiving the ask
+        /// 14. `[] mint0`, `[] mint1` Only with Token-2022, the mints of pda_tmp_token0 and maker_token1
+        /// 15. `[writable] market` Only if the escrow was listed, the market stored in the escrow info
+        /// 16. `[writable] referrer_token1` Only with a referral fee, the referrer's token account of token1
+        /// 17. `[] system_program` Only with wrap_token1
+        /// 18. `[] price_feed` Only for an oracle-priced escrow, the price feed stored in the escrow info
+        /// 19. `[writable] funding` Only for a standing order, the funding account stored in the escrow info
+        Exchange {
+            /// the amount the taker expects to receive of token0 in exhange for the amount in of token0 in pda_tmp_token0
+            amount: u64,
+            /// share of the token1 leg in basis points the taker pays the referrer on top of it,
+            /// at most the config's max_referral_fee_bps, omitted from the data if none
+            referral_fee_bps: Option<u16>,
+            /// whether the token1 the taker pays is first moved as lamports from the taker into
+            /// taker_token1, a classic native mint account, so the taker can pay SOL
+            wrap_token1: bool,
+            /// whether taker_token0, a classic native mint account of the taker, is closed to the taker
+            /// after receiving token0, so the taker receives SOL
+            unwrap_token0: bool,
+            /// the most token1 the taker pays, referral fee included, protecting them from an
+            /// oracle-priced escrow's price moving, omitted from the data if none
+            max_token1_amount: Option<u64>,
+        },
+        /// Locks tokens behind a hashlock and a timeout (HTLC) and transfers ownership of the given temp token account to the PDA
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] maker` The account of the person locking the tokens (maker)
+        /// 1. `[writable] tmp_token0` Temporary token account that should be created prior to this instruction and owned by the maker
+        /// 2. `[] recipient_token0` The token account that receives the tokens once the preimage is revealed
+        /// 3. `[writable] htlc_info_account` The HTLC account, it will hold all necessary info about the lock.
+        /// 4. `[] rent` The rent sysvar
+        /// 5. `[] token_program` The token program
+        InitHtlc {
+            /// the hash function the preimage is checked with
+            hash_kind: HashKind,
+            /// hash of the secret preimage
+            hashlock: [u8; 32],
+            /// unix timestamp until which the preimage is accepted
+            timeout: i64,
+        },
+        /// Releases the locked tokens to the recipient, can be called by anyone knowing the preimage
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 1. `[writable] recipient_token0` The recipient's token account stored in the HTLC info
+        /// 2. `[writable] maker` The maker's main account to send their rent fees to
+        /// 3. `[writable] htlc_info_account` The HTLC account holding the lock info
+        /// 4. `[] clock` The clock sysvar
+        /// 5. `[] token_program` The token program
+        /// 6. `[] pda_account` The PDA account
+        ClaimHtlc {
+            /// the secret that hashes to the hashlock
+            preimage: Vec<u8>,
+        },
+        /// Returns the locked tokens to the maker once the timeout passed
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] maker` The maker, also receives the rent fees
+        /// 1. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 2. `[writable] maker_token0` The maker's token account receiving the tokens back
+        /// 3. `[writable] htlc_info_account` The HTLC account holding the lock info
+        /// 4. `[] clock` The clock sysvar
+        /// 5. `[] token_program` The token program
+        /// 6. `[] pda_account` The PDA account
+        RefundHtlc,
+        /// Starts a three-party escrow by creating and populating an arbiter escrow account and transferring ownership of the given temp token account to the PDA
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] payer` The account of the person paying for the service (payer)
+        /// 1. `[writable] tmp_token0` Temporary token account that should be created prior to this instruction and owned by the payer
+        /// 2. `[] payer_token0` The payer's token account receiving refunds
+        /// 3. `[] payee_token0` The payee's token account receiving released funds, its owner is the payee
+        /// 4. `[] arbiter` The account that can resolve disputes
+        /// 5. `[writable] escrow_info_account` The arbiter escrow account, it will hold all necessary info about the escrow.
+        /// 6. `[] rent` The rent sysvar
+        /// 7. `[] token_program` The token program
+        InitArbiterEscrow,
+        /// Releases all funds to the payee, only allowed while not disputed
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] payer` The payer, also receives the rent fees
+        /// 1. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 2. `[writable] payee_token0` The payee's token account stored in the escrow info
+        /// 3. `[writable] escrow_info_account` The arbiter escrow account holding the escrow info
+        /// 4. `[] token_program` The token program
+        /// 5. `[] pda_account` The PDA account
+        ReleaseToPayee,
+        /// Refunds all funds to the payer, only allowed while not disputed
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] payee` The payee
+        /// 1. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 2. `[writable] payer_token0` The payer's token account stored in the escrow info
+        /// 3. `[writable] payer` The payer's main account to send their rent fees to
+        /// 4. `[writable] escrow_info_account` The arbiter escrow account holding the escrow info
+        /// 5. `[] token_program` The token program
+        /// 6. `[] pda_account` The PDA account
+        RefundToPayer,
+        /// Marks the escrow as disputed, from then on only the arbiter can settle it
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] party` Either the payer or the payee
+        /// 1. `[writable] escrow_info_account` The arbiter escrow account holding the escrow info
+        RaiseDispute,
+        /// Splits the funds of a disputed escrow between payee and payer
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] arbiter` The arbiter stored in the escrow info
+        /// 1. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 2. `[writable] payee_token0` The payee's token account stored in the escrow info
+        /// 3. `[writable] payer_token0` The payer's token account stored in the escrow info
+        /// 4. `[writable] payer` The payer's main account to send their rent fees to
+        /// 5. `[writable] escrow_info_account` The arbiter escrow account holding the escrow info
+        /// 6. `[] token_program` The token program
+        /// 7. `[] pda_account` The PDA account
+        ResolveDispute {
+            /// the amount going to the payee, the rest of the vault goes back to the payer
+            payee_amount: u64,
+        },
+        /// Starts a milestone escrow by creating and populating a milestone escrow account and transferring ownership of the given temp token account to the PDA
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] payer` The account of the person paying for the milestones (payer)
+        /// 1. `[writable] tmp_token0` Temporary token account holding exactly the sum of all milestones, owned by the payer
+        /// 2. `[] payer_token0` The payer's token account receiving the remainder on cancel
+        /// 3. `[] payee_token0` The payee's token account receiving the tranches
+        /// 4. `[writable] escrow_info_account` The milestone escrow account, it will hold all necessary info about the escrow.
+        /// 5. `[] rent` The rent sysvar
+        /// 6. `[] token_program` The token program
+        InitMilestoneEscrow {
+            /// the tranche of each milestone, at most MAX_MILESTONES
+            amounts: Vec<u64>,
+        },
+        /// Approves a milestone and releases its tranche to the payee, the last one releases whatever is left in the vault
+        /// and closes the escrow
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] payer` The payer, also receives the rent fees
+        /// 1. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 2. `[writable] payee_token0` The payee's token account stored in the escrow info
+        /// 3. `[writable] escrow_info_account` The milestone escrow account holding the escrow info
+        /// 4. `[] token_program` The token program
+        /// 5. `[] pda_account` The PDA account
+        ApproveMilestone {
+            /// index of the milestone to approve
+            index: u8,
+        },
+        /// Cancels a milestone escrow, the unapproved remainder goes back to the payer
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] payer` The payer, also receives the rent fees
+        /// 1. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 2. `[writable] payer_token0` The payer's token account stored in the escrow info
+        /// 3. `[writable] escrow_info_account` The milestone escrow account holding the escrow info
+        /// 4. `[] token_program` The token program
+        /// 5. `[] pda_account` The PDA account
+        CancelMilestoneEscrow,
+        /// Locks the given temp token account for a beneficiary that vests linearly from start to end, nothing vests before the cliff
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] funder` The account of the person funding the vesting (funder)
+        /// 1. `[writable] tmp_token0` Temporary token account holding the tokens to vest, owned by the funder
+        /// 2. `[] funder_token0` The funder's token account receiving the unvested remainder on revoke
+        /// 3. `[] beneficiary_token0` The beneficiary's token account receiving the vested tokens
+        /// 4. `[writable] vesting_info_account` The vesting account, it will hold all necessary info about the schedule.
+        /// 5. `[] rent` The rent sysvar
+        /// 6. `[] token_program` The token program
+        InitVesting {
+            /// unix timestamp the linear vesting starts from
+            start_ts: i64,
+            /// unix timestamp before which nothing can be withdrawn
+            cliff_ts: i64,
+            /// unix timestamp from which everything is vested
+            end_ts: i64,
+            /// whether the funder can revoke the unvested portion
+            is_revocable: bool,
+        },
+        /// Transfers everything vested so far but not yet withdrawn to the beneficiary, the last withdrawal everything left in the
+        /// vault, and closes the vesting once fully withdrawn.
+        /// Anyone can call this as the tokens only ever go to the beneficiary's account.
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 1. `[writable] beneficiary_token0` The beneficiary's token account stored in the vesting info
+        /// 2. `[writable] funder` The funder's main account to send their rent fees to
+        /// 3. `[writable] vesting_info_account` The vesting account holding the schedule
+        /// 4. `[] clock` The clock sysvar
+        /// 5. `[] token_program` The token program
+        /// 6. `[] pda_account` The PDA account
+        WithdrawVested,
+        /// Pays out what is vested to the beneficiary and returns the rest of the vault to the funder
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] funder` The funder, also receives the rent fees
+        /// 1. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 2. `[writable] beneficiary_token0` The beneficiary's token account stored in the vesting info
+        /// 3. `[writable] funder_token0` The funder's token account stored in the vesting info
+        /// 4. `[writable] vesting_info_account` The vesting account holding the schedule
+        /// 5. `[] clock` The clock sysvar
+        /// 6. `[] token_program` The token program
+        /// 7. `[] pda_account` The PDA account
+        RevokeVesting,
+        /// Accepts several plain escrows at once, settling either all of them or none
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] taker` The account of the person taking the trades (taker)
+        /// 1. `[writable] taker_token1` The taker's token account for the token they send
+        /// 2. `[writable] taker_token0` The taker's token account for the token they will receive
+        /// 3. `[] token_program` The token program
+        /// 4. `[] pda_account` The PDA account
+        /// 5. `[] config` The protocol config PDA, no escrow can be taken while it is paused
+        /// 6. For each escrow, in the order of the expected amounts:
+        ///    * `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        ///    * `[writable] maker` The maker's main account to send their rent fees to
+        ///    * `[writable] maker_token1` The maker's token account that will receive tokens
+        ///    * `[writable] escrow_info_account` The escrow account holding the escrow info
+        ///    * `[writable] market` Only if the escrow was listed, the market stored in the escrow info
+        BatchExchange {
+            /// the amount of token0 the taker expects to receive from each escrow
+            amounts: Vec<u64>,
+            /// the most token1 the taker is willing to pay for all escrows together
+            max_total_amount: u64,
+        },
+        /// Settles two crossed plain escrows against each other, A offering what B asks for and vice versa.
+        /// Can be called by anyone, each maker receives at least what they asked for.
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[writable] escrow_a_info_account` The escrow account of the first escrow
+        /// 1. `[writable] pda_tmp_token_a` The first escrow's vault
+        /// 2. `[writable] maker_a` The first maker's main account to send their rent fees to
+        /// 3. `[writable] maker_a_token1` The first maker's token account receiving from the second vault
+        /// 4. `[writable] escrow_b_info_account` The escrow account of the second escrow
+        /// 5. `[writable] pda_tmp_token_b` The second escrow's vault
+        /// 6. `[writable] maker_b` The second maker's main account to send their rent fees to
+        /// 7. `[writable] maker_b_token1` The second maker's token account receiving from the first vault
+        /// 8. `[] token_program` The token program
+        /// 9. `[] pda_account` The PDA account
+        /// 10. `[] config` The protocol config PDA, no escrows can be matched while it is paused
+        /// 11. `[writable] cranker_token_a` Only with take_surplus, the caller's account of the first vault's mint
+        /// 12. `[writable] cranker_token_b` Only with take_surplus, the caller's account of the second vault's mint
+        /// 13. `[writable] market_a` Only if the first escrow was listed, the market stored in its escrow info
+        /// 14. `[writable] market_b` Only if the second escrow was listed, the market stored in its escrow info
+        Match {
+            /// whether the surplus of both vaults goes to the caller instead of the makers
+            take_surplus: bool,
+        },
+        /// Creates the market of escrows offering token0 for token1 at its PDA, can be called by anyone
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] payer` The account paying the market's rent
+        /// 1. `[writable] market` The market PDA of [b"market", mint0, mint1]
+        /// 2. `[] mint0` The mint of the offered token
+        /// 3. `[] mint1` The mint of the asked token
+        /// 4. `[] rent` The rent sysvar
+        /// 5. `[] system_program` The system program
+        InitMarket,
+        /// Returns all deposited tokens to the maker and closes the escrow.
+        /// A non-custodial escrow's delegation is reduced by its amount instead
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] maker` The maker, also receives the rent fees, or its spl-token multisig
+        /// 1. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close,
+        ///    for a non-custodial escrow the maker's token account
+        /// 2. `[writable] maker_token0` The maker's token account receiving the tokens back
+        /// 3. `[writable] escrow_info_account` The escrow account holding the escrow info
+        /// 4. `[] token_program` The token program
+        /// 5. `[] pda_account` The PDA account
+        /// 6. `[signer]` Only if maker is an spl-token multisig instead of a signing wallet, m of its signers
+        /// 7. `[] mint0` Only with Token-2022, the mint of pda_tmp_token0
+        /// 8. For each basket vault stored in the escrow info, in order:
+        ///    * `[writable]` The PDA's basket vault to get tokens from and eventually close
+        ///    * `[writable]` The maker's token account of the basket vault's mint
+        /// 9. `[writable] maker_token1` Only if the escrow unwraps token1, the PDA owned maker_token1 to close to the maker
+        /// 10. `[writable] market` Only if the escrow was listed, the market stored in the escrow info
+        /// 11. `[writable] funding` Only for a standing order, the funding account whose approval is reduced
+        ///     by the remaining amount
+        CancelEscrow,
+        /// Returns all deposited tokens of an expired escrow to the maker, can be called by anyone.
+        /// A non-custodial escrow's or standing order's delegation can't be changed without the maker
+        /// and is left as is: nothing is pulled through it once the escrow is closed, but the maker
+        /// has to revoke it with the token program's Revoke to take it back
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[writable] maker` The maker's main account to send their rent fees to
+        /// 1. `[writable] pda_tmp_token0` The PDA's temporary token account to get tokens from and eventually close
+        /// 2. `[writable] maker_token0` The maker's token account receiving the tokens back, owned by the maker
+        /// 3. `[writable] escrow_info_account` The escrow account holding the escrow info
+        /// 4. `[] token_program` The token program
+        /// 5. `[] pda_account` The PDA account
+        /// 6. `[] clock` The clock sysvar
+        /// 7. `[] mint0` Only with Token-2022, the mint of pda_tmp_token0
+        /// 8. For each basket vault stored in the escrow info, in order:
+        ///    * `[writable]` The PDA's basket vault to get tokens from and eventually close
+        ///    * `[writable]` The maker's token account of the basket vault's mint, owned by the maker
+        /// 9. `[writable] maker_token1` Only if the escrow unwraps token1, the PDA owned maker_token1 to close to the maker
+        /// 10. `[writable] market` Only if the escrow was listed, the market stored in the escrow info
+        ExpireEscrow,
+        /// Creates the protocol config at its PDA, the program's upgrade authority becomes its admin
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] admin` The program's upgrade authority, paying the config's rent and
+        ///    allowed to change it
+        /// 1. `[writable] config` The config PDA of [b"config"]
+        /// 2. `[] rent` The rent sysvar
+        /// 3. `[] system_program` The system program
+        /// 4. `[] program_data` The escrow program's ProgramData account of the upgradeable loader
+        InitConfig {
+            /// highest referral fee in basis points an exchange may pay
+            max_referral_fee_bps: u16,
+        },
+        /// Changes the highest referral fee an exchange may pay
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] admin` The config's admin
+        /// 1. `[writable] config` The config PDA
+        SetReferralFeeCap {
+            /// highest referral fee in basis points an exchange may pay
+            max_referral_fee_bps: u16,
+        },
+        /// Creates the associated token account of a wallet if it does not exist yet, so instruction
+        /// builders can make sure the recipients of an exchange exist. Can be called by anyone
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] payer` The account paying the rent of the new account
+        /// 1. `[writable] associated_account` The associated token account of wallet and mint
+        /// 2. `[] wallet` The owner of the associated token account
+        /// 3. `[] mint` The mint of the associated token account
+        /// 4. `[] system_program` The system program
+        /// 5. `[] token_program` The token program of the mint
+        /// 6. `[] associated_token_program` The associated token account program
+        /// 7. `[] rent` The rent sysvar
+        CreateRecipientAccount,
+        /// Offers a plain escrow's maker a different amount of token1, the whole balance of the given
+        /// temp token account, which is handed over to the PDA until the offer is settled
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] taker` The account of the person making the counter-offer (taker)
+        /// 1. `[writable] tmp_token1` Temporary token account holding the offered token1, owned by the taker
+        /// 2. `[] taker_token1` The taker's token account getting token1 back if the offer is rejected or withdrawn
+        /// 3. `[] taker_token0` The taker's token account receiving token0 if the offer is accepted
+        /// 4. `[] escrow_info_account` The escrow account of the plain escrow
+        /// 5. `[writable] counter_info_account` The counter-offer account, it will hold all necessary info about the offer.
+        /// 6. `[] rent` The rent sysvar
+        /// 7. `[] token_program` The token program
+        /// 8. `[] config` The protocol config PDA, no counter-offer can be made while it is paused
+        ProposeCounter {
+            /// the amount of token0 the taker expects from the escrow's vault
+            amount0: u64,
+        },
+        /// Settles the escrow at the counter-offer's amount, token0 goes to the taker and the
+        /// counter-offer's token1 to the maker
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] maker` The maker, also receives the escrow's rent fees
+        /// 1. `[writable] pda_tmp_token0` The escrow's vault to get tokens from and eventually close
+        /// 2. `[writable] maker_token1` The maker's token account stored in the escrow info
+        /// 3. `[writable] escrow_info_account` The escrow account holding the escrow info
+        /// 4. `[writable] counter_info_account` The counter-offer account holding the offer info
+        /// 5. `[writable] pda_tmp_token1` The counter-offer's vault to get tokens from and eventually close
+        /// 6. `[writable] taker_token0` The taker's token account stored in the counter-offer info
+        /// 7. `[writable] taker` The taker's main account to send the counter-offer's rent fees to
+        /// 8. `[] token_program` The token program
+        /// 9. `[] pda_account` The PDA account
+        /// 10. `[] config` The protocol config PDA, no counter-offer can be accepted while it is paused
+        /// 11. `[writable] market` Only if the escrow was listed, the market stored in the escrow info
+        AcceptCounter {
+            /// the amount of token1 the maker expects to receive from the counter-offer's vault
+            amount1: u64,
+        },
+        /// Returns a counter-offer's token1 to the taker on behalf of the escrow's maker
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] maker` The maker of the escrow the offer was made for
+        /// 1. `[] escrow_info_account` The escrow account holding the escrow info
+        /// 2. `[writable] taker` The taker's main account to send the counter-offer's rent fees to
+        /// 3. `[writable] counter_info_account` The counter-offer account holding the offer info
+        /// 4. `[writable] pda_tmp_token1` The counter-offer's vault to get tokens from and eventually close
+        /// 5. `[writable] taker_token1` The taker's token account stored in the counter-offer info
+        /// 6. `[] token_program` The token program
+        /// 7. `[] pda_account` The PDA account
+        RejectCounter,
+        /// Returns a counter-offer's token1 to the taker, who can withdraw it at any time until it is accepted
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] taker` The taker, also receives the rent fees
+        /// 1. `[writable] counter_info_account` The counter-offer account holding the offer info
+        /// 2. `[writable] pda_tmp_token1` The counter-offer's vault to get tokens from and eventually close
+        /// 3. `[writable] taker_token1` The taker's token account stored in the counter-offer info
+        /// 4. `[] token_program` The token program
+        /// 5. `[] pda_account` The PDA account
+        WithdrawCounter,
+        /// Writes a covered option by handing the collateral's temp token account over to the PDA,
+        /// which mints the option's single token to the writer and gives up minting more.
+        /// No premium is paid to the program: the writer sells the option token outside of it like
+        /// any other token, e.g. through an escrow of it asking for the premium.
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] writer` The account of the person writing the option (writer)
+        /// 1. `[writable] tmp_collateral` Temporary token account holding the underlying of a call or the
+        ///    strike of a put, owned by the writer
+        /// 2. `[] writer_payment` The writer's token account that will receive the exercise amount
+        /// 3. `[writable] option_mint` Mint of the option token with the PDA as mint authority, no freeze
+        ///    authority, no decimals and no supply yet
+        /// 4. `[writable] writer_option_token` The writer's token account of option_mint receiving the option token
+        /// 5. `[writable] option_info_account` The option account, it will hold all necessary info about the option.
+        /// 6. `[] rent` The rent sysvar
+        /// 7. `[] token_program` The token program
+        /// 8. `[] pda_account` The PDA account
+        /// 9. `[] config` The protocol config PDA, no option can be written while it is paused
+        WriteOption {
+            kind: OptionKind,
+            /// the amount the holder pays to exercise, the strike of a call or the underlying of a put
+            exercise_amount: u64,
+            /// unix timestamp from which on the option can no longer be exercised
+            expires_at: i64,
+        },
+        /// Exercises a covered option before it expires, burning the holder's option token and paying
+        /// the exercise amount to the writer for the collateral
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] holder` The owner of the option token (holder)
+        /// 1. `[writable] holder_option_token` The holder's token account of the option mint
+        /// 2. `[writable] holder_payment` The holder's token account paying the exercise amount
+        /// 3. `[writable] holder_collateral` The holder's token account that will receive the collateral
+        /// 4. `[writable] option_mint` The option mint stored in the option info
+        /// 5. `[writable] pda_collateral_vault` The PDA's collateral vault to get tokens from and eventually close
+        /// 6. `[writable] writer_payment` The writer's token account stored in the option info
+        /// 7. `[writable] writer` The writer's main account to send their rent fees to
+        /// 8. `[writable] option_info_account` The option account holding the option info
+        /// 9. `[] token_program` The token program
+        /// 10. `[] pda_account` The PDA account
+        /// 11. `[] config` The protocol config PDA, no option can be exercised while it is paused
+        ExerciseOption,
+        /// Returns an expired option's collateral to its writer, the option token stays worthless
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] writer` The writer, also receives the rent fees
+        /// 1. `[writable] pda_collateral_vault` The PDA's collateral vault to get tokens from and eventually close
+        /// 2. `[writable] writer_collateral` The writer's token account to return the collateral to
+        /// 3. `[writable] option_info_account` The option account holding the option info
+        /// 4. `[] token_program` The token program
+        /// 5. `[] pda_account` The PDA account
+        ReclaimOptionCollateral,
+        /// Pauses or resumes opening and filling trades: creating, taking and matching escrows,
+        /// counter-offers and writing and exercising options. Cancelling, expiring, withdrawing and
+        /// reclaiming keeps working
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] admin` The config's admin
+        /// 1. `[writable] config` The config PDA
+        SetPaused {
+            /// whether no trade can be opened or filled
+            is_paused: bool,
+        },
+        /// Creates the mint policy PDA without any mints listed, escrows can't be created with any
+        /// mint as an allowlist until some are added
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] admin` The config's admin, paying the policy's rent
+        /// 1. `[] config` The config PDA
+        /// 2. `[writable] mint_policy` The mint policy PDA of [b"mint_policy"]
+        /// 3. `[] rent` The rent sysvar
+        /// 4. `[] system_program` The system program
+        InitMintPolicy {
+            /// whether the listed mints are the only ones allowed or the ones blocked
+            mode: PolicyMode,
+        },
+        /// Lists a mint in the mint policy
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] admin` The config's admin
+        /// 1. `[] config` The config PDA
+        /// 2. `[writable] mint_policy` The mint policy PDA
+        /// 3. `[] mint` The mint to list
+        AddPolicyMint,
+        /// Removes a mint from the mint policy, escrows already created with it are left alone
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] admin` The config's admin
+        /// 1. `[] config` The config PDA
+        /// 2. `[writable] mint_policy` The mint policy PDA
+        /// 3. `[] mint` The listed mint, which need not be a mint anymore
+        RemovePolicyMint,
+        /// Attests the subject until expires_at by creating the attestation PDA, or renews an
+        /// attestation the issuer gave the subject before
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] issuer` The issuer, paying the attestation's rent
+        /// 1. `[] subject` The attested account, the taker of escrows requiring the issuer's attestation
+        /// 2. `[writable] attestation` The attestation PDA of [b"attestation", issuer, subject]
+        /// 3. `[] rent` The rent sysvar
+        /// 4. `[] system_program` The system program
+        IssueAttestation {
+            /// unix timestamp from which on the attestation is no longer valid
+            expires_at: i64,
+        },
+        /// Revokes an attestation by closing it
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer, writable] issuer` The attestation's issuer, receives the rent fees
+        /// 1. `[writable] attestation` The attestation PDA
+        RevokeAttestation,
+        /// Hands the config's admin role to another account, which has to sign as well
+        ///
+        ///
+        /// Accounts expected:
+        ///
+        /// 0. `[signer] admin` The config's admin
+        /// 1. `[writable] config` The config PDA
+        /// 2. `[signer] new_admin` The account becoming the config's admin
+        SetAdmin,
+    }
 }
 
 impl EscrowInstruction {
-    /// Unpacks a byte buffer into a [EscrowInstruction](enum.EscrowInstruction.html).
+    /// Unpacks a byte buffer of either layout into a [EscrowInstruction](enum.EscrowInstruction.html).
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        match input.split_first() {
+            Some((&BORSH_INSTRUCTION_PREFIX, rest)) => Self::try_from_slice(rest)
+                .map_err(|_| InvalidInstructionData)?
+                .check(),
+            _ => Self::unpack_legacy(input),
+        }
+    }
+
+    /// Checks the limits Borsh can't express, zero amounts and timestamps have to be given as
+    /// None instead
+    fn check(self) -> Result<Self, ProgramError> {
+        let is_valid = match &self {
+            Self::InitEscrow {
+                basket_len,
+                extra_ask_amounts,
+                expires_at,
+                delegated_amount,
+                oracle_quote,
+                refill_amount,
+                ..
+            } => {
+                *basket_len as usize <= MAX_BASKET_VAULTS
+                    && extra_ask_amounts.len() <= MAX_EXTRA_ASKS
+                    && *expires_at != Some(0)
+                    && *delegated_amount != Some(0)
+                    && *refill_amount != Some(0)
+                    && oracle_quote.map_or(true, |oracle_quote| {
+                        oracle_quote.spread_bps as i64 > -(BPS_DENOMINATOR as i64)
+                    })
+            }
+            Self::Exchange {
+                referral_fee_bps, ..
+            } => referral_fee_bps.map_or(true, |fee_bps| fee_bps as u64 <= BPS_DENOMINATOR),
+            Self::InitMilestoneEscrow { amounts } => {
+                !amounts.is_empty() && amounts.len() <= MAX_MILESTONES
+            }
+            Self::BatchExchange { amounts, .. } => amounts.len() <= u8::MAX as usize,
+            Self::InitConfig {
+                max_referral_fee_bps,
+            }
+            | Self::SetReferralFeeCap {
+                max_referral_fee_bps,
+            } => *max_referral_fee_bps as u64 <= BPS_DENOMINATOR,
+            _ => true,
+        };
+        if !is_valid {
+            return Err(InvalidInstructionData.into());
+        }
+        Ok(self)
+    }
+
+    /// Unpacks a byte buffer of the legacy layout, which only has the original InitEscrow and
+    /// Exchange: their tag followed by the amount
+    pub fn unpack_legacy(input: &[u8]) -> Result<Self, ProgramError> {
         let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
 
         Ok(match tag {
             0 => Self::InitEscrow {
                 amount: Self::unpack_init_escrow(rest)?,
+                basket_len: 0,
+                extra_ask_amounts: Vec::new(),
+                list_in_market: false,
+                expires_at: None,
+                wrap_amount: 0,
+                unwrap_token1: false,
+                pay_to_ata: false,
+                delegated_amount: None,
+                oracle_quote: None,
+                refill_amount: None,
+                required_issuer: None,
             },
             1 => Self::Exchange {
                 amount: Self::unpack_exchange(rest)?,
+                referral_fee_bps: None,
+                wrap_token1: false,
+                unwrap_token0: false,
+                max_token1_amount: None,
             },
             _ => return Err(InvalidInstruction.into()),
         })
@@ -75,19 +804,49 @@ impl EscrowInstruction {
         return Self::unpack_init_escrow(input);
     }
 
+    /// Packs the instruction with Borsh behind BORSH_INSTRUCTION_PREFIX
     pub fn pack(&self) -> Vec<u8> {
+        let mut buf = vec![BORSH_INSTRUCTION_PREFIX];
+        // serializing into a Vec can't fail
+        self.serialize(&mut buf).unwrap();
+        buf
+    }
+
+    /// Packs the instruction in the legacy layout, for programs deployed before Borsh. Only
+    /// the original InitEscrow and Exchange without any of the options added since have one
+    pub fn pack_legacy(&self) -> Result<Vec<u8>, ProgramError> {
         let mut buf = Vec::with_capacity(size_of::<Self>());
         match self {
-            &Self::InitEscrow { amount } => {
+            Self::InitEscrow {
+                amount,
+                basket_len: 0,
+                extra_ask_amounts,
+                list_in_market: false,
+                expires_at: None,
+                wrap_amount: 0,
+                unwrap_token1: false,
+                pay_to_ata: false,
+                delegated_amount: None,
+                oracle_quote: None,
+                refill_amount: None,
+                required_issuer: None,
+            } if extra_ask_amounts.is_empty() => {
                 buf.push(0); // tag for enum
                 buf.extend_from_slice(&amount.to_le_bytes());
             }
-            &Self::Exchange { amount } => {
+            Self::Exchange {
+                amount,
+                referral_fee_bps: None,
+                wrap_token1: false,
+                unwrap_token0: false,
+                max_token1_amount: None,
+            } => {
                 buf.push(1); // tag for enum
                 buf.extend_from_slice(&amount.to_le_bytes());
             }
+            _ => return Err(InvalidInstruction.into()),
         };
-        buf
+        Ok(buf)
     }
 }
 
@@ -102,15 +861,106 @@ pub fn init_escrow(
     token_program_id: &Pubkey,
     amount: u64,
 ) -> Result<Instruction, ProgramError> {
-    let data = EscrowInstruction::InitEscrow { amount }.pack();
+    init_basket_escrow(
+        escrow_program_id,
+        maker,
+        tmp_token0,
+        &[],
+        maker_token1,
+        escrow_info,
+        token_program_id,
+        amount,
+    )
+}
 
-    let mut accounts = Vec::with_capacity(6);
+/// Creates a `InitEscrow` instruction depositing the basket vaults together with tmp_token0.
+pub fn init_basket_escrow(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    tmp_token0: &Pubkey,
+    basket_vaults: &[Pubkey],
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    init_multi_asset_escrow(
+        escrow_program_id,
+        maker,
+        tmp_token0,
+        basket_vaults,
+        maker_token1,
+        &[],
+        escrow_info,
+        token_program_id,
+        None,
+        None,
+        None,
+        amount,
+    )
+}
+
+/// Creates a `InitEscrow` instruction depositing the basket vaults together with tmp_token0
+/// and asking for the extra asks, given as (maker token account, amount) pairs, on top of token1.
+/// The (mint0, mint1) pair must be given for Token-2022, the escrow is listed in the market
+/// if given and can't be taken from expires_at on.
+pub fn init_multi_asset_escrow(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    tmp_token0: &Pubkey,
+    basket_vaults: &[Pubkey],
+    maker_token1: &Pubkey,
+    extra_asks: &[(Pubkey, u64)],
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    mints: Option<(&Pubkey, &Pubkey)>,
+    market: Option<&Pubkey>,
+    expires_at: Option<i64>,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    if basket_vaults.len() > MAX_BASKET_VAULTS || extra_asks.len() > MAX_EXTRA_ASKS {
+        return Err(InvalidInstructionData.into());
+    }
+    let data = EscrowInstruction::InitEscrow {
+        amount,
+        basket_len: basket_vaults.len() as u8,
+        extra_ask_amounts: extra_asks.iter().map(|(_, amount)| *amount).collect(),
+        list_in_market: market.is_some(),
+        expires_at,
+        wrap_amount: 0,
+        unwrap_token1: false,
+        pay_to_ata: false,
+        delegated_amount: None,
+        oracle_quote: None,
+        refill_amount: None,
+        required_issuer: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let mut accounts = Vec::with_capacity(9 + basket_vaults.len() + extra_asks.len());
     accounts.push(AccountMeta::new_readonly(*maker, true));
     accounts.push(AccountMeta::new(*tmp_token0, false));
     accounts.push(AccountMeta::new_readonly(*maker_token1, false));
     accounts.push(AccountMeta::new(*escrow_info, false));
     accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
     accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(config, false));
+    accounts.push(AccountMeta::new_readonly(mint_policy, false));
+    for basket_vault in basket_vaults {
+        accounts.push(AccountMeta::new(*basket_vault, false));
+    }
+    for (recipient, _) in extra_asks {
+        accounts.push(AccountMeta::new_readonly(*recipient, false));
+    }
+    if let Some((mint0, mint1)) = mints {
+        accounts.push(AccountMeta::new_readonly(*mint0, false));
+        accounts.push(AccountMeta::new_readonly(*mint1, false));
+    }
+    if let Some(market) = market {
+        accounts.push(AccountMeta::new(*market, false));
+    }
 
     Ok(Instruction {
         program_id: *escrow_program_id,
@@ -119,6 +969,7 @@ pub fn init_escrow(
     })
 }
 
+/// Creates a `Exchange` instruction.
 pub fn exchange(
     escrow_program_id: &Pubkey,
     taker: &Pubkey,
@@ -132,9 +983,92 @@ pub fn exchange(
     pda: &Pubkey,
     amount: u64,
 ) -> Result<Instruction, ProgramError> {
-    let data = EscrowInstruction::Exchange { amount }.pack();
+    exchange_basket(
+        escrow_program_id,
+        taker,
+        taker_token1,
+        taker_token0,
+        pda_tmp_token0,
+        &[],
+        maker,
+        maker_token1,
+        escrow_info,
+        token_program_id,
+        pda,
+        amount,
+    )
+}
+
+/// Creates a `Exchange` instruction for an escrow with basket vaults,
+/// given as (pda basket vault, taker token account) pairs in the order stored in the escrow info.
+pub fn exchange_basket(
+    escrow_program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    basket: &[(Pubkey, Pubkey)],
+    maker: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    exchange_multi_asset(
+        escrow_program_id,
+        taker,
+        taker_token1,
+        taker_token0,
+        pda_tmp_token0,
+        basket,
+        maker,
+        maker_token1,
+        &[],
+        escrow_info,
+        token_program_id,
+        pda,
+        None,
+        None,
+        None,
+        amount,
+    )
+}
+
+/// Creates a `Exchange` instruction for an escrow with basket vaults and extra asks.
+/// The basket is given as (pda basket vault, taker token account) pairs and the extra asks as
+/// (taker token account, maker token account) pairs, both in the order stored in the escrow info.
+/// The (mint0, mint1) pair must be given for Token-2022 and the market if the escrow was listed,
+/// the referral as (referrer token1 account, fee bps).
+pub fn exchange_multi_asset(
+    escrow_program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    basket: &[(Pubkey, Pubkey)],
+    maker: &Pubkey,
+    maker_token1: &Pubkey,
+    extra_asks: &[(Pubkey, Pubkey)],
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    mints: Option<(&Pubkey, &Pubkey)>,
+    market: Option<&Pubkey>,
+    referral: Option<(&Pubkey, u16)>,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Exchange {
+        amount,
+        referral_fee_bps: referral.map(|(_, fee_bps)| fee_bps),
+        wrap_token1: false,
+        unwrap_token0: false,
+        max_token1_amount: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
 
-    let mut accounts = Vec::with_capacity(9);
+    let mut accounts = Vec::with_capacity(14 + 2 * basket.len() + 2 * extra_asks.len());
     accounts.push(AccountMeta::new_readonly(*taker, true));
     accounts.push(AccountMeta::new(*taker_token1, false));
     accounts.push(AccountMeta::new(*taker_token0, false));
@@ -144,6 +1078,1825 @@ pub fn exchange(
     accounts.push(AccountMeta::new(*escrow_info, false));
     accounts.push(AccountMeta::new_readonly(*token_program_id, false));
     accounts.push(AccountMeta::new_readonly(*pda, false));
+    accounts.push(AccountMeta::new_readonly(config, false));
+    for (pda_basket_vault, taker_basket_token) in basket {
+        accounts.push(AccountMeta::new(*pda_basket_vault, false));
+        accounts.push(AccountMeta::new(*taker_basket_token, false));
+    }
+    for (taker_ask_token, maker_ask_token) in extra_asks {
+        accounts.push(AccountMeta::new(*taker_ask_token, false));
+        accounts.push(AccountMeta::new(*maker_ask_token, false));
+    }
+    if let Some((mint0, mint1)) = mints {
+        accounts.push(AccountMeta::new_readonly(*mint0, false));
+        accounts.push(AccountMeta::new_readonly(*mint1, false));
+    }
+    if let Some(market) = market {
+        accounts.push(AccountMeta::new(*market, false));
+    }
+    if let Some((referrer_token1, _)) = referral {
+        accounts.push(AccountMeta::new(*referrer_token1, false));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `InitEscrow` instruction of the classic token program for a maker that offers or asks
+/// for SOL. A wrap_amount above 0 is moved from the maker into tmp_token0, a native mint account,
+/// and with unwrap_token1 maker_token1, a native mint account, is closed to the maker on exchange.
+/// Escrows unwrapping token1 can't be listed, the market is for those only wrapping.
+pub fn init_sol_escrow(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    tmp_token0: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    wrap_amount: u64,
+    unwrap_token1: bool,
+    market: Option<&Pubkey>,
+    expires_at: Option<i64>,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitEscrow {
+        amount,
+        basket_len: 0,
+        extra_ask_amounts: Vec::new(),
+        list_in_market: market.is_some(),
+        expires_at,
+        wrap_amount,
+        unwrap_token1,
+        pay_to_ata: false,
+        delegated_amount: None,
+        oracle_quote: None,
+        refill_amount: None,
+        required_issuer: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let mut accounts = Vec::with_capacity(8);
+    accounts.push(AccountMeta::new(*maker, true));
+    accounts.push(AccountMeta::new(*tmp_token0, false));
+    accounts.push(AccountMeta::new(*maker_token1, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    accounts.push(AccountMeta::new_readonly(config, false));
+    accounts.push(AccountMeta::new_readonly(mint_policy, false));
+    if wrap_amount > 0 {
+        accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+    }
+    if let Some(market) = market {
+        accounts.push(AccountMeta::new(*market, false));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `Exchange` instruction of the classic token program for a plain escrow or one unwrapping
+/// token1, for a taker that pays or receives SOL. With wrap_token1 the amount the maker expects is
+/// moved from the taker into taker_token1, a native mint account, and with unwrap_token0
+/// taker_token0, a native mint account, is closed to the taker once it received token0.
+pub fn exchange_sol(
+    escrow_program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    maker: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    pda: &Pubkey,
+    market: Option<&Pubkey>,
+    wrap_token1: bool,
+    unwrap_token0: bool,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Exchange {
+        amount,
+        referral_fee_bps: None,
+        wrap_token1,
+        unwrap_token0,
+        max_token1_amount: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let mut accounts = Vec::with_capacity(11);
+    accounts.push(AccountMeta::new(*taker, true));
+    accounts.push(AccountMeta::new(*taker_token1, false));
+    accounts.push(AccountMeta::new(*taker_token0, false));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*maker, false));
+    accounts.push(AccountMeta::new(*maker_token1, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+    accounts.push(AccountMeta::new_readonly(config, false));
+    if let Some(market) = market {
+        accounts.push(AccountMeta::new(*market, false));
+    }
+    if wrap_token1 {
+        accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a plain `InitEscrow` instruction paying the maker's associated token account of mint1,
+/// which is created on exchange if missing. mint0 must be given for Token-2022.
+pub fn init_ata_escrow(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    tmp_token0: &Pubkey,
+    mint1: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    mint0: Option<&Pubkey>,
+    expires_at: Option<i64>,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitEscrow {
+        amount,
+        basket_len: 0,
+        extra_ask_amounts: Vec::new(),
+        list_in_market: false,
+        expires_at,
+        wrap_amount: 0,
+        unwrap_token1: false,
+        pay_to_ata: true,
+        delegated_amount: None,
+        oracle_quote: None,
+        refill_amount: None,
+        required_issuer: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let mut accounts = Vec::with_capacity(8);
+    accounts.push(AccountMeta::new_readonly(*maker, true));
+    accounts.push(AccountMeta::new(*tmp_token0, false));
+    accounts.push(AccountMeta::new_readonly(*mint1, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(config, false));
+    accounts.push(AccountMeta::new_readonly(mint_policy, false));
+    if let Some(mint0) = mint0 {
+        accounts.push(AccountMeta::new_readonly(*mint0, false));
+        accounts.push(AccountMeta::new_readonly(*mint1, false));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a non-custodial `InitEscrow` instruction of the classic token program, approving the PDA
+/// to take amount0 from maker_token0 on exchange instead of handing over a tmp token account.
+/// It is taken with maker_token0 as pda_tmp_token0 and amount0 as amount.
+pub fn init_delegated_escrow(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    maker_token0: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    expires_at: Option<i64>,
+    amount0: u64,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitEscrow {
+        amount,
+        basket_len: 0,
+        extra_ask_amounts: Vec::new(),
+        list_in_market: false,
+        expires_at,
+        wrap_amount: 0,
+        unwrap_token1: false,
+        pay_to_ata: false,
+        delegated_amount: Some(amount0),
+        oracle_quote: None,
+        refill_amount: None,
+        required_issuer: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*maker, true),
+        AccountMeta::new(*maker_token0, false),
+        AccountMeta::new_readonly(*maker_token1, false),
+        AccountMeta::new(*escrow_info, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new_readonly(mint_policy, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates the instructions taking a plain escrow that pay the taker's and the maker's associated
+/// token accounts, creating them first if missing, paid by payer. The mints of Token-2022 are
+/// passed on to the `Exchange`.
+pub fn exchange_with_atas(
+    escrow_program_id: &Pubkey,
+    payer: &Pubkey,
+    taker: &Pubkey,
+    taker_token1: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    mint0: &Pubkey,
+    maker: &Pubkey,
+    mint1: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    amount: u64,
+) -> Result<Vec<Instruction>, ProgramError> {
+    let taker_token0 = token::get_associated_token_address(taker, mint0, token_program_id);
+    let maker_token1 = token::get_associated_token_address(maker, mint1, token_program_id);
+    let mints = if *token_program_id == token::token_2022::id() {
+        Some((mint0, mint1))
+    } else {
+        None
+    };
+    Ok(vec![
+        create_recipient_account(escrow_program_id, payer, taker, mint0, token_program_id)?,
+        create_recipient_account(escrow_program_id, payer, maker, mint1, token_program_id)?,
+        exchange_multi_asset(
+            escrow_program_id,
+            taker,
+            taker_token1,
+            &taker_token0,
+            pda_tmp_token0,
+            &[],
+            maker,
+            &maker_token1,
+            &[],
+            escrow_info,
+            token_program_id,
+            pda,
+            mints,
+            None,
+            None,
+            amount,
+        )?,
+    ])
+}
+
+/// Creates a `CreateRecipientAccount` instruction for the associated token account of wallet and mint.
+pub fn create_recipient_account(
+    escrow_program_id: &Pubkey,
+    payer: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::CreateRecipientAccount.pack();
+    let associated_account = token::get_associated_token_address(wallet, mint, token_program_id);
+
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(associated_account, false),
+        AccountMeta::new_readonly(*wallet, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(token::associated_token::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `InitHtlc` instruction.
+pub fn init_htlc(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    tmp_token0: &Pubkey,
+    recipient_token0: &Pubkey,
+    htlc_info: &Pubkey,
+    token_program_id: &Pubkey,
+    hash_kind: HashKind,
+    hashlock: [u8; 32],
+    timeout: i64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitHtlc {
+        hash_kind,
+        hashlock,
+        timeout,
+    }
+    .pack();
+
+    let mut accounts = Vec::with_capacity(6);
+    accounts.push(AccountMeta::new_readonly(*maker, true));
+    accounts.push(AccountMeta::new(*tmp_token0, false));
+    accounts.push(AccountMeta::new_readonly(*recipient_token0, false));
+    accounts.push(AccountMeta::new(*htlc_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `ClaimHtlc` instruction.
+pub fn claim_htlc(
+    escrow_program_id: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    recipient_token0: &Pubkey,
+    maker: &Pubkey,
+    htlc_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    preimage: Vec<u8>,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::ClaimHtlc { preimage }.pack();
+
+    let mut accounts = Vec::with_capacity(7);
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*recipient_token0, false));
+    accounts.push(AccountMeta::new(*maker, false));
+    accounts.push(AccountMeta::new(*htlc_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::clock::id(), false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `RefundHtlc` instruction.
+pub fn refund_htlc(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    maker_token0: &Pubkey,
+    htlc_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::RefundHtlc.pack();
+
+    let mut accounts = Vec::with_capacity(7);
+    accounts.push(AccountMeta::new(*maker, true));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*maker_token0, false));
+    accounts.push(AccountMeta::new(*htlc_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::clock::id(), false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `InitArbiterEscrow` instruction.
+pub fn init_arbiter_escrow(
+    escrow_program_id: &Pubkey,
+    payer: &Pubkey,
+    tmp_token0: &Pubkey,
+    payer_token0: &Pubkey,
+    payee_token0: &Pubkey,
+    arbiter: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitArbiterEscrow.pack();
+
+    let mut accounts = Vec::with_capacity(8);
+    accounts.push(AccountMeta::new_readonly(*payer, true));
+    accounts.push(AccountMeta::new(*tmp_token0, false));
+    accounts.push(AccountMeta::new_readonly(*payer_token0, false));
+    accounts.push(AccountMeta::new_readonly(*payee_token0, false));
+    accounts.push(AccountMeta::new_readonly(*arbiter, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `ReleaseToPayee` instruction.
+pub fn release_to_payee(
+    escrow_program_id: &Pubkey,
+    payer: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    payee_token0: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::ReleaseToPayee.pack();
+
+    let mut accounts = Vec::with_capacity(6);
+    accounts.push(AccountMeta::new(*payer, true));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*payee_token0, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `RefundToPayer` instruction.
+pub fn refund_to_payer(
+    escrow_program_id: &Pubkey,
+    payee: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    payer_token0: &Pubkey,
+    payer: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::RefundToPayer.pack();
+
+    let mut accounts = Vec::with_capacity(7);
+    accounts.push(AccountMeta::new_readonly(*payee, true));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*payer_token0, false));
+    accounts.push(AccountMeta::new(*payer, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `RaiseDispute` instruction.
+pub fn raise_dispute(
+    escrow_program_id: &Pubkey,
+    party: &Pubkey,
+    escrow_info: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::RaiseDispute.pack();
+
+    let mut accounts = Vec::with_capacity(2);
+    accounts.push(AccountMeta::new_readonly(*party, true));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `ResolveDispute` instruction.
+pub fn resolve_dispute(
+    escrow_program_id: &Pubkey,
+    arbiter: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    payee_token0: &Pubkey,
+    payer_token0: &Pubkey,
+    payer: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    payee_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::ResolveDispute { payee_amount }.pack();
+
+    let mut accounts = Vec::with_capacity(8);
+    accounts.push(AccountMeta::new_readonly(*arbiter, true));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*payee_token0, false));
+    accounts.push(AccountMeta::new(*payer_token0, false));
+    accounts.push(AccountMeta::new(*payer, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `InitMilestoneEscrow` instruction.
+pub fn init_milestone_escrow(
+    escrow_program_id: &Pubkey,
+    payer: &Pubkey,
+    tmp_token0: &Pubkey,
+    payer_token0: &Pubkey,
+    payee_token0: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    amounts: Vec<u64>,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitMilestoneEscrow { amounts }.pack();
+
+    let mut accounts = Vec::with_capacity(7);
+    accounts.push(AccountMeta::new_readonly(*payer, true));
+    accounts.push(AccountMeta::new(*tmp_token0, false));
+    accounts.push(AccountMeta::new_readonly(*payer_token0, false));
+    accounts.push(AccountMeta::new_readonly(*payee_token0, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `ApproveMilestone` instruction.
+pub fn approve_milestone(
+    escrow_program_id: &Pubkey,
+    payer: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    payee_token0: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    index: u8,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::ApproveMilestone { index }.pack();
+
+    let mut accounts = Vec::with_capacity(6);
+    accounts.push(AccountMeta::new(*payer, true));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*payee_token0, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `CancelMilestoneEscrow` instruction.
+pub fn cancel_milestone_escrow(
+    escrow_program_id: &Pubkey,
+    payer: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    payer_token0: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::CancelMilestoneEscrow.pack();
+
+    let mut accounts = Vec::with_capacity(6);
+    accounts.push(AccountMeta::new(*payer, true));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*payer_token0, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `InitVesting` instruction.
+pub fn init_vesting(
+    escrow_program_id: &Pubkey,
+    funder: &Pubkey,
+    tmp_token0: &Pubkey,
+    funder_token0: &Pubkey,
+    beneficiary_token0: &Pubkey,
+    vesting_info: &Pubkey,
+    token_program_id: &Pubkey,
+    start_ts: i64,
+    cliff_ts: i64,
+    end_ts: i64,
+    is_revocable: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitVesting {
+        start_ts,
+        cliff_ts,
+        end_ts,
+        is_revocable,
+    }
+    .pack();
+
+    let mut accounts = Vec::with_capacity(7);
+    accounts.push(AccountMeta::new_readonly(*funder, true));
+    accounts.push(AccountMeta::new(*tmp_token0, false));
+    accounts.push(AccountMeta::new_readonly(*funder_token0, false));
+    accounts.push(AccountMeta::new_readonly(*beneficiary_token0, false));
+    accounts.push(AccountMeta::new(*vesting_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `WithdrawVested` instruction.
+pub fn withdraw_vested(
+    escrow_program_id: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    beneficiary_token0: &Pubkey,
+    funder: &Pubkey,
+    vesting_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::WithdrawVested.pack();
+
+    let mut accounts = Vec::with_capacity(7);
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*beneficiary_token0, false));
+    accounts.push(AccountMeta::new(*funder, false));
+    accounts.push(AccountMeta::new(*vesting_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::clock::id(), false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `RevokeVesting` instruction.
+pub fn revoke_vesting(
+    escrow_program_id: &Pubkey,
+    funder: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    beneficiary_token0: &Pubkey,
+    funder_token0: &Pubkey,
+    vesting_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::RevokeVesting.pack();
+
+    let mut accounts = Vec::with_capacity(8);
+    accounts.push(AccountMeta::new(*funder, true));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*beneficiary_token0, false));
+    accounts.push(AccountMeta::new(*funder_token0, false));
+    accounts.push(AccountMeta::new(*vesting_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::clock::id(), false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// One escrow taken by a `BatchExchange`, its accounts as found in the escrow info
+pub struct BatchExchangeEscrow {
+    pub pda_tmp_token0: Pubkey,
+    pub maker: Pubkey,
+    pub maker_token1: Pubkey,
+    pub escrow_info: Pubkey,
+    /// the market the escrow was listed in, if any
+    pub market: Option<Pubkey>,
+    /// the amount of token0 the taker expects to receive
+    pub amount: u64,
+}
+
+/// Creates a `BatchExchange` instruction.
+pub fn batch_exchange(
+    escrow_program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    escrows: &[BatchExchangeEscrow],
+    max_total_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    if escrows.len() > u8::MAX as usize {
+        return Err(InvalidInstructionData.into());
+    }
+    let data = EscrowInstruction::BatchExchange {
+        amounts: escrows.iter().map(|escrow| escrow.amount).collect(),
+        max_total_amount,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let mut accounts = Vec::with_capacity(6 + 5 * escrows.len());
+    accounts.push(AccountMeta::new_readonly(*taker, true));
+    accounts.push(AccountMeta::new(*taker_token1, false));
+    accounts.push(AccountMeta::new(*taker_token0, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+    accounts.push(AccountMeta::new_readonly(config, false));
+    for escrow in escrows {
+        accounts.push(AccountMeta::new(escrow.pda_tmp_token0, false));
+        accounts.push(AccountMeta::new(escrow.maker, false));
+        accounts.push(AccountMeta::new(escrow.maker_token1, false));
+        accounts.push(AccountMeta::new(escrow.escrow_info, false));
+        if let Some(market) = escrow.market {
+            accounts.push(AccountMeta::new(market, false));
+        }
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `Match` instruction, the surplus goes to the cranker's (token a, token b) accounts if given.
+/// The markets must be given for the escrows that were listed.
+pub fn match_escrows(
+    escrow_program_id: &Pubkey,
+    escrow_a_info: &Pubkey,
+    pda_tmp_token_a: &Pubkey,
+    maker_a: &Pubkey,
+    maker_a_token1: &Pubkey,
+    escrow_b_info: &Pubkey,
+    pda_tmp_token_b: &Pubkey,
+    maker_b: &Pubkey,
+    maker_b_token1: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    cranker_tokens: Option<(&Pubkey, &Pubkey)>,
+    market_a: Option<&Pubkey>,
+    market_b: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Match {
+        take_surplus: cranker_tokens.is_some(),
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let mut accounts = Vec::with_capacity(15);
+    accounts.push(AccountMeta::new(*escrow_a_info, false));
+    accounts.push(AccountMeta::new(*pda_tmp_token_a, false));
+    accounts.push(AccountMeta::new(*maker_a, false));
+    accounts.push(AccountMeta::new(*maker_a_token1, false));
+    accounts.push(AccountMeta::new(*escrow_b_info, false));
+    accounts.push(AccountMeta::new(*pda_tmp_token_b, false));
+    accounts.push(AccountMeta::new(*maker_b, false));
+    accounts.push(AccountMeta::new(*maker_b_token1, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+    accounts.push(AccountMeta::new_readonly(config, false));
+    if let Some((cranker_token_a, cranker_token_b)) = cranker_tokens {
+        accounts.push(AccountMeta::new(*cranker_token_a, false));
+        accounts.push(AccountMeta::new(*cranker_token_b, false));
+    }
+    for market in market_a.iter().chain(market_b.iter()) {
+        accounts.push(AccountMeta::new(**market, false));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `InitMarket` instruction for the market of escrows offering mint0 for mint1.
+pub fn init_market(
+    escrow_program_id: &Pubkey,
+    payer: &Pubkey,
+    mint0: &Pubkey,
+    mint1: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitMarket.pack();
+    let (market, _bump_seed) = Market::find_address(mint0, mint1, escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(market, false),
+        AccountMeta::new_readonly(*mint0, false),
+        AccountMeta::new_readonly(*mint1, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `CancelEscrow` instruction, the basket is given as (pda basket vault, maker token account) pairs
+/// in the order stored in the escrow info. mint0 must be given for Token-2022, maker_token1 if the escrow
+/// unwraps token1, the market if the escrow was listed and the funding account of a standing order.
+pub fn cancel_escrow(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    maker_token0: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    mint0: Option<&Pubkey>,
+    basket: &[(Pubkey, Pubkey)],
+    unwrapped_maker_token1: Option<&Pubkey>,
+    market: Option<&Pubkey>,
+    funding: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::CancelEscrow.pack();
+
+    let mut accounts = Vec::with_capacity(9 + 2 * basket.len());
+    accounts.push(AccountMeta::new(*maker, true));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*maker_token0, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+    if let Some(mint0) = mint0 {
+        accounts.push(AccountMeta::new_readonly(*mint0, false));
+    }
+    for (pda_basket_vault, maker_basket_token) in basket {
+        accounts.push(AccountMeta::new(*pda_basket_vault, false));
+        accounts.push(AccountMeta::new(*maker_basket_token, false));
+    }
+    if let Some(maker_token1) = unwrapped_maker_token1 {
+        accounts.push(AccountMeta::new(*maker_token1, false));
+    }
+    if let Some(market) = market {
+        accounts.push(AccountMeta::new(*market, false));
+    }
+    if let Some(funding) = funding {
+        accounts.push(AccountMeta::new(*funding, false));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `ExpireEscrow` instruction, the basket is given as (pda basket vault, maker token account) pairs
+/// in the order stored in the escrow info. mint0 must be given for Token-2022, maker_token1 if the escrow
+/// unwraps token1 and the market if the escrow was listed.
+pub fn expire_escrow(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    maker_token0: &Pubkey,
+    escrow_info: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    mint0: Option<&Pubkey>,
+    basket: &[(Pubkey, Pubkey)],
+    unwrapped_maker_token1: Option<&Pubkey>,
+    market: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::ExpireEscrow.pack();
+
+    let mut accounts = Vec::with_capacity(10 + 2 * basket.len());
+    accounts.push(AccountMeta::new(*maker, false));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*maker_token0, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(*token_program_id, false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::clock::id(), false));
+    if let Some(mint0) = mint0 {
+        accounts.push(AccountMeta::new_readonly(*mint0, false));
+    }
+    for (pda_basket_vault, maker_basket_token) in basket {
+        accounts.push(AccountMeta::new(*pda_basket_vault, false));
+        accounts.push(AccountMeta::new(*maker_basket_token, false));
+    }
+    if let Some(maker_token1) = unwrapped_maker_token1 {
+        accounts.push(AccountMeta::new(*maker_token1, false));
+    }
+    if let Some(market) = market {
+        accounts.push(AccountMeta::new(*market, false));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `InitConfig` instruction making admin, the program's upgrade authority, the config's admin.
+pub fn init_config(
+    escrow_program_id: &Pubkey,
+    admin: &Pubkey,
+    max_referral_fee_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitConfig {
+        max_referral_fee_bps,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (program_data, _bump_seed) =
+        Pubkey::find_program_address(&[escrow_program_id.as_ref()], &bpf_loader_upgradeable::id());
+
+    let accounts = vec![
+        AccountMeta::new(*admin, true),
+        AccountMeta::new(config, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(program_data, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetReferralFeeCap` instruction.
+pub fn set_referral_fee_cap(
+    escrow_program_id: &Pubkey,
+    admin: &Pubkey,
+    max_referral_fee_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::SetReferralFeeCap {
+        max_referral_fee_bps,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(config, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `ProposeCounter` instruction offering the whole balance of tmp_token1.
+pub fn propose_counter(
+    escrow_program_id: &Pubkey,
+    taker: &Pubkey,
+    tmp_token1: &Pubkey,
+    taker_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    escrow_info: &Pubkey,
+    counter_info: &Pubkey,
+    token_program_id: &Pubkey,
+    amount0: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::ProposeCounter { amount0 }.pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*taker, true),
+        AccountMeta::new(*tmp_token1, false),
+        AccountMeta::new_readonly(*taker_token1, false),
+        AccountMeta::new_readonly(*taker_token0, false),
+        AccountMeta::new_readonly(*escrow_info, false),
+        AccountMeta::new(*counter_info, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(config, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `AcceptCounter` instruction, the market must be given if the escrow was listed.
+pub fn accept_counter(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    counter_info: &Pubkey,
+    pda_tmp_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    taker: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+    market: Option<&Pubkey>,
+    amount1: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::AcceptCounter { amount1 }.pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let mut accounts = vec![
+        AccountMeta::new(*maker, true),
+        AccountMeta::new(*pda_tmp_token0, false),
+        AccountMeta::new(*maker_token1, false),
+        AccountMeta::new(*escrow_info, false),
+        AccountMeta::new(*counter_info, false),
+        AccountMeta::new(*pda_tmp_token1, false),
+        AccountMeta::new(*taker_token0, false),
+        AccountMeta::new(*taker, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*pda, false),
+        AccountMeta::new_readonly(config, false),
+    ];
+    if let Some(market) = market {
+        accounts.push(AccountMeta::new(*market, false));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `RejectCounter` instruction.
+pub fn reject_counter(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    escrow_info: &Pubkey,
+    taker: &Pubkey,
+    counter_info: &Pubkey,
+    pda_tmp_token1: &Pubkey,
+    taker_token1: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::RejectCounter.pack();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*maker, true),
+        AccountMeta::new_readonly(*escrow_info, false),
+        AccountMeta::new(*taker, false),
+        AccountMeta::new(*counter_info, false),
+        AccountMeta::new(*pda_tmp_token1, false),
+        AccountMeta::new(*taker_token1, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*pda, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `WithdrawCounter` instruction.
+pub fn withdraw_counter(
+    escrow_program_id: &Pubkey,
+    taker: &Pubkey,
+    counter_info: &Pubkey,
+    pda_tmp_token1: &Pubkey,
+    taker_token1: &Pubkey,
+    token_program_id: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::WithdrawCounter.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*taker, true),
+        AccountMeta::new(*counter_info, false),
+        AccountMeta::new(*pda_tmp_token1, false),
+        AccountMeta::new(*taker_token1, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*pda, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an oracle-priced `InitEscrow` instruction of the classic token program, asking for the
+/// oracle quote's price of tmp_token0 in token1 on exchange, but at least min_amount
+pub fn init_oracle_escrow(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    tmp_token0: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    oracle_quote: OracleQuote,
+    expires_at: Option<i64>,
+    min_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitEscrow {
+        amount: min_amount,
+        basket_len: 0,
+        extra_ask_amounts: Vec::new(),
+        list_in_market: false,
+        expires_at,
+        wrap_amount: 0,
+        unwrap_token1: false,
+        pay_to_ata: false,
+        delegated_amount: None,
+        oracle_quote: Some(oracle_quote),
+        refill_amount: None,
+        required_issuer: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*maker, true),
+        AccountMeta::new(*tmp_token0, false),
+        AccountMeta::new_readonly(*maker_token1, false),
+        AccountMeta::new(*escrow_info, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new_readonly(mint_policy, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `Exchange` instruction of the classic token program for an oracle-priced escrow,
+/// paying at most max_token1_amount at the price feed's current price
+pub fn exchange_oracle(
+    escrow_program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    maker: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    pda: &Pubkey,
+    price_feed: &Pubkey,
+    max_token1_amount: u64,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Exchange {
+        amount,
+        referral_fee_bps: None,
+        wrap_token1: false,
+        unwrap_token0: false,
+        max_token1_amount: Some(max_token1_amount),
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*taker, true),
+        AccountMeta::new(*taker_token1, false),
+        AccountMeta::new(*taker_token0, false),
+        AccountMeta::new(*pda_tmp_token0, false),
+        AccountMeta::new(*maker, false),
+        AccountMeta::new(*maker_token1, false),
+        AccountMeta::new(*escrow_info, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*pda, false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new_readonly(*price_feed, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `WriteOption` instruction of the classic token program
+pub fn write_option(
+    escrow_program_id: &Pubkey,
+    writer: &Pubkey,
+    tmp_collateral: &Pubkey,
+    writer_payment: &Pubkey,
+    option_mint: &Pubkey,
+    writer_option_token: &Pubkey,
+    option_info: &Pubkey,
+    pda: &Pubkey,
+    kind: OptionKind,
+    exercise_amount: u64,
+    expires_at: i64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::WriteOption {
+        kind,
+        exercise_amount,
+        expires_at,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*writer, true),
+        AccountMeta::new(*tmp_collateral, false),
+        AccountMeta::new_readonly(*writer_payment, false),
+        AccountMeta::new(*option_mint, false),
+        AccountMeta::new(*writer_option_token, false),
+        AccountMeta::new(*option_info, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*pda, false),
+        AccountMeta::new_readonly(config, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `ExerciseOption` instruction of the classic token program
+pub fn exercise_option(
+    escrow_program_id: &Pubkey,
+    holder: &Pubkey,
+    holder_option_token: &Pubkey,
+    holder_payment: &Pubkey,
+    holder_collateral: &Pubkey,
+    option_mint: &Pubkey,
+    pda_collateral_vault: &Pubkey,
+    writer_payment: &Pubkey,
+    writer: &Pubkey,
+    option_info: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::ExerciseOption.pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*holder, true),
+        AccountMeta::new(*holder_option_token, false),
+        AccountMeta::new(*holder_payment, false),
+        AccountMeta::new(*holder_collateral, false),
+        AccountMeta::new(*option_mint, false),
+        AccountMeta::new(*pda_collateral_vault, false),
+        AccountMeta::new(*writer_payment, false),
+        AccountMeta::new(*writer, false),
+        AccountMeta::new(*option_info, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*pda, false),
+        AccountMeta::new_readonly(config, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `ReclaimOptionCollateral` instruction of the classic token program
+pub fn reclaim_option_collateral(
+    escrow_program_id: &Pubkey,
+    writer: &Pubkey,
+    pda_collateral_vault: &Pubkey,
+    writer_collateral: &Pubkey,
+    option_info: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::ReclaimOptionCollateral.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*writer, true),
+        AccountMeta::new(*pda_collateral_vault, false),
+        AccountMeta::new(*writer_collateral, false),
+        AccountMeta::new(*option_info, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*pda, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a standing order's `InitEscrow` instruction of the classic token program. After each
+/// exchange tmp_token0 is refilled with its initial balance from funding, of which the PDA is
+/// approved for refill_amount, as long as the approval, funding's balance and refill_amount allow.
+pub fn init_standing_order(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    tmp_token0: &Pubkey,
+    maker_token1: &Pubkey,
+    funding: &Pubkey,
+    escrow_info: &Pubkey,
+    refill_amount: u64,
+    expires_at: Option<i64>,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitEscrow {
+        amount,
+        basket_len: 0,
+        extra_ask_amounts: Vec::new(),
+        list_in_market: false,
+        expires_at,
+        wrap_amount: 0,
+        unwrap_token1: false,
+        pay_to_ata: false,
+        delegated_amount: None,
+        oracle_quote: None,
+        refill_amount: Some(refill_amount),
+        required_issuer: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*maker, true),
+        AccountMeta::new(*tmp_token0, false),
+        AccountMeta::new_readonly(*maker_token1, false),
+        AccountMeta::new(*escrow_info, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new_readonly(mint_policy, false),
+        AccountMeta::new(*funding, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `Exchange` instruction of the classic token program for a standing order
+pub fn exchange_standing_order(
+    escrow_program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    maker: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    pda: &Pubkey,
+    funding: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Exchange {
+        amount,
+        referral_fee_bps: None,
+        wrap_token1: false,
+        unwrap_token0: false,
+        max_token1_amount: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*taker, true),
+        AccountMeta::new(*taker_token1, false),
+        AccountMeta::new(*taker_token0, false),
+        AccountMeta::new(*pda_tmp_token0, false),
+        AccountMeta::new(*maker, false),
+        AccountMeta::new(*maker_token1, false),
+        AccountMeta::new(*escrow_info, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*pda, false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new(*funding, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetAdmin` instruction.
+pub fn set_admin(
+    escrow_program_id: &Pubkey,
+    admin: &Pubkey,
+    new_admin: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::SetAdmin.pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(config, false),
+        AccountMeta::new_readonly(*new_admin, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `SetPaused` instruction.
+pub fn set_paused(
+    escrow_program_id: &Pubkey,
+    admin: &Pubkey,
+    is_paused: bool,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::SetPaused { is_paused }.pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(config, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `InitMintPolicy` instruction.
+pub fn init_mint_policy(
+    escrow_program_id: &Pubkey,
+    admin: &Pubkey,
+    mode: PolicyMode,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitMintPolicy { mode }.pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new(*admin, true),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new(mint_policy, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `AddPolicyMint` instruction.
+pub fn add_policy_mint(
+    escrow_program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::AddPolicyMint.pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new(mint_policy, false),
+        AccountMeta::new_readonly(*mint, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `RemovePolicyMint` instruction.
+pub fn remove_policy_mint(
+    escrow_program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::RemovePolicyMint.pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new(mint_policy, false),
+        AccountMeta::new_readonly(*mint, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a plain `InitEscrow` instruction of the classic token program for a maker that is an
+/// spl-token multisig, authorized by the given signers of it.
+pub fn init_multisig_escrow(
+    escrow_program_id: &Pubkey,
+    multisig_maker: &Pubkey,
+    signers: &[&Pubkey],
+    tmp_token0: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitEscrow {
+        amount,
+        basket_len: 0,
+        extra_ask_amounts: Vec::new(),
+        list_in_market: false,
+        expires_at: None,
+        wrap_amount: 0,
+        unwrap_token1: false,
+        pay_to_ata: false,
+        delegated_amount: None,
+        oracle_quote: None,
+        refill_amount: None,
+        required_issuer: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let mut accounts = Vec::with_capacity(8 + signers.len());
+    accounts.push(AccountMeta::new_readonly(*multisig_maker, false));
+    accounts.push(AccountMeta::new(*tmp_token0, false));
+    accounts.push(AccountMeta::new_readonly(*maker_token1, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::rent::id(), false));
+    accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    accounts.push(AccountMeta::new_readonly(config, false));
+    accounts.push(AccountMeta::new_readonly(mint_policy, false));
+    for signer in signers {
+        accounts.push(AccountMeta::new_readonly(**signer, true));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `Exchange` instruction of the classic token program for a plain escrow and a taker
+/// that is an spl-token multisig, authorized by the given signers of it.
+pub fn exchange_multisig(
+    escrow_program_id: &Pubkey,
+    multisig_taker: &Pubkey,
+    signers: &[&Pubkey],
+    taker_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    maker: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    pda: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Exchange {
+        amount,
+        referral_fee_bps: None,
+        wrap_token1: false,
+        unwrap_token0: false,
+        max_token1_amount: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+
+    let mut accounts = Vec::with_capacity(10 + signers.len());
+    accounts.push(AccountMeta::new_readonly(*multisig_taker, false));
+    accounts.push(AccountMeta::new(*taker_token1, false));
+    accounts.push(AccountMeta::new(*taker_token0, false));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*maker, false));
+    accounts.push(AccountMeta::new(*maker_token1, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+    accounts.push(AccountMeta::new_readonly(config, false));
+    for signer in signers {
+        accounts.push(AccountMeta::new_readonly(**signer, true));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `CancelEscrow` instruction of the classic token program for a plain escrow of a
+/// maker that is an spl-token multisig, authorized by the given signers of it.
+pub fn cancel_multisig_escrow(
+    escrow_program_id: &Pubkey,
+    multisig_maker: &Pubkey,
+    signers: &[&Pubkey],
+    pda_tmp_token0: &Pubkey,
+    maker_token0: &Pubkey,
+    escrow_info: &Pubkey,
+    pda: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::CancelEscrow.pack();
+
+    let mut accounts = Vec::with_capacity(6 + signers.len());
+    accounts.push(AccountMeta::new(*multisig_maker, false));
+    accounts.push(AccountMeta::new(*pda_tmp_token0, false));
+    accounts.push(AccountMeta::new(*maker_token0, false));
+    accounts.push(AccountMeta::new(*escrow_info, false));
+    accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    accounts.push(AccountMeta::new_readonly(*pda, false));
+    for signer in signers {
+        accounts.push(AccountMeta::new_readonly(**signer, true));
+    }
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `InitEscrow` instruction of the classic token program for a plain escrow that only
+/// takers holding a valid attestation by the required issuer can take.
+pub fn init_gated_escrow(
+    escrow_program_id: &Pubkey,
+    maker: &Pubkey,
+    tmp_token0: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    required_issuer: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::InitEscrow {
+        amount,
+        basket_len: 0,
+        extra_ask_amounts: Vec::new(),
+        list_in_market: false,
+        expires_at: None,
+        wrap_amount: 0,
+        unwrap_token1: false,
+        pay_to_ata: false,
+        delegated_amount: None,
+        oracle_quote: None,
+        refill_amount: None,
+        required_issuer: Some(*required_issuer),
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (mint_policy, _bump_seed) = MintPolicy::find_address(escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*maker, true),
+        AccountMeta::new(*tmp_token0, false),
+        AccountMeta::new_readonly(*maker_token1, false),
+        AccountMeta::new(*escrow_info, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new_readonly(mint_policy, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `Exchange` instruction of the classic token program for an escrow requiring an
+/// attestation by the given issuer, passing the taker's attestation PDA.
+pub fn exchange_gated(
+    escrow_program_id: &Pubkey,
+    taker: &Pubkey,
+    taker_token1: &Pubkey,
+    taker_token0: &Pubkey,
+    pda_tmp_token0: &Pubkey,
+    maker: &Pubkey,
+    maker_token1: &Pubkey,
+    escrow_info: &Pubkey,
+    pda: &Pubkey,
+    issuer: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::Exchange {
+        amount,
+        referral_fee_bps: None,
+        wrap_token1: false,
+        unwrap_token0: false,
+        max_token1_amount: None,
+    }
+    .pack();
+    let (config, _bump_seed) = Config::find_address(escrow_program_id);
+    let (attestation, _bump_seed) = Attestation::find_address(issuer, taker, escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*taker, true),
+        AccountMeta::new(*taker_token1, false),
+        AccountMeta::new(*taker_token0, false),
+        AccountMeta::new(*pda_tmp_token0, false),
+        AccountMeta::new(*maker, false),
+        AccountMeta::new(*maker_token1, false),
+        AccountMeta::new(*escrow_info, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*pda, false),
+        AccountMeta::new_readonly(config, false),
+        AccountMeta::new_readonly(attestation, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `IssueAttestation` instruction.
+pub fn issue_attestation(
+    escrow_program_id: &Pubkey,
+    issuer: &Pubkey,
+    subject: &Pubkey,
+    expires_at: i64,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::IssueAttestation { expires_at }.pack();
+    let (attestation, _bump_seed) = Attestation::find_address(issuer, subject, escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new(*issuer, true),
+        AccountMeta::new_readonly(*subject, false),
+        AccountMeta::new(attestation, false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *escrow_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a `RevokeAttestation` instruction.
+pub fn revoke_attestation(
+    escrow_program_id: &Pubkey,
+    issuer: &Pubkey,
+    subject: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = EscrowInstruction::RevokeAttestation.pack();
+    let (attestation, _bump_seed) = Attestation::find_address(issuer, subject, escrow_program_id);
+
+    let accounts = vec![
+        AccountMeta::new(*issuer, true),
+        AccountMeta::new(attestation, false),
+    ];
 
     Ok(Instruction {
         program_id: *escrow_program_id,
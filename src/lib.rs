@@ -1,8 +1,12 @@
 pub mod entrypoint;
 pub mod error;
+pub mod event;
+pub mod idl;
 pub mod instruction;
+pub mod oracle;
 pub mod processor;
 pub mod state;
+pub mod token;
 
 // this is the account this program is deployed to
 // solana_program::declare_id!("EPj...");
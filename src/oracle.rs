@@ -0,0 +1,133 @@
+//! Price feeds oracle-priced escrows quote their ask against.
+//!
+//! The escrow is not tied to an oracle program, any account whose data starts with this
+//! little endian layout is a feed, so a relay of any oracle can publish one. The maker picks
+//! the feed and thereby trusts whoever writes it.
+//!
+//! | offset | type      | field        |
+//! |--------|-----------|--------------|
+//! | 0      | `[u8; 4]` | magic `PRCF` |
+//! | 4      | `i64`     | price        |
+//! | 12     | `u64`     | confidence   |
+//! | 20     | `i32`     | exponent     |
+//! | 24     | `i64`     | publish_time |
+//!
+//! The price is in token1 base units per base unit of token0 as price * 10^exponent, the
+//! confidence the half-width of its interval in the same unit and publish_time the unix
+//! timestamp it was last updated at.
+
+use crate::{
+    error::EscrowError,
+    state::{OracleQuote, BPS_DENOMINATOR},
+};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::{
+    account_info::AccountInfo, clock::UnixTimestamp, program_error::ProgramError,
+};
+
+pub const PRICE_FEED_MAGIC: [u8; 4] = *b"PRCF";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriceFeed {
+    pub price: i64,
+    pub confidence: u64,
+    pub exponent: i32,
+    pub publish_time: UnixTimestamp,
+}
+
+impl PriceFeed {
+    pub const LEN: usize = 32;
+
+    /// Unpacks the feed from the start of an account's data, which may be longer
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() < PriceFeed::LEN {
+            return Err(EscrowError::InvalidPrice.into());
+        }
+        let src = array_ref![input, 0, PriceFeed::LEN];
+        let (magic, price, confidence, exponent, publish_time) = array_refs![src, 4, 8, 8, 4, 8];
+        if *magic != PRICE_FEED_MAGIC {
+            return Err(EscrowError::InvalidPrice.into());
+        }
+        Ok(PriceFeed {
+            price: i64::from_le_bytes(*price),
+            confidence: u64::from_le_bytes(*confidence),
+            exponent: i32::from_le_bytes(*exponent),
+            publish_time: i64::from_le_bytes(*publish_time),
+        })
+    }
+
+    pub fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, PriceFeed::LEN];
+        let (magic_dst, price_dst, confidence_dst, exponent_dst, publish_time_dst) =
+            mut_array_refs![dst, 4, 8, 8, 4, 8];
+        *magic_dst = PRICE_FEED_MAGIC;
+        *price_dst = self.price.to_le_bytes();
+        *confidence_dst = self.confidence.to_le_bytes();
+        *exponent_dst = self.exponent.to_le_bytes();
+        *publish_time_dst = self.publish_time.to_le_bytes();
+    }
+
+    /// Amount of token1 amount0 of token0 is worth at the quote's spread on the feed's price,
+    /// rounded up in the maker's favour. Fails if the price is older than the quote's max_age
+    /// at now or less certain than its max_confidence_bps.
+    pub fn quote(
+        &self,
+        quote: &OracleQuote,
+        amount0: u64,
+        now: UnixTimestamp,
+    ) -> Result<u64, ProgramError> {
+        if self.price <= 0 {
+            return Err(EscrowError::InvalidPrice.into());
+        }
+        let price = self.price as u128;
+        if now.saturating_sub(self.publish_time) > quote.max_age as i64 {
+            return Err(EscrowError::StalePrice.into());
+        }
+        if self.confidence as u128 * BPS_DENOMINATOR as u128
+            > price * quote.max_confidence_bps as u128
+        {
+            return Err(EscrowError::PriceUncertain.into());
+        }
+
+        let spread_factor = BPS_DENOMINATOR as i64 + quote.spread_bps as i64;
+        if spread_factor <= 0 {
+            return Err(EscrowError::InvalidPrice.into());
+        }
+        let mut numerator = (amount0 as u128)
+            .checked_mul(price)
+            .and_then(|value| value.checked_mul(spread_factor as u128))
+            .ok_or(EscrowError::InvalidPrice)?;
+        let mut denominator = BPS_DENOMINATOR as u128;
+        // exponents too large for a u128 can't quote an amount that fits a u64 either way
+        let scale = 10u128
+            .checked_pow(self.exponent.unsigned_abs())
+            .ok_or(EscrowError::InvalidPrice)?;
+        if self.exponent >= 0 {
+            numerator = numerator
+                .checked_mul(scale)
+                .ok_or(EscrowError::InvalidPrice)?;
+        } else {
+            denominator = denominator
+                .checked_mul(scale)
+                .ok_or(EscrowError::InvalidPrice)?;
+        }
+        let amount1 = numerator / denominator + (numerator % denominator != 0) as u128;
+        if amount1 > u64::MAX as u128 {
+            return Err(EscrowError::InvalidPrice.into());
+        }
+        Ok(amount1 as u64)
+    }
+}
+
+/// Reads the escrow's price feed account and quotes amount0 of token0 in token1 at now
+pub fn quote_amount1(
+    price_feed: &AccountInfo,
+    quote: &OracleQuote,
+    amount0: u64,
+    now: UnixTimestamp,
+) -> Result<u64, ProgramError> {
+    if *price_feed.key != quote.price_feed_pubkey {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    PriceFeed::unpack(&price_feed.data.borrow())?.quote(quote, amount0, now)
+}
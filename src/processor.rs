@@ -1,16 +1,36 @@
-use crate::{error::EscrowError, instruction::EscrowInstruction, state::Escrow};
+use crate::{
+    error::EscrowError,
+    event::TradeEvent,
+    instruction::EscrowInstruction,
+    oracle,
+    state::{
+        ArbiterEscrow, Ask, Attestation, Config, CounterOffer, CoveredOption, Escrow, HashKind,
+        Htlc, Market, MarketEntry, Milestone, MilestoneEscrow, MilestoneStatus, MintPolicy,
+        OptionKind, OracleQuote, PodEscrow, PodMarket, PodMilestoneEscrow, PodMintPolicy,
+        PolicyMode, Refill, Vesting, ATTESTATION_SEED, BPS_DENOMINATOR, CONFIG_SEED, MARKET_SEED,
+        MAX_MILESTONES, MINT_POLICY_SEED,
+    },
+    token,
+};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable,
+    clock::{Clock, UnixTimestamp},
     entrypoint::ProgramResult,
     msg,
     program::{invoke, invoke_signed},
     program_error::ProgramError,
+    program_option::COption,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
+    system_instruction,
     sysvar::{rent::Rent, Sysvar},
 };
 use spl_token::state::Account as TokenAccount;
 
+/// seed of the PDA that owns all tmp token accounts (vaults) handed over to the program
+const PDA_SEED: &[u8] = b"escrow";
+
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -19,13 +39,226 @@ pub fn process_instruction(
     let instruction = EscrowInstruction::unpack(instruction_data)?;
 
     match instruction {
-        EscrowInstruction::InitEscrow { amount } => {
+        EscrowInstruction::InitEscrow {
+            amount,
+            basket_len,
+            extra_ask_amounts,
+            list_in_market,
+            expires_at,
+            wrap_amount,
+            unwrap_token1,
+            pay_to_ata,
+            delegated_amount,
+            oracle_quote,
+            refill_amount,
+            required_issuer,
+        } => {
             msg!("Instruction - InitEscrow");
-            process_init_escrow(accounts, amount, program_id)
+            process_init_escrow(
+                accounts,
+                amount,
+                basket_len,
+                &extra_ask_amounts,
+                list_in_market,
+                expires_at,
+                wrap_amount,
+                unwrap_token1,
+                pay_to_ata,
+                delegated_amount,
+                oracle_quote,
+                refill_amount,
+                required_issuer,
+                program_id,
+            )
         }
-        EscrowInstruction::Exchange { amount } => {
+        EscrowInstruction::Exchange {
+            amount,
+            referral_fee_bps,
+            wrap_token1,
+            unwrap_token0,
+            max_token1_amount,
+        } => {
             msg!("Instruction: Exchange");
-            process_exchange(accounts, amount, program_id)
+            process_exchange(
+                accounts,
+                amount,
+                referral_fee_bps,
+                wrap_token1,
+                unwrap_token0,
+                max_token1_amount,
+                program_id,
+            )
+        }
+        EscrowInstruction::InitHtlc {
+            hash_kind,
+            hashlock,
+            timeout,
+        } => {
+            msg!("Instruction: InitHtlc");
+            process_init_htlc(accounts, hash_kind, hashlock, timeout, program_id)
+        }
+        EscrowInstruction::ClaimHtlc { preimage } => {
+            msg!("Instruction: ClaimHtlc");
+            process_claim_htlc(accounts, &preimage, program_id)
+        }
+        EscrowInstruction::RefundHtlc => {
+            msg!("Instruction: RefundHtlc");
+            process_refund_htlc(accounts, program_id)
+        }
+        EscrowInstruction::InitArbiterEscrow => {
+            msg!("Instruction: InitArbiterEscrow");
+            process_init_arbiter_escrow(accounts, program_id)
+        }
+        EscrowInstruction::ReleaseToPayee => {
+            msg!("Instruction: ReleaseToPayee");
+            process_release_to_payee(accounts, program_id)
+        }
+        EscrowInstruction::RefundToPayer => {
+            msg!("Instruction: RefundToPayer");
+            process_refund_to_payer(accounts, program_id)
+        }
+        EscrowInstruction::RaiseDispute => {
+            msg!("Instruction: RaiseDispute");
+            process_raise_dispute(accounts, program_id)
+        }
+        EscrowInstruction::ResolveDispute { payee_amount } => {
+            msg!("Instruction: ResolveDispute");
+            process_resolve_dispute(accounts, payee_amount, program_id)
+        }
+        EscrowInstruction::InitMilestoneEscrow { amounts } => {
+            msg!("Instruction: InitMilestoneEscrow");
+            process_init_milestone_escrow(accounts, &amounts, program_id)
+        }
+        EscrowInstruction::ApproveMilestone { index } => {
+            msg!("Instruction: ApproveMilestone");
+            process_approve_milestone(accounts, index, program_id)
+        }
+        EscrowInstruction::CancelMilestoneEscrow => {
+            msg!("Instruction: CancelMilestoneEscrow");
+            process_cancel_milestone_escrow(accounts, program_id)
+        }
+        EscrowInstruction::InitVesting {
+            start_ts,
+            cliff_ts,
+            end_ts,
+            is_revocable,
+        } => {
+            msg!("Instruction: InitVesting");
+            process_init_vesting(
+                accounts,
+                start_ts,
+                cliff_ts,
+                end_ts,
+                is_revocable,
+                program_id,
+            )
+        }
+        EscrowInstruction::WithdrawVested => {
+            msg!("Instruction: WithdrawVested");
+            process_withdraw_vested(accounts, program_id)
+        }
+        EscrowInstruction::RevokeVesting => {
+            msg!("Instruction: RevokeVesting");
+            process_revoke_vesting(accounts, program_id)
+        }
+        EscrowInstruction::BatchExchange {
+            amounts,
+            max_total_amount,
+        } => {
+            msg!("Instruction: BatchExchange");
+            process_batch_exchange(accounts, &amounts, max_total_amount, program_id)
+        }
+        EscrowInstruction::Match { take_surplus } => {
+            msg!("Instruction: Match");
+            process_match(accounts, take_surplus, program_id)
+        }
+        EscrowInstruction::InitMarket => {
+            msg!("Instruction: InitMarket");
+            process_init_market(accounts, program_id)
+        }
+        EscrowInstruction::CancelEscrow => {
+            msg!("Instruction: CancelEscrow");
+            process_cancel_escrow(accounts, false, program_id)
+        }
+        EscrowInstruction::ExpireEscrow => {
+            msg!("Instruction: ExpireEscrow");
+            process_cancel_escrow(accounts, true, program_id)
+        }
+        EscrowInstruction::InitConfig {
+            max_referral_fee_bps,
+        } => {
+            msg!("Instruction: InitConfig");
+            process_init_config(accounts, max_referral_fee_bps, program_id)
+        }
+        EscrowInstruction::SetReferralFeeCap {
+            max_referral_fee_bps,
+        } => {
+            msg!("Instruction: SetReferralFeeCap");
+            process_set_referral_fee_cap(accounts, max_referral_fee_bps, program_id)
+        }
+        EscrowInstruction::CreateRecipientAccount => {
+            msg!("Instruction: CreateRecipientAccount");
+            process_create_recipient_account(accounts)
+        }
+        EscrowInstruction::ProposeCounter { amount0 } => {
+            msg!("Instruction: ProposeCounter");
+            process_propose_counter(accounts, amount0, program_id)
+        }
+        EscrowInstruction::AcceptCounter { amount1 } => {
+            msg!("Instruction: AcceptCounter");
+            process_accept_counter(accounts, amount1, program_id)
+        }
+        EscrowInstruction::RejectCounter => {
+            msg!("Instruction: RejectCounter");
+            process_reject_counter(accounts, program_id)
+        }
+        EscrowInstruction::WithdrawCounter => {
+            msg!("Instruction: WithdrawCounter");
+            process_withdraw_counter(accounts, program_id)
+        }
+        EscrowInstruction::WriteOption {
+            kind,
+            exercise_amount,
+            expires_at,
+        } => {
+            msg!("Instruction: WriteOption");
+            process_write_option(accounts, kind, exercise_amount, expires_at, program_id)
+        }
+        EscrowInstruction::ExerciseOption => {
+            msg!("Instruction: ExerciseOption");
+            process_exercise_option(accounts, program_id)
+        }
+        EscrowInstruction::ReclaimOptionCollateral => {
+            msg!("Instruction: ReclaimOptionCollateral");
+            process_reclaim_option_collateral(accounts, program_id)
+        }
+        EscrowInstruction::SetPaused { is_paused } => {
+            msg!("Instruction: SetPaused");
+            process_set_paused(accounts, is_paused, program_id)
+        }
+        EscrowInstruction::InitMintPolicy { mode } => {
+            msg!("Instruction: InitMintPolicy");
+            process_init_mint_policy(accounts, mode, program_id)
+        }
+        EscrowInstruction::AddPolicyMint => {
+            msg!("Instruction: AddPolicyMint");
+            process_update_mint_policy(accounts, false, program_id)
+        }
+        EscrowInstruction::RemovePolicyMint => {
+            msg!("Instruction: RemovePolicyMint");
+            process_update_mint_policy(accounts, true, program_id)
+        }
+        EscrowInstruction::IssueAttestation { expires_at } => {
+            msg!("Instruction: IssueAttestation");
+            process_issue_attestation(accounts, expires_at, program_id)
+        }
+        EscrowInstruction::RevokeAttestation => {
+            msg!("Instruction: RevokeAttestation");
+            process_revoke_attestation(accounts, program_id)
+        }
+        EscrowInstruction::SetAdmin => {
+            msg!("Instruction: SetAdmin");
+            process_set_admin(accounts, program_id)
         }
     }
 }
@@ -33,20 +266,29 @@ pub fn process_instruction(
 fn process_init_escrow(
     accounts: &[AccountInfo],
     amount: u64,
+    basket_len: u8,
+    extra_ask_amounts: &[u64],
+    list_in_market: bool,
+    expires_at: Option<UnixTimestamp>,
+    wrap_amount: u64,
+    unwrap_token1: bool,
+    pay_to_ata: bool,
+    delegated_amount: Option<u64>,
+    oracle_quote: Option<OracleQuote>,
+    refill_amount: Option<u64>,
+    required_issuer: Option<Pubkey>,
     program_id: &Pubkey,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
+    // a multisig maker's signers are checked once they are read
     let maker = next_account_info(account_info_iter)?;
 
-    if !maker.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
     let tmp_token0 = next_account_info(account_info_iter)?;
 
+    // with pay_to_ata this is the mint of the maker's associated token account
     let maker_token1 = next_account_info(account_info_iter)?;
     // this could still be a mint_account, but it would fail later at taker when trying to move funds to it?
-    if *maker_token1.owner != spl_token::id() {
+    if !token::is_token_program(maker_token1.owner) {
         return Err(ProgramError::IncorrectProgramId);
     }
 
@@ -73,26 +315,232 @@ fn process_init_escrow(
     escrow_info.tmp_token0_pubkey = *tmp_token0.key;
     escrow_info.maker_token1_pubkey = *maker_token1.key;
     escrow_info.maker_token1_expected_amount = amount;
-
-    Escrow::pack(escrow_info, &mut escrow_info_account.data.borrow_mut())?;
-    // this pda will control all tmp_token0 accounts
-    let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
+    escrow_info.expires_at = expires_at;
+    escrow_info.delegated_amount = delegated_amount;
+    escrow_info.oracle_quote = oracle_quote;
+    escrow_info.required_issuer = required_issuer;
 
     let token_program = next_account_info(account_info_iter)?;
-    let owner_change_ix = spl_token::instruction::set_authority(
-        token_program.key,
-        tmp_token0.key,
-        Some(&pda),
-        spl_token::instruction::AuthorityType::AccountOwner,
-        maker.key,
-        &[&maker.key],
-    )?;
+    token::check_token_program(token_program)?;
+    // all of an escrow's token accounts belong to the same token program
+    if maker_token1.owner != token_program.key {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
-    msg!("Calling the token program to transfer token account ownership...");
-    invoke(
-        &owner_change_ix,
-        &[tmp_token0.clone(), maker.clone(), token_program.clone()],
-    )?;
+    check_not_paused(&unpack_config(
+        next_account_info(account_info_iter)?,
+        program_id,
+    )?)?;
+    let mint_policy_account = next_account_info(account_info_iter)?;
+    let maker_signers = next_authority_signers(account_info_iter, maker)?;
+    // a multisig can't pay for wrapping SOL
+    if !maker_signers.is_empty() && wrap_amount > 0 {
+        return Err(EscrowError::UnsupportedEscrowFeature.into());
+    }
+
+    // the account may not exist yet, so its mint is the account passed
+    let maker_token1_mint = || -> Result<Pubkey, ProgramError> {
+        if pay_to_ata {
+            Ok(*maker_token1.key)
+        } else {
+            Ok(token::unpack_token_account(maker_token1)?.mint)
+        }
+    };
+    if pay_to_ata {
+        // unwrapping hands over an existing account
+        if unwrap_token1 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        token::unpack_mint(maker_token1)?;
+        escrow_info.maker_token1_pubkey =
+            token::get_associated_token_address(maker.key, maker_token1.key, token_program.key);
+    }
+
+    let basket_vaults = account_info_iter
+        .by_ref()
+        .take(basket_len as usize)
+        .collect::<Vec<_>>();
+    if basket_vaults.len() != basket_len as usize {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    escrow_info.basket_vault_pubkeys = Vec::with_capacity(basket_vaults.len());
+    for basket_vault in basket_vaults.iter() {
+        // every vault is paid out and closed once on exchange, so none may appear twice
+        if *basket_vault.key == escrow_info.tmp_token0_pubkey
+            || escrow_info.basket_vault_pubkeys.contains(basket_vault.key)
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+        escrow_info.basket_vault_pubkeys.push(*basket_vault.key);
+    }
+
+    escrow_info.extra_asks = Vec::with_capacity(extra_ask_amounts.len());
+    for amount in extra_ask_amounts {
+        let recipient = next_account_info(account_info_iter)?;
+        if recipient.owner != token_program.key {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let recipient_data = token::unpack_token_account(recipient)?;
+        escrow_info.extra_asks.push(Ask {
+            mint: recipient_data.mint,
+            amount: *amount,
+            recipient_pubkey: *recipient.key,
+        });
+    }
+
+    // every deposited and asked mint must pass the policy
+    let mut mints = vec![
+        token::unpack_token_account(tmp_token0)?.mint,
+        maker_token1_mint()?,
+    ];
+    for basket_vault in basket_vaults.iter() {
+        mints.push(token::unpack_token_account(basket_vault)?.mint);
+    }
+    mints.extend(escrow_info.extra_asks.iter().map(|ask| ask.mint));
+    check_mints_allowed(mint_policy_account, &mints, program_id)?;
+
+    if unwrap_token1 {
+        check_native_account(token_program, maker_token1)?;
+        escrow_info.unwrap_token1 = true;
+    }
+
+    // a non-custodial escrow takes from the maker's own account, which never joins a basket
+    if delegated_amount.is_some() && (basket_len > 0 || wrap_amount > 0) {
+        return Err(EscrowError::UnsupportedEscrowFeature.into());
+    }
+    // a standing order refills its single custodial vault, which it has to keep
+    if refill_amount.is_some()
+        && (basket_len > 0 || delegated_amount.is_some() || unwrap_token1 || list_in_market)
+    {
+        return Err(EscrowError::UnsupportedEscrowFeature.into());
+    }
+
+    if token::is_token_2022(token_program) {
+        // transfer_checked needs every mint, which only plain escrows pass
+        if !escrow_info.is_plain() {
+            return Err(EscrowError::UnsupportedEscrowFeature.into());
+        }
+        let mint0 = next_account_info(account_info_iter)?;
+        let mint1 = next_account_info(account_info_iter)?;
+        if token::unpack_token_account(tmp_token0)?.mint != *mint0.key
+            || maker_token1_mint()? != *mint1.key
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        token::check_mint_extensions(mint0)?;
+        token::check_mint_extensions(mint1)?;
+        // an associated token account is checked on exchange once it exists
+        if !pay_to_ata {
+            token::check_recipient_extensions(maker_token1)?;
+        }
+    }
+
+    if wrap_amount > 0 {
+        let system_program = next_account_info(account_info_iter)?;
+        check_native_account(token_program, tmp_token0)?;
+        msg!("Wrapping the maker's SOL into tmp_token0...");
+        wrap_sol(
+            system_program,
+            token_program,
+            maker,
+            tmp_token0,
+            wrap_amount,
+        )?;
+    }
+
+    if list_in_market {
+        // a basket or extra asks have no single price to sort by
+        if !escrow_info.is_plain() {
+            return Err(EscrowError::UnsupportedEscrowFeature.into());
+        }
+        let market_account = next_account_info(account_info_iter)?;
+        let tmp_token0_data = token::unpack_token_account(tmp_token0)?;
+        let (market_pubkey, _bump_seed) =
+            Market::find_address(&tmp_token0_data.mint, &maker_token1_mint()?, program_id);
+        if market_pubkey != *market_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if tmp_token0_data.amount == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut market_data = market_account.data.borrow_mut();
+        let evicted = PodMarket::load_mut(&mut market_data)?.insert(MarketEntry {
+            escrow_pubkey: *escrow_info_account.key,
+            amount0: tmp_token0_data.amount,
+            amount1: amount,
+        })?;
+        if let Some(evicted) = evicted {
+            msg!("Market is full, evicted escrow {}", evicted.escrow_pubkey);
+        }
+        escrow_info.market_pubkey = Some(market_pubkey);
+    }
+
+    // the vault's balance, wrapped SOL included, is the tranche every refill tops it up with
+    let funding = match refill_amount {
+        Some(refill_amount) => {
+            token::check_classic_token_program(token_program)?;
+            let funding = next_account_info(account_info_iter)?;
+            let tmp_token0_data = token::unpack_token_account(tmp_token0)?;
+            if token::unpack_token_account(funding)?.mint != tmp_token0_data.mint {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            if tmp_token0_data.amount == 0 {
+                return Err(ProgramError::InvalidArgument);
+            }
+            escrow_info.refill = Some(Refill {
+                funding_pubkey: *funding.key,
+                tranche_amount: tmp_token0_data.amount,
+                remaining_amount: refill_amount,
+            });
+            Some((funding, refill_amount))
+        }
+        None => None,
+    };
+
+    // the account must have been created with the get_packed_len of the escrow to store
+    Escrow::pack(escrow_info, &mut escrow_info_account.data.borrow_mut())?;
+
+    match delegated_amount {
+        Some(delegated_amount) => approve_pda(
+            token_program,
+            tmp_token0,
+            maker,
+            &maker_signers,
+            delegated_amount,
+            program_id,
+        )?,
+        None => hand_over_vault(token_program, tmp_token0, maker, &maker_signers, program_id)?,
+    }
+    for basket_vault in basket_vaults {
+        hand_over_vault(
+            token_program,
+            basket_vault,
+            maker,
+            &maker_signers,
+            program_id,
+        )?;
+    }
+    // the PDA closes it to the maker once the taker paid into it
+    if unwrap_token1 {
+        hand_over_vault(
+            token_program,
+            maker_token1,
+            maker,
+            &maker_signers,
+            program_id,
+        )?;
+    }
+    if let Some((funding, refill_amount)) = funding {
+        approve_pda(
+            token_program,
+            funding,
+            maker,
+            &maker_signers,
+            refill_amount,
+            program_id,
+        )?;
+    }
 
     Ok(())
 }
@@ -100,15 +548,16 @@ fn process_init_escrow(
 fn process_exchange(
     accounts: &[AccountInfo],
     taker_token0_expected_amount: u64,
+    referral_fee_bps: Option<u16>,
+    wrap_token1: bool,
+    unwrap_token0: bool,
+    max_token1_amount: Option<u64>,
     program_id: &Pubkey,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
+    // a multisig taker's signers are checked once they are read
     let taker = next_account_info(account_info_iter)?;
 
-    if !taker.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-
     // will pay from this
     let taker_token1 = next_account_info(account_info_iter)?;
 
@@ -122,105 +571,3096 @@ fn process_exchange(
     // as maker cannot change it anymore as our program is our owner now.
     // (would be an issue if we implemented a cancel instruction for maker)
     let pda_tmp_token0 = next_account_info(account_info_iter)?;
-    let pda_tmp_token0_data = TokenAccount::unpack(&pda_tmp_token0.data.borrow())?;
-
-    // this prevents front-running by maker changing the amounts
-    if taker_token0_expected_amount != pda_tmp_token0_data.amount {
-        return Err(EscrowError::ExpectedAmountMismatch.into());
-    }
+    let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
 
     let maker = next_account_info(account_info_iter)?;
     let maker_token1 = next_account_info(account_info_iter)?;
     let escrow_account = next_account_info(account_info_iter)?;
+    // a refill is pulled through the PDA's delegation, only an escrow the program wrote may
+    // name the funding account it is pulled from
+    if escrow_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
-    let escrow_info = Escrow::unpack(&escrow_account.data.borrow())?;
+    let escrow_data = escrow_account.data.borrow();
+    let escrow_info = PodEscrow::load(&escrow_data)?;
     // validate the provided account args against the one stored in the escrow info
-    if escrow_info.tmp_token0_pubkey != *pda_tmp_token0.key {
+    if escrow_info.header.tmp_token0_pubkey != pda_tmp_token0.key.to_bytes() {
         return Err(ProgramError::InvalidAccountData);
     }
-    if escrow_info.maker_pubkey != *maker.key {
+    if escrow_info.header.maker_pubkey != maker.key.to_bytes() {
         return Err(ProgramError::InvalidAccountData);
     }
-    if escrow_info.maker_token1_pubkey != *maker_token1.key {
+    if escrow_info.header.maker_token1_pubkey != maker_token1.key.to_bytes() {
         return Err(ProgramError::InvalidAccountData);
     }
+    check_not_expired(escrow_info.expires_at)?;
 
     let token_program = next_account_info(account_info_iter)?;
+    token::check_token_program(token_program)?;
 
-    let transfer_token1_to_maker = spl_token::instruction::transfer(
-        token_program.key,
-        taker_token1.key,
-        maker_token1.key,
-        taker.key,
-        &[&taker.key],
-        escrow_info.maker_token1_expected_amount,
-    )?;
-    msg!("Calling the token program to transfer token1s to the maker...");
-    invoke(
-        &transfer_token1_to_maker,
-        &[
-            taker_token1.clone(),
-            maker_token1.clone(),
-            // taker is the data.owner & signer and signer exctension allows us to send on their behalf
-            taker.clone(),
-            token_program.clone(),
-        ],
+    let (pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    let config = unpack_config(next_account_info(account_info_iter)?, program_id)?;
+    check_not_paused(&config)?;
+    let taker_signers = next_authority_signers(account_info_iter, taker)?;
+    // a multisig can neither pay for wrapping SOL nor receive unwrapped SOL
+    if !taker_signers.is_empty() && (wrap_token1 || unwrap_token0) {
+        return Err(EscrowError::UnsupportedEscrowFeature.into());
+    }
+    if let Some(required_issuer) = escrow_info.required_issuer {
+        let attestation =
+            next_account_info(account_info_iter).map_err(|_| EscrowError::AttestationRequired)?;
+        check_attestation(attestation, &required_issuer, taker.key, program_id)?;
+    }
+
+    // a non-custodial escrow offers its delegated amount, which the maker may have revoked since
+    let amount0 = match escrow_info.delegated_amount {
+        Some(delegated_amount) => {
+            check_delegation(&pda_tmp_token0_data, &pda, delegated_amount)?;
+            delegated_amount
+        }
+        None => pda_tmp_token0_data.amount,
+    };
+
+    // this prevents front-running by maker changing the amounts
+    if taker_token0_expected_amount != amount0 {
+        return Err(EscrowError::ExpectedAmountMismatch.into());
+    }
+
+    // Token-2022 escrows are plain, their mints take the place of the basket and extra asks
+    let (mint0, mint1) = if token::is_token_2022(token_program) {
+        let mint0 = next_account_info(account_info_iter)?;
+        let mint1 = next_account_info(account_info_iter)?;
+        token::check_recipient_extensions(taker_token0)?;
+        token::check_recipient_extensions(maker_token1)?;
+        (Some(mint0), Some(mint1))
+    } else {
+        (None, None)
+    };
+
+    if unwrap_token0 {
+        check_native_account(token_program, taker_token0)?;
+    }
+
+    for basket_vault_pubkey in escrow_info.basket_vault_pubkeys.iter() {
+        let pda_basket_vault = next_account_info(account_info_iter)?;
+        if *basket_vault_pubkey != pda_basket_vault.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // the mint of the taker's account is checked by the token program on transfer
+        let taker_basket_token = next_account_info(account_info_iter)?;
+
+        let pda_basket_vault_data = token::unpack_token_account(pda_basket_vault)?;
+        msg!("Calling the token program to transfer basket tokens to the taker...");
+        transfer_from_vault(
+            token_program,
+            pda_basket_vault,
+            None,
+            taker_basket_token,
+            pda_account,
+            bump_seed,
+            pda_basket_vault_data.amount,
+        )?;
+
+        close_vault(
+            token_program,
+            pda_basket_vault,
+            maker,
+            pda_account,
+            bump_seed,
+        )?;
+    }
+
+    for ask in escrow_info.extra_asks.iter() {
+        // the mint is checked by the token program, the recipient's was recorded on init
+        let taker_ask_token = next_account_info(account_info_iter)?;
+        let maker_ask_token = next_account_info(account_info_iter)?;
+        if ask.recipient_pubkey != maker_ask_token.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        msg!("Calling the token program to transfer asked tokens to the maker...");
+        transfer_from_owner(
+            token_program,
+            taker_ask_token,
+            None,
+            maker_ask_token,
+            taker,
+            &taker_signers,
+            ask.amount.into(),
+        )?;
+    }
+
+    delist_escrow(
+        account_info_iter,
+        escrow_info.market_pubkey,
+        escrow_account.key,
     )?;
 
-    let (pda, bump_seed) = Pubkey::find_program_address(&[b"escrow"], program_id);
-    let pda_account = next_account_info(account_info_iter)?;
+    // the referrer's cut is paid by the taker on top of the price, the maker receives the same either way
+    let referral = match referral_fee_bps {
+        Some(referral_fee_bps) => {
+            if referral_fee_bps > config.max_referral_fee_bps {
+                return Err(EscrowError::ReferralFeeTooHigh.into());
+            }
+            let referrer_token1_account = next_account_info(account_info_iter)?;
+            token::check_recipient_extensions(referrer_token1_account)?;
+            Some((referrer_token1_account, referral_fee_bps))
+        }
+        None => None,
+    };
 
-    let transfer_token0_to_taker = spl_token::instruction::transfer(
-        token_program.key,
-        pda_tmp_token0.key,
-        taker_token0.key,
-        &pda,
-        // we can sign as pda as we created pda
-        &[&pda],
-        pda_tmp_token0_data.amount,
+    let system_program = if wrap_token1 {
+        Some(next_account_info(account_info_iter)?)
+    } else {
+        None
+    };
+
+    // an oracle-priced escrow asks for the feed's current price, but at least the maker's minimum
+    let amount1 = match escrow_info.oracle_quote {
+        Some(oracle_quote) => {
+            let price_feed = next_account_info(account_info_iter)?;
+            let amount1 = oracle::quote_amount1(
+                price_feed,
+                &oracle_quote,
+                amount0,
+                Clock::get()?.unix_timestamp,
+            )?;
+            if amount1 < escrow_info.header.maker_token1_expected_amount.into() {
+                return Err(EscrowError::PriceBelowMinimum.into());
+            }
+            amount1
+        }
+        None => escrow_info.header.maker_token1_expected_amount.into(),
+    };
+    let referral_fee = referral.map_or(0, |(_, referral_fee_bps)| {
+        (amount1 as u128 * referral_fee_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    });
+    let total_amount1 = amount1
+        .checked_add(referral_fee)
+        .ok_or(EscrowError::AmountOverflow)?;
+    if let Some(max_token1_amount) = max_token1_amount {
+        if total_amount1 > max_token1_amount {
+            return Err(EscrowError::SpendingCapExceeded.into());
+        }
+    }
+
+    if let Some(system_program) = system_program {
+        check_native_account(token_program, taker_token1)?;
+        msg!("Wrapping the taker's SOL into taker_token1...");
+        wrap_sol(
+            system_program,
+            token_program,
+            taker,
+            taker_token1,
+            total_amount1,
+        )?;
+    }
+
+    let mut referrer_token1 = None;
+    if let Some((referrer_token1_account, _)) = referral {
+        if referral_fee > 0 {
+            msg!("Calling the token program to transfer the referral fee to the referrer...");
+            transfer_from_owner(
+                token_program,
+                taker_token1,
+                mint1,
+                referrer_token1_account,
+                taker,
+                &taker_signers,
+                referral_fee,
+            )?;
+        }
+        referrer_token1 = Some(*referrer_token1_account.key);
+    }
+
+    let is_delegated = escrow_info.delegated_amount.is_some();
+    let unwrap_token1 = escrow_info.unwrap_token1;
+    let refill = escrow_info.refill.map(|refill| Refill::from(*refill));
+    drop(escrow_data);
+
+    // a standing order pulls its next tranche into the emptied vault and stays open until the
+    // cap, the delegation or the funding account's balance runs out
+    let refill_from = match refill {
+        Some(refill) => {
+            let funding = next_account_info(account_info_iter)?;
+            if refill.funding_pubkey != *funding.key {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            let can_refill = refill.remaining_amount >= refill.tranche_amount
+                && check_delegation(
+                    &token::unpack_token_account(funding)?,
+                    &pda,
+                    refill.tranche_amount,
+                )
+                .is_ok();
+            if can_refill {
+                Some((funding, refill))
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+
+    // maker paid for the rent, refund them. A non-custodial escrow's account stays the maker's,
+    // a refilled standing order keeps its vault and stays open
+    settle_escrow(
+        &Settlement {
+            escrow_account,
+            maker,
+            taker,
+            vault0: pda_tmp_token0,
+            mint0,
+            taker_token0,
+            amount0,
+            token1_source: Token1Source::Owner {
+                account: taker_token1,
+                signers: &taker_signers,
+            },
+            mint1,
+            maker_token1,
+            amount1,
+            referrer_token1,
+            referral_fee,
+            close_vault0: !is_delegated && refill_from.is_none(),
+            close_escrow: refill_from.is_none(),
+        },
+        token_program,
+        pda_account,
+        bump_seed,
     )?;
+
+    if unwrap_token0 {
+        msg!("Calling the token program to unwrap the taker's SOL...");
+        unwrap_sol(token_program, taker_token0, taker)?;
+    }
+    if unwrap_token1 {
+        msg!("Calling the token program to unwrap the maker's SOL...");
+        close_vault(token_program, maker_token1, maker, pda_account, bump_seed)?;
+    }
+
+    if let Some((funding, refill)) = refill_from {
+        msg!("Calling the token program to refill the vault from the funding account...");
+        transfer_from_vault(
+            token_program,
+            funding,
+            None,
+            pda_tmp_token0,
+            pda_account,
+            bump_seed,
+            refill.tranche_amount,
+        )?;
+        // only the remaining amount changes, the rest of the escrow stays as it is
+        let mut escrow_data = escrow_account.data.borrow_mut();
+        if let Some(pod_refill) = PodEscrow::load_refill_mut(&mut escrow_data)? {
+            pod_refill.remaining_amount = (refill.remaining_amount - refill.tranche_amount).into();
+        }
+    }
+
+    Ok(())
+}
+
+/// Where the token1s an escrow is settled for come from
+enum Token1Source<'a, 'b> {
+    /// the taker's account, the taker or its multisig signers authorize the transfer
+    Owner {
+        account: &'a AccountInfo<'b>,
+        signers: &'a [AccountInfo<'b>],
+    },
+    /// a counter-offer's vault, closed to the taker once emptied
+    Vault(&'a AccountInfo<'b>),
+}
+
+/// The legs an Exchange and an accepted counter-offer settle alike
+struct Settlement<'a, 'b> {
+    escrow_account: &'a AccountInfo<'b>,
+    maker: &'a AccountInfo<'b>,
+    taker: &'a AccountInfo<'b>,
+    /// the escrow's vault, or the maker's account a non-custodial escrow holds a delegation of
+    vault0: &'a AccountInfo<'b>,
+    mint0: Option<&'a AccountInfo<'b>>,
+    taker_token0: &'a AccountInfo<'b>,
+    amount0: u64,
+    token1_source: Token1Source<'a, 'b>,
+    mint1: Option<&'a AccountInfo<'b>>,
+    maker_token1: &'a AccountInfo<'b>,
+    amount1: u64,
+    referrer_token1: Option<Pubkey>,
+    referral_fee: u64,
+    close_vault0: bool,
+    close_escrow: bool,
+}
+
+/// Pays token0s out of the vault to the taker and token1s to the maker, logs the trade and
+/// closes what the settlement says to, its rent goes back to the maker
+fn settle_escrow<'a>(
+    settlement: &Settlement<'_, 'a>,
+    token_program: &AccountInfo<'a>,
+    pda_account: &AccountInfo<'a>,
+    bump_seed: u8,
+) -> ProgramResult {
     msg!("Calling the token program to transfer token0s to the taker...");
-    invoke_signed(
-        &transfer_token0_to_taker,
-        &[
-            pda_tmp_token0.clone(),
-            taker_token0.clone(),
-            pda_account.clone(),
-            token_program.clone(),
-        ],
-        &[&[&b"escrow"[..], &[bump_seed]]],
+    transfer_from_vault(
+        token_program,
+        settlement.vault0,
+        settlement.mint0,
+        settlement.taker_token0,
+        pda_account,
+        bump_seed,
+        settlement.amount0,
     )?;
+    if settlement.close_vault0 {
+        close_vault(
+            token_program,
+            settlement.vault0,
+            settlement.maker,
+            pda_account,
+            bump_seed,
+        )?;
+    }
 
-    let close_pda_tmp_token0 = spl_token::instruction::close_account(
-        token_program.key,
-        pda_tmp_token0.key,
-        // maker paid for the rent, refund them
-        maker.key,
-        &pda,
-        &[&pda],
+    msg!("Calling the token program to transfer token1s to the maker...");
+    match settlement.token1_source {
+        Token1Source::Owner { account, signers } => transfer_from_owner(
+            token_program,
+            account,
+            settlement.mint1,
+            settlement.maker_token1,
+            settlement.taker,
+            signers,
+            settlement.amount1,
+        )?,
+        Token1Source::Vault(vault) => {
+            transfer_from_vault(
+                token_program,
+                vault,
+                settlement.mint1,
+                settlement.maker_token1,
+                pda_account,
+                bump_seed,
+                settlement.amount1,
+            )?;
+            close_vault(
+                token_program,
+                vault,
+                settlement.taker,
+                pda_account,
+                bump_seed,
+            )?;
+        }
+    }
+
+    TradeEvent {
+        escrow: *settlement.escrow_account.key,
+        maker: *settlement.maker.key,
+        taker: *settlement.taker.key,
+        amount0: settlement.amount0,
+        amount1: settlement.amount1,
+        referrer_token1: settlement.referrer_token1,
+        referral_fee: settlement.referral_fee,
+    }
+    .emit();
+
+    if settlement.close_escrow {
+        msg!("Closing the escrow account...");
+        close_info_account(settlement.escrow_account, settlement.maker)?;
+    }
+
+    Ok(())
+}
+
+fn process_batch_exchange(
+    accounts: &[AccountInfo],
+    taker_token0_expected_amounts: &[u64],
+    max_total_amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let taker = next_account_info(account_info_iter)?;
+
+    if !taker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let taker_token1 = next_account_info(account_info_iter)?;
+    let taker_token0 = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+    check_not_paused(&unpack_config(
+        next_account_info(account_info_iter)?,
+        program_id,
+    )?)?;
+
+    // any failing escrow fails the whole transaction, so it's all or nothing
+    let mut total_amount: u64 = 0;
+    for taker_token0_expected_amount in taker_token0_expected_amounts {
+        let pda_tmp_token0 = next_account_info(account_info_iter)?;
+        let maker = next_account_info(account_info_iter)?;
+        let maker_token1 = next_account_info(account_info_iter)?;
+        let escrow_account = next_account_info(account_info_iter)?;
+        if escrow_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
+        if *taker_token0_expected_amount != pda_tmp_token0_data.amount {
+            return Err(EscrowError::ExpectedAmountMismatch.into());
+        }
+
+        let (maker_token1_expected_amount, market_pubkey) = {
+            let escrow_data = escrow_account.data.borrow();
+            let escrow_info = PodEscrow::load(&escrow_data)?;
+            if escrow_info.header.tmp_token0_pubkey != pda_tmp_token0.key.to_bytes() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            if escrow_info.header.maker_pubkey != maker.key.to_bytes() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            if escrow_info.header.maker_token1_pubkey != maker_token1.key.to_bytes() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            if !escrow_info.is_plain() {
+                return Err(EscrowError::UnsupportedEscrowFeature.into());
+            }
+            check_not_expired(escrow_info.expires_at)?;
+            (
+                u64::from(escrow_info.header.maker_token1_expected_amount),
+                escrow_info.market_pubkey,
+            )
+        };
+        delist_escrow(account_info_iter, market_pubkey, escrow_account.key)?;
+
+        total_amount = total_amount
+            .checked_add(maker_token1_expected_amount)
+            .ok_or(EscrowError::AmountOverflow)?;
+        if total_amount > max_total_amount {
+            return Err(EscrowError::SpendingCapExceeded.into());
+        }
+
+        transfer_from_owner(
+            token_program,
+            taker_token1,
+            None,
+            maker_token1,
+            taker,
+            &[],
+            maker_token1_expected_amount,
+        )?;
+        transfer_from_vault(
+            token_program,
+            pda_tmp_token0,
+            None,
+            taker_token0,
+            pda_account,
+            bump_seed,
+            pda_tmp_token0_data.amount,
+        )?;
+        close_vault(token_program, pda_tmp_token0, maker, pda_account, bump_seed)?;
+        close_info_account(escrow_account, maker)?;
+    }
+    msg!(
+        "Settled {} escrows for {} token1s",
+        taker_token0_expected_amounts.len(),
+        total_amount
+    );
+
+    Ok(())
+}
+
+/// One of the two escrows settled by a Match
+struct MatchSide<'a, 'b> {
+    escrow_account: &'a AccountInfo<'b>,
+    vault: &'a AccountInfo<'b>,
+    vault_data: TokenAccount,
+    maker: &'a AccountInfo<'b>,
+    maker_token1: &'a AccountInfo<'b>,
+    maker_token1_mint: Pubkey,
+    maker_token1_expected_amount: u64,
+    market_pubkey: Option<Pubkey>,
+}
+
+fn next_match_side<'a, 'b, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    account_info_iter: &mut I,
+    program_id: &Pubkey,
+) -> Result<MatchSide<'a, 'b>, ProgramError> {
+    let escrow_account = next_account_info(account_info_iter)?;
+    if escrow_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let vault = next_account_info(account_info_iter)?;
+    let maker = next_account_info(account_info_iter)?;
+    let maker_token1 = next_account_info(account_info_iter)?;
+
+    let escrow_data = escrow_account.data.borrow();
+    let escrow_info = PodEscrow::load(&escrow_data)?;
+    if escrow_info.header.tmp_token0_pubkey != vault.key.to_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.header.maker_pubkey != maker.key.to_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.header.maker_token1_pubkey != maker_token1.key.to_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if !escrow_info.is_plain() {
+        return Err(EscrowError::UnsupportedEscrowFeature.into());
+    }
+    check_not_expired(escrow_info.expires_at)?;
+
+    let vault_data = token::unpack_token_account(vault)?;
+    let maker_token1_mint = token::unpack_token_account(maker_token1)?.mint;
+    Ok(MatchSide {
+        escrow_account,
+        vault,
+        vault_data,
+        maker,
+        maker_token1,
+        maker_token1_mint,
+        maker_token1_expected_amount: escrow_info.header.maker_token1_expected_amount.into(),
+        market_pubkey: escrow_info.market_pubkey,
+    })
+}
+
+fn process_match(
+    accounts: &[AccountInfo],
+    take_surplus: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let a = next_match_side(account_info_iter, program_id)?;
+    let b = next_match_side(account_info_iter, program_id)?;
+    if a.escrow_account.key == b.escrow_account.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // each vault must hold at least what the other maker asks for, in the mint they ask for
+    if a.vault_data.mint != b.maker_token1_mint || b.vault_data.mint != a.maker_token1_mint {
+        return Err(EscrowError::EscrowsNotCrossed.into());
+    }
+    let surplus_a = a
+        .vault_data
+        .amount
+        .checked_sub(b.maker_token1_expected_amount)
+        .ok_or(EscrowError::EscrowsNotCrossed)?;
+    let surplus_b = b
+        .vault_data
+        .amount
+        .checked_sub(a.maker_token1_expected_amount)
+        .ok_or(EscrowError::EscrowsNotCrossed)?;
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+    check_not_paused(&unpack_config(
+        next_account_info(account_info_iter)?,
+        program_id,
+    )?)?;
+
+    // without a cranker taking it, each maker receives the other's whole vault
+    let (amount_to_b, amount_to_a) = if take_surplus {
+        let cranker_token_a = next_account_info(account_info_iter)?;
+        let cranker_token_b = next_account_info(account_info_iter)?;
+        msg!("Calling the token program to pay the surplus to the cranker...");
+        if surplus_a > 0 {
+            transfer_from_vault(
+                token_program,
+                a.vault,
+                None,
+                cranker_token_a,
+                pda_account,
+                bump_seed,
+                surplus_a,
+            )?;
+        }
+        if surplus_b > 0 {
+            transfer_from_vault(
+                token_program,
+                b.vault,
+                None,
+                cranker_token_b,
+                pda_account,
+                bump_seed,
+                surplus_b,
+            )?;
+        }
+        (
+            b.maker_token1_expected_amount,
+            a.maker_token1_expected_amount,
+        )
+    } else {
+        (a.vault_data.amount, b.vault_data.amount)
+    };
+
+    msg!("Calling the token program to settle the crossed escrows...");
+    transfer_from_vault(
+        token_program,
+        a.vault,
+        None,
+        b.maker_token1,
+        pda_account,
+        bump_seed,
+        amount_to_b,
     )?;
-    msg!("Calling the token program to close pda's temp account...");
-    invoke_signed(
-        &close_pda_tmp_token0,
-        &[
-            pda_tmp_token0.clone(),
-            maker.clone(),
-            pda_account.clone(),
-            token_program.clone(),
-        ],
-        &[&[&b"escrow"[..], &[bump_seed]]],
+    transfer_from_vault(
+        token_program,
+        b.vault,
+        None,
+        a.maker_token1,
+        pda_account,
+        bump_seed,
+        amount_to_a,
     )?;
 
-    msg!("Closing the escrow account...");
-    // we can directly write to maker's account as long as we only increase the SOL balance
-    **maker.lamports.borrow_mut() = maker
+    for side in [&a, &b].iter() {
+        close_vault(
+            token_program,
+            side.vault,
+            side.maker,
+            pda_account,
+            bump_seed,
+        )?;
+        close_info_account(side.escrow_account, side.maker)?;
+    }
+    // the markets follow the optional cranker accounts
+    delist_escrow(account_info_iter, a.market_pubkey, a.escrow_account.key)?;
+    delist_escrow(account_info_iter, b.market_pubkey, b.escrow_account.key)?;
+
+    Ok(())
+}
+
+fn process_init_market(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let market_account = next_account_info(account_info_iter)?;
+    let mint0 = next_account_info(account_info_iter)?;
+    let mint1 = next_account_info(account_info_iter)?;
+    if !token::is_token_program(mint0.owner) || !token::is_token_program(mint1.owner) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if mint0.key == mint1.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let (market_pubkey, bump_seed) = Market::find_address(mint0.key, mint1.key, program_id);
+    if market_pubkey != *market_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if market_account.owner == program_id {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    msg!("Calling the system program to create the market account...");
+    create_pda_account(
+        payer,
+        market_account,
+        system_program,
+        rent,
+        Market::LEN,
+        program_id,
+        &[
+            MARKET_SEED,
+            mint0.key.as_ref(),
+            mint1.key.as_ref(),
+            &[bump_seed],
+        ],
+    )?;
+
+    let market = Market {
+        is_initialized: true,
+        mint0: *mint0.key,
+        mint1: *mint1.key,
+        entries: Vec::new(),
+    };
+    Market::pack(market, &mut market_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_init_config(
+    accounts: &[AccountInfo],
+    max_referral_fee_bps: u16,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let config_account = next_account_info(account_info_iter)?;
+    let (config_pubkey, bump_seed) = Config::find_address(program_id);
+    if config_pubkey != *config_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if config_account.owner == program_id {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let system_program = next_account_info(account_info_iter)?;
+    // otherwise whoever sends InitConfig first after the deployment would own the config
+    check_upgrade_authority(next_account_info(account_info_iter)?, admin.key, program_id)?;
+
+    msg!("Calling the system program to create the config account...");
+    create_pda_account(
+        admin,
+        config_account,
+        system_program,
+        rent,
+        Config::LEN,
+        program_id,
+        &[CONFIG_SEED, &[bump_seed]],
+    )?;
+
+    let config = Config {
+        is_initialized: true,
+        admin_pubkey: *admin.key,
+        max_referral_fee_bps,
+        is_paused: false,
+    };
+    Config::pack(config, &mut config_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_referral_fee_cap(
+    accounts: &[AccountInfo],
+    max_referral_fee_bps: u16,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let config_account = next_account_info(account_info_iter)?;
+    let mut config = unpack_config(config_account, program_id)?;
+    if !config.is_initialized() || config.admin_pubkey != *admin.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    config.max_referral_fee_bps = max_referral_fee_bps;
+    Config::pack(config, &mut config_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_paused(
+    accounts: &[AccountInfo],
+    is_paused: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let config_account = next_account_info(account_info_iter)?;
+    let mut config = unpack_config(config_account, program_id)?;
+    if !config.is_initialized() || config.admin_pubkey != *admin.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    config.is_paused = is_paused;
+    Config::pack(config, &mut config_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_set_admin(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let config_account = next_account_info(account_info_iter)?;
+    let mut config = unpack_config(config_account, program_id)?;
+    if !config.is_initialized() || config.admin_pubkey != *admin.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // the new admin signs too, so the role can't be handed to an account nobody controls
+    let new_admin = next_account_info(account_info_iter)?;
+    if !new_admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    config.admin_pubkey = *new_admin.key;
+    Config::pack(config, &mut config_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Fails unless authority is the upgrade authority in the escrow program's ProgramData account
+fn check_upgrade_authority(
+    program_data: &AccountInfo,
+    authority: &Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (program_data_pubkey, _bump_seed) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    if program_data_pubkey != *program_data.key
+        || *program_data.owner != bpf_loader_upgradeable::id()
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // UpgradeableLoaderState::ProgramData serialized with bincode: the u32 variant index 3, the
+    // u64 slot of the last deployment and the authority as an Option<Pubkey>
+    let data = program_data.data.borrow();
+    match data.get(..45) {
+        Some(header)
+            if header[..4] == 3u32.to_le_bytes()
+                && header[12] == 1
+                && header[13..] == authority.as_ref()[..] =>
+        {
+            Ok(())
+        }
+        _ => Err(EscrowError::NotUpgradeAuthority.into()),
+    }
+}
+
+/// Reads the admin and config accounts, failing unless admin is the config's admin and signed
+fn next_admin<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    account_info_iter: &mut I,
+    program_id: &Pubkey,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    let admin = next_account_info(account_info_iter)?;
+
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let config = unpack_config(next_account_info(account_info_iter)?, program_id)?;
+    if !config.is_initialized() || config.admin_pubkey != *admin.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(admin)
+}
+
+fn process_init_mint_policy(
+    accounts: &[AccountInfo],
+    mode: PolicyMode,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let admin = next_admin(account_info_iter, program_id)?;
+
+    let mint_policy_account = next_account_info(account_info_iter)?;
+    let (mint_policy_pubkey, bump_seed) = MintPolicy::find_address(program_id);
+    if mint_policy_pubkey != *mint_policy_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if mint_policy_account.owner == program_id {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    msg!("Calling the system program to create the mint policy account...");
+    create_pda_account(
+        admin,
+        mint_policy_account,
+        system_program,
+        rent,
+        MintPolicy::LEN,
+        program_id,
+        &[MINT_POLICY_SEED, &[bump_seed]],
+    )?;
+
+    let mint_policy = MintPolicy {
+        is_initialized: true,
+        mode,
+        mints: Vec::new(),
+    };
+    MintPolicy::pack(mint_policy, &mut mint_policy_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Adds a mint to the policy or, with is_removal, removes it
+fn process_update_mint_policy(
+    accounts: &[AccountInfo],
+    is_removal: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    next_admin(account_info_iter, program_id)?;
+
+    let mint_policy_account = next_account_info(account_info_iter)?;
+    let (mint_policy_pubkey, _bump_seed) = MintPolicy::find_address(program_id);
+    if mint_policy_pubkey != *mint_policy_account.key || mint_policy_account.owner != program_id {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let mut mint_policy_data = mint_policy_account.data.borrow_mut();
+    let mint_policy = PodMintPolicy::load_mut(&mut mint_policy_data)?;
+
+    let mint = next_account_info(account_info_iter)?;
+    let position = mint_policy
+        .mints()
+        .iter()
+        .position(|listed| *listed == mint.key.to_bytes());
+    match (position, is_removal) {
+        (None, false) => {
+            token::unpack_mint(mint)?;
+            mint_policy.push(mint.key)?;
+        }
+        (Some(position), true) => mint_policy.remove(position),
+        // listing a mint twice or removing one that isn't listed
+        _ => return Err(ProgramError::InvalidArgument),
+    }
+
+    Ok(())
+}
+
+fn process_issue_attestation(
+    accounts: &[AccountInfo],
+    expires_at: UnixTimestamp,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let issuer = next_account_info(account_info_iter)?;
+    if !issuer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let subject = next_account_info(account_info_iter)?;
+
+    let attestation_account = next_account_info(account_info_iter)?;
+    let (attestation_pubkey, bump_seed) =
+        Attestation::find_address(issuer.key, subject.key, program_id);
+    if attestation_pubkey != *attestation_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // an existing attestation of the subject is renewed
+    if attestation_account.owner == program_id {
+        let mut attestation = Attestation::unpack(&attestation_account.data.borrow())?;
+        attestation.expires_at = expires_at;
+        Attestation::pack(attestation, &mut attestation_account.data.borrow_mut())?;
+        return Ok(());
+    }
+
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    msg!("Calling the system program to create the attestation account...");
+    create_pda_account(
+        issuer,
+        attestation_account,
+        system_program,
+        rent,
+        Attestation::LEN,
+        program_id,
+        &[
+            ATTESTATION_SEED,
+            issuer.key.as_ref(),
+            subject.key.as_ref(),
+            &[bump_seed],
+        ],
+    )?;
+
+    let attestation = Attestation {
+        is_initialized: true,
+        issuer_pubkey: *issuer.key,
+        subject_pubkey: *subject.key,
+        expires_at,
+    };
+    Attestation::pack(attestation, &mut attestation_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_revoke_attestation(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let issuer = next_account_info(account_info_iter)?;
+    if !issuer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let attestation_account = next_account_info(account_info_iter)?;
+    if attestation_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let attestation = Attestation::unpack(&attestation_account.data.borrow())?;
+    if attestation.issuer_pubkey != *issuer.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    close_info_account(attestation_account, issuer)?;
+
+    Ok(())
+}
+
+/// Cancel by the maker or, with is_expiry, return of an expired escrow by anyone
+fn process_cancel_escrow(
+    accounts: &[AccountInfo],
+    is_expiry: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    // a multisig maker's signers are checked once they are read
+    let maker = next_account_info(account_info_iter)?;
+
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let maker_token0 = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    if escrow_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let escrow_data = escrow_account.data.borrow();
+    let escrow_info = PodEscrow::load(&escrow_data)?;
+    if escrow_info.header.tmp_token0_pubkey != pda_tmp_token0.key.to_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.header.maker_pubkey != maker.key.to_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+    let maker_signers = if is_expiry {
+        Vec::new()
+    } else {
+        next_authority_signers(account_info_iter, maker)?
+    };
+
+    // without the maker's signature the tokens may only go back to accounts the maker owns
+    let check_returned_to_maker = |maker_token: &AccountInfo| -> ProgramResult {
+        if is_expiry && token::unpack_token_account(maker_token)?.owner != *maker.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    };
+
+    if is_expiry {
+        let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+        match escrow_info.expires_at {
+            Some(expires_at) if clock.unix_timestamp >= expires_at => {}
+            _ => return Err(EscrowError::TimeoutNotExpired.into()),
+        }
+    }
+    check_returned_to_maker(maker_token0)?;
+
+    // Token-2022 escrows are plain, their mint takes the place of the basket
+    let mint0 = if token::is_token_2022(token_program) {
+        token::check_recipient_extensions(maker_token0)?;
+        Some(next_account_info(account_info_iter)?)
+    } else {
+        None
+    };
+
+    let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
+    match escrow_info.delegated_amount {
+        // the tokens never left the maker, only the PDA's approval for them is taken back
+        Some(delegated_amount) => {
+            if !is_expiry {
+                reduce_pda_approval(
+                    token_program,
+                    pda_tmp_token0,
+                    &pda_tmp_token0_data,
+                    maker,
+                    &maker_signers,
+                    delegated_amount,
+                    program_id,
+                )?;
+            }
+        }
+        None => {
+            msg!("Calling the token program to return token0s to the maker...");
+            transfer_from_vault(
+                token_program,
+                pda_tmp_token0,
+                mint0,
+                maker_token0,
+                pda_account,
+                bump_seed,
+                pda_tmp_token0_data.amount,
+            )?;
+            close_vault(token_program, pda_tmp_token0, maker, pda_account, bump_seed)?;
+        }
+    }
+
+    for basket_vault_pubkey in escrow_info.basket_vault_pubkeys.iter() {
+        let pda_basket_vault = next_account_info(account_info_iter)?;
+        if *basket_vault_pubkey != pda_basket_vault.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let maker_basket_token = next_account_info(account_info_iter)?;
+        check_returned_to_maker(maker_basket_token)?;
+
+        let pda_basket_vault_data = token::unpack_token_account(pda_basket_vault)?;
+        msg!("Calling the token program to return basket tokens to the maker...");
+        transfer_from_vault(
+            token_program,
+            pda_basket_vault,
+            None,
+            maker_basket_token,
+            pda_account,
+            bump_seed,
+            pda_basket_vault_data.amount,
+        )?;
+        close_vault(
+            token_program,
+            pda_basket_vault,
+            maker,
+            pda_account,
+            bump_seed,
+        )?;
+    }
+
+    if escrow_info.unwrap_token1 {
+        let maker_token1 = next_account_info(account_info_iter)?;
+        if escrow_info.header.maker_token1_pubkey != maker_token1.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // anything sent to it meanwhile goes to the maker as SOL too
+        msg!("Calling the token program to close maker_token1 to the maker...");
+        close_vault(token_program, maker_token1, maker, pda_account, bump_seed)?;
+    }
+
+    delist_escrow(
+        account_info_iter,
+        escrow_info.market_pubkey,
+        escrow_account.key,
+    )?;
+
+    // the approval for the tranches not pulled yet is taken back as well. An expiry has no maker
+    // to sign for it, the maker revokes what is left of the approval themselves
+    if let (Some(refill), false) = (escrow_info.refill, is_expiry) {
+        let funding = next_account_info(account_info_iter)?;
+        if refill.funding_pubkey != funding.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        reduce_pda_approval(
+            token_program,
+            funding,
+            &token::unpack_token_account(funding)?,
+            maker,
+            &maker_signers,
+            refill.remaining_amount.into(),
+            program_id,
+        )?;
+    }
+    drop(escrow_data);
+
+    msg!("Closing the escrow account...");
+    close_info_account(escrow_account, maker)?;
+
+    Ok(())
+}
+
+fn process_init_htlc(
+    accounts: &[AccountInfo],
+    hash_kind: HashKind,
+    hashlock: [u8; 32],
+    timeout: i64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+
+    if !maker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let tmp_token0 = next_account_info(account_info_iter)?;
+
+    let recipient_token0 = next_account_info(account_info_iter)?;
+    if *recipient_token0.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let htlc_info_account = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+
+    if !rent.is_exempt(htlc_info_account.lamports(), htlc_info_account.data_len()) {
+        return Err(EscrowError::NotRentExempt.into());
+    }
+
+    let mut htlc_info = Htlc::unpack_unchecked(&htlc_info_account.data.borrow())?;
+    if htlc_info.is_initialized() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    htlc_info.is_initialized = true;
+    htlc_info.maker_pubkey = *maker.key;
+    htlc_info.tmp_token0_pubkey = *tmp_token0.key;
+    htlc_info.recipient_token0_pubkey = *recipient_token0.key;
+    htlc_info.hash_kind = hash_kind;
+    htlc_info.hashlock = hashlock;
+    htlc_info.timeout = timeout;
+
+    Htlc::pack(htlc_info, &mut htlc_info_account.data.borrow_mut())?;
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    hand_over_vault(token_program, tmp_token0, maker, &[], program_id)?;
+
+    Ok(())
+}
+
+fn process_claim_htlc(
+    accounts: &[AccountInfo],
+    preimage: &[u8],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let recipient_token0 = next_account_info(account_info_iter)?;
+    let maker = next_account_info(account_info_iter)?;
+    let htlc_info_account = next_account_info(account_info_iter)?;
+    if htlc_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let htlc_info = Htlc::unpack(&htlc_info_account.data.borrow())?;
+    if htlc_info.tmp_token0_pubkey != *pda_tmp_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if htlc_info.recipient_token0_pubkey != *recipient_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if htlc_info.maker_pubkey != *maker.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    // the maker can refund from the timeout on, so the preimage must come strictly before it
+    if clock.unix_timestamp >= htlc_info.timeout {
+        return Err(EscrowError::TimeoutExpired.into());
+    }
+    if htlc_info.hash_kind.hash(preimage) != htlc_info.hashlock {
+        return Err(EscrowError::InvalidPreimage.into());
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
+    msg!("Calling the token program to transfer token0s to the recipient...");
+    transfer_from_vault(
+        token_program,
+        pda_tmp_token0,
+        None,
+        recipient_token0,
+        pda_account,
+        bump_seed,
+        pda_tmp_token0_data.amount,
+    )?;
+
+    close_vault(token_program, pda_tmp_token0, maker, pda_account, bump_seed)?;
+
+    msg!("Closing the htlc account...");
+    close_info_account(htlc_info_account, maker)?;
+
+    Ok(())
+}
+
+fn process_refund_htlc(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+
+    if !maker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let maker_token0 = next_account_info(account_info_iter)?;
+    let htlc_info_account = next_account_info(account_info_iter)?;
+    if htlc_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let htlc_info = Htlc::unpack(&htlc_info_account.data.borrow())?;
+    if htlc_info.tmp_token0_pubkey != *pda_tmp_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if htlc_info.maker_pubkey != *maker.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    if clock.unix_timestamp < htlc_info.timeout {
+        return Err(EscrowError::TimeoutNotExpired.into());
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
+    msg!("Calling the token program to transfer token0s back to the maker...");
+    transfer_from_vault(
+        token_program,
+        pda_tmp_token0,
+        None,
+        maker_token0,
+        pda_account,
+        bump_seed,
+        pda_tmp_token0_data.amount,
+    )?;
+
+    close_vault(token_program, pda_tmp_token0, maker, pda_account, bump_seed)?;
+
+    msg!("Closing the htlc account...");
+    close_info_account(htlc_info_account, maker)?;
+
+    Ok(())
+}
+
+fn process_init_arbiter_escrow(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let tmp_token0 = next_account_info(account_info_iter)?;
+
+    let payer_token0 = next_account_info(account_info_iter)?;
+    if *payer_token0.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let payee_token0 = next_account_info(account_info_iter)?;
+    if *payee_token0.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // the owner of the receiving account is the one allowed to refund
+    let payee_token0_data = token::unpack_token_account(payee_token0)?;
+
+    let arbiter = next_account_info(account_info_iter)?;
+
+    let escrow_info_account = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+
+    if !rent.is_exempt(
+        escrow_info_account.lamports(),
+        escrow_info_account.data_len(),
+    ) {
+        return Err(EscrowError::NotRentExempt.into());
+    }
+
+    let mut escrow_info = ArbiterEscrow::unpack_unchecked(&escrow_info_account.data.borrow())?;
+    if escrow_info.is_initialized() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    escrow_info.is_initialized = true;
+    escrow_info.payer_pubkey = *payer.key;
+    escrow_info.tmp_token0_pubkey = *tmp_token0.key;
+    escrow_info.payer_token0_pubkey = *payer_token0.key;
+    escrow_info.payee_pubkey = payee_token0_data.owner;
+    escrow_info.payee_token0_pubkey = *payee_token0.key;
+    escrow_info.arbiter_pubkey = *arbiter.key;
+    escrow_info.is_disputed = false;
+
+    ArbiterEscrow::pack(escrow_info, &mut escrow_info_account.data.borrow_mut())?;
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    hand_over_vault(token_program, tmp_token0, payer, &[], program_id)?;
+
+    Ok(())
+}
+
+fn process_release_to_payee(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let payee_token0 = next_account_info(account_info_iter)?;
+    let escrow_info_account = next_account_info(account_info_iter)?;
+    if escrow_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let escrow_info = ArbiterEscrow::unpack(&escrow_info_account.data.borrow())?;
+    if escrow_info.payer_pubkey != *payer.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.tmp_token0_pubkey != *pda_tmp_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.payee_token0_pubkey != *payee_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.is_disputed {
+        return Err(EscrowError::DisputeActive.into());
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
+    msg!("Calling the token program to transfer token0s to the payee...");
+    transfer_from_vault(
+        token_program,
+        pda_tmp_token0,
+        None,
+        payee_token0,
+        pda_account,
+        bump_seed,
+        pda_tmp_token0_data.amount,
+    )?;
+
+    close_vault(token_program, pda_tmp_token0, payer, pda_account, bump_seed)?;
+
+    msg!("Closing the escrow account...");
+    close_info_account(escrow_info_account, payer)?;
+
+    Ok(())
+}
+
+fn process_refund_to_payer(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payee = next_account_info(account_info_iter)?;
+
+    if !payee.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let payer_token0 = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let escrow_info_account = next_account_info(account_info_iter)?;
+    if escrow_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let escrow_info = ArbiterEscrow::unpack(&escrow_info_account.data.borrow())?;
+    if escrow_info.payee_pubkey != *payee.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.tmp_token0_pubkey != *pda_tmp_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.payer_token0_pubkey != *payer_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.payer_pubkey != *payer.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.is_disputed {
+        return Err(EscrowError::DisputeActive.into());
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
+    msg!("Calling the token program to transfer token0s back to the payer...");
+    transfer_from_vault(
+        token_program,
+        pda_tmp_token0,
+        None,
+        payer_token0,
+        pda_account,
+        bump_seed,
+        pda_tmp_token0_data.amount,
+    )?;
+
+    close_vault(token_program, pda_tmp_token0, payer, pda_account, bump_seed)?;
+
+    msg!("Closing the escrow account...");
+    close_info_account(escrow_info_account, payer)?;
+
+    Ok(())
+}
+
+fn process_raise_dispute(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let party = next_account_info(account_info_iter)?;
+
+    if !party.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let escrow_info_account = next_account_info(account_info_iter)?;
+    if escrow_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut escrow_info = ArbiterEscrow::unpack(&escrow_info_account.data.borrow())?;
+    if escrow_info.payer_pubkey != *party.key && escrow_info.payee_pubkey != *party.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.is_disputed {
+        return Err(EscrowError::DisputeActive.into());
+    }
+
+    escrow_info.is_disputed = true;
+    ArbiterEscrow::pack(escrow_info, &mut escrow_info_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+fn process_resolve_dispute(
+    accounts: &[AccountInfo],
+    payee_amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let arbiter = next_account_info(account_info_iter)?;
+
+    if !arbiter.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let payee_token0 = next_account_info(account_info_iter)?;
+    let payer_token0 = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let escrow_info_account = next_account_info(account_info_iter)?;
+    if escrow_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let escrow_info = ArbiterEscrow::unpack(&escrow_info_account.data.borrow())?;
+    if escrow_info.arbiter_pubkey != *arbiter.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.tmp_token0_pubkey != *pda_tmp_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.payee_token0_pubkey != *payee_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.payer_token0_pubkey != *payer_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.payer_pubkey != *payer.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if !escrow_info.is_disputed {
+        return Err(EscrowError::NoDispute.into());
+    }
+
+    let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
+    let payer_amount = pda_tmp_token0_data
+        .amount
+        .checked_sub(payee_amount)
+        .ok_or(EscrowError::InsufficientVaultBalance)?;
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    if payee_amount > 0 {
+        msg!("Calling the token program to transfer token0s to the payee...");
+        transfer_from_vault(
+            token_program,
+            pda_tmp_token0,
+            None,
+            payee_token0,
+            pda_account,
+            bump_seed,
+            payee_amount,
+        )?;
+    }
+    if payer_amount > 0 {
+        msg!("Calling the token program to transfer token0s back to the payer...");
+        transfer_from_vault(
+            token_program,
+            pda_tmp_token0,
+            None,
+            payer_token0,
+            pda_account,
+            bump_seed,
+            payer_amount,
+        )?;
+    }
+
+    close_vault(token_program, pda_tmp_token0, payer, pda_account, bump_seed)?;
+
+    msg!("Closing the escrow account...");
+    close_info_account(escrow_info_account, payer)?;
+
+    Ok(())
+}
+
+fn process_init_milestone_escrow(
+    accounts: &[AccountInfo],
+    amounts: &[u64],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // the vault must cover every tranche, no more no less
+    let tmp_token0 = next_account_info(account_info_iter)?;
+    let tmp_token0_data = token::unpack_token_account(tmp_token0)?;
+    let total = amounts
+        .iter()
+        .try_fold(0u64, |total, amount| total.checked_add(*amount))
+        .ok_or(EscrowError::AmountOverflow)?;
+    if total != tmp_token0_data.amount {
+        return Err(EscrowError::ExpectedAmountMismatch.into());
+    }
+
+    let payer_token0 = next_account_info(account_info_iter)?;
+    if *payer_token0.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let payee_token0 = next_account_info(account_info_iter)?;
+    if *payee_token0.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let escrow_info_account = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+
+    if !rent.is_exempt(
+        escrow_info_account.lamports(),
+        escrow_info_account.data_len(),
+    ) {
+        return Err(EscrowError::NotRentExempt.into());
+    }
+
+    let mut escrow_info = MilestoneEscrow::unpack_unchecked(&escrow_info_account.data.borrow())?;
+    if escrow_info.is_initialized() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    escrow_info.is_initialized = true;
+    escrow_info.payer_pubkey = *payer.key;
+    escrow_info.tmp_token0_pubkey = *tmp_token0.key;
+    escrow_info.payer_token0_pubkey = *payer_token0.key;
+    escrow_info.payee_token0_pubkey = *payee_token0.key;
+    escrow_info.milestone_count = amounts.len() as u8;
+    escrow_info.milestones = [Milestone::default(); MAX_MILESTONES];
+    for (milestone, amount) in escrow_info.milestones.iter_mut().zip(amounts) {
+        milestone.amount = *amount;
+    }
+
+    MilestoneEscrow::pack(escrow_info, &mut escrow_info_account.data.borrow_mut())?;
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    hand_over_vault(token_program, tmp_token0, payer, &[], program_id)?;
+
+    Ok(())
+}
+
+fn process_approve_milestone(
+    accounts: &[AccountInfo],
+    index: u8,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let payee_token0 = next_account_info(account_info_iter)?;
+    let escrow_info_account = next_account_info(account_info_iter)?;
+    if escrow_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // the approval is written in place, the account is only closed once the borrow ended
+    let (tranche, is_settled) = {
+        let mut escrow_data = escrow_info_account.data.borrow_mut();
+        let escrow_info = PodMilestoneEscrow::load_mut(&mut escrow_data)?;
+        if escrow_info.payer_pubkey != payer.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if escrow_info.tmp_token0_pubkey != pda_tmp_token0.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if escrow_info.payee_token0_pubkey != payee_token0.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let milestone_count = escrow_info.milestone_count;
+        let milestone = escrow_info
+            .milestones
+            .get_mut(index as usize)
+            .filter(|_| index < milestone_count)
+            .ok_or(EscrowError::InvalidMilestone)?;
+        if milestone.status != MilestoneStatus::Pending as u8 {
+            return Err(EscrowError::InvalidMilestone.into());
+        }
+        milestone.status = MilestoneStatus::Approved as u8;
+        (u64::from(milestone.amount), escrow_info.is_settled())
+    };
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    // the last approval sweeps the vault
+    let amount = if is_settled {
+        vault_balance(pda_tmp_token0)?
+    } else {
+        tranche
+    };
+    msg!("Calling the token program to transfer the tranche to the payee...");
+    transfer_from_vault(
+        token_program,
+        pda_tmp_token0,
+        None,
+        payee_token0,
+        pda_account,
+        bump_seed,
+        amount,
+    )?;
+
+    if is_settled {
+        close_vault(token_program, pda_tmp_token0, payer, pda_account, bump_seed)?;
+
+        msg!("Closing the escrow account...");
+        close_info_account(escrow_info_account, payer)?;
+    }
+
+    Ok(())
+}
+
+fn process_cancel_milestone_escrow(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let payer_token0 = next_account_info(account_info_iter)?;
+    let escrow_info_account = next_account_info(account_info_iter)?;
+    if escrow_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let escrow_info = MilestoneEscrow::unpack(&escrow_info_account.data.borrow())?;
+    if escrow_info.payer_pubkey != *payer.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.tmp_token0_pubkey != *pda_tmp_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if escrow_info.payer_token0_pubkey != *payer_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    // approved tranches already left the vault, what is left is the unapproved remainder
+    let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
+    if pda_tmp_token0_data.amount > 0 {
+        msg!("Calling the token program to transfer the remainder back to the payer...");
+        transfer_from_vault(
+            token_program,
+            pda_tmp_token0,
+            None,
+            payer_token0,
+            pda_account,
+            bump_seed,
+            pda_tmp_token0_data.amount,
+        )?;
+    }
+
+    close_vault(token_program, pda_tmp_token0, payer, pda_account, bump_seed)?;
+
+    msg!("Closing the escrow account...");
+    close_info_account(escrow_info_account, payer)?;
+
+    Ok(())
+}
+
+fn process_init_vesting(
+    accounts: &[AccountInfo],
+    start_ts: i64,
+    cliff_ts: i64,
+    end_ts: i64,
+    is_revocable: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let funder = next_account_info(account_info_iter)?;
+
+    if !funder.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if !(start_ts <= cliff_ts && cliff_ts <= end_ts && start_ts < end_ts) {
+        return Err(EscrowError::InvalidVestingSchedule.into());
+    }
+
+    let tmp_token0 = next_account_info(account_info_iter)?;
+    let tmp_token0_data = token::unpack_token_account(tmp_token0)?;
+
+    let funder_token0 = next_account_info(account_info_iter)?;
+    if *funder_token0.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let beneficiary_token0 = next_account_info(account_info_iter)?;
+    if *beneficiary_token0.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let vesting_info_account = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+
+    if !rent.is_exempt(
+        vesting_info_account.lamports(),
+        vesting_info_account.data_len(),
+    ) {
+        return Err(EscrowError::NotRentExempt.into());
+    }
+
+    let mut vesting_info = Vesting::unpack_unchecked(&vesting_info_account.data.borrow())?;
+    if vesting_info.is_initialized() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    vesting_info.is_initialized = true;
+    vesting_info.funder_pubkey = *funder.key;
+    vesting_info.tmp_token0_pubkey = *tmp_token0.key;
+    vesting_info.funder_token0_pubkey = *funder_token0.key;
+    vesting_info.beneficiary_token0_pubkey = *beneficiary_token0.key;
+    vesting_info.start_ts = start_ts;
+    vesting_info.cliff_ts = cliff_ts;
+    vesting_info.end_ts = end_ts;
+    vesting_info.total_amount = tmp_token0_data.amount;
+    vesting_info.withdrawn_amount = 0;
+    vesting_info.is_revocable = is_revocable;
+
+    Vesting::pack(vesting_info, &mut vesting_info_account.data.borrow_mut())?;
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    hand_over_vault(token_program, tmp_token0, funder, &[], program_id)?;
+
+    Ok(())
+}
+
+fn process_withdraw_vested(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let beneficiary_token0 = next_account_info(account_info_iter)?;
+    let funder = next_account_info(account_info_iter)?;
+    let vesting_info_account = next_account_info(account_info_iter)?;
+    if vesting_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let mut vesting_info = Vesting::unpack(&vesting_info_account.data.borrow())?;
+    if vesting_info.tmp_token0_pubkey != *pda_tmp_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if vesting_info.beneficiary_token0_pubkey != *beneficiary_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if vesting_info.funder_pubkey != *funder.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let withdrawable = vesting_info
+        .vested_amount(clock.unix_timestamp)
+        .saturating_sub(vesting_info.withdrawn_amount);
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    vesting_info.withdrawn_amount += withdrawable;
+    let is_complete = vesting_info.withdrawn_amount == vesting_info.total_amount;
+    // the last withdrawal sweeps the vault
+    let amount = if is_complete {
+        vault_balance(pda_tmp_token0)?
+    } else {
+        withdrawable
+    };
+    if amount > 0 {
+        msg!("Calling the token program to transfer vested token0s to the beneficiary...");
+        transfer_from_vault(
+            token_program,
+            pda_tmp_token0,
+            None,
+            beneficiary_token0,
+            pda_account,
+            bump_seed,
+            amount,
+        )?;
+    }
+
+    if is_complete {
+        close_vault(token_program, pda_tmp_token0, funder, pda_account, bump_seed)?;
+
+        msg!("Closing the vesting account...");
+        close_info_account(vesting_info_account, funder)?;
+    } else {
+        Vesting::pack(vesting_info, &mut vesting_info_account.data.borrow_mut())?;
+    }
+
+    Ok(())
+}
+
+fn process_revoke_vesting(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let funder = next_account_info(account_info_iter)?;
+
+    if !funder.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let beneficiary_token0 = next_account_info(account_info_iter)?;
+    let funder_token0 = next_account_info(account_info_iter)?;
+    let vesting_info_account = next_account_info(account_info_iter)?;
+    if vesting_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let vesting_info = Vesting::unpack(&vesting_info_account.data.borrow())?;
+    if vesting_info.funder_pubkey != *funder.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if vesting_info.tmp_token0_pubkey != *pda_tmp_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if vesting_info.beneficiary_token0_pubkey != *beneficiary_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if vesting_info.funder_token0_pubkey != *funder_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if !vesting_info.is_revocable {
+        return Err(EscrowError::NotRevocable.into());
+    }
+
+    let clock = &Clock::from_account_info(next_account_info(account_info_iter)?)?;
+    let vested = vesting_info.vested_amount(clock.unix_timestamp);
+    // what vested stays the beneficiary's, only the unvested portion goes back
+    let beneficiary_amount = vested.saturating_sub(vesting_info.withdrawn_amount);
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    // the funder gets the rest of the vault
+    let funder_amount = vault_balance(pda_tmp_token0)?.saturating_sub(beneficiary_amount);
+
+    if beneficiary_amount > 0 {
+        msg!("Calling the token program to transfer vested token0s to the beneficiary...");
+        transfer_from_vault(
+            token_program,
+            pda_tmp_token0,
+            None,
+            beneficiary_token0,
+            pda_account,
+            bump_seed,
+            beneficiary_amount,
+        )?;
+    }
+    if funder_amount > 0 {
+        msg!("Calling the token program to transfer unvested token0s back to the funder...");
+        transfer_from_vault(
+            token_program,
+            pda_tmp_token0,
+            None,
+            funder_token0,
+            pda_account,
+            bump_seed,
+            funder_amount,
+        )?;
+    }
+
+    close_vault(token_program, pda_tmp_token0, funder, pda_account, bump_seed)?;
+
+    msg!("Closing the vesting account...");
+    close_info_account(vesting_info_account, funder)?;
+
+    Ok(())
+}
+
+fn process_create_recipient_account(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = next_account_info(account_info_iter)?;
+    let associated_account = next_account_info(account_info_iter)?;
+    let wallet = next_account_info(account_info_iter)?;
+    let mint = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_token_program(token_program)?;
+    let associated_token_program = next_account_info(account_info_iter)?;
+    if *associated_token_program.key != token::associated_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let rent = next_account_info(account_info_iter)?;
+
+    let associated_pubkey =
+        token::get_associated_token_address(wallet.key, mint.key, token_program.key);
+    if associated_pubkey != *associated_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // creating it again would fail, so an existing account is only checked
+    if associated_account.owner == token_program.key {
+        let associated_account_data = token::unpack_token_account(associated_account)?;
+        if associated_account_data.mint != *mint.key || associated_account_data.owner != *wallet.key
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        return Ok(());
+    }
+
+    msg!("Calling the associated token account program to create the account...");
+    invoke(
+        &token::create_associated_token_account(payer.key, wallet.key, mint.key, token_program.key),
+        &[
+            payer.clone(),
+            associated_account.clone(),
+            wallet.clone(),
+            mint.clone(),
+            system_program.clone(),
+            token_program.clone(),
+            rent.clone(),
+            associated_token_program.clone(),
+        ],
+    )
+}
+
+fn process_propose_counter(
+    accounts: &[AccountInfo],
+    amount0: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let taker = next_account_info(account_info_iter)?;
+
+    if !taker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let tmp_token1 = next_account_info(account_info_iter)?;
+    let taker_token1 = next_account_info(account_info_iter)?;
+    let taker_token0 = next_account_info(account_info_iter)?;
+
+    let escrow_account = next_account_info(account_info_iter)?;
+    if escrow_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let escrow_data = escrow_account.data.borrow();
+    let escrow_info = PodEscrow::load(&escrow_data)?;
+    // the counter-offer replaces token1's amount, which only plain escrows have a single one of
+    if !escrow_info.is_plain() {
+        return Err(EscrowError::UnsupportedEscrowFeature.into());
+    }
+    check_not_expired(escrow_info.expires_at)?;
+
+    let counter_account = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    if !rent.is_exempt(counter_account.lamports(), counter_account.data_len()) {
+        return Err(EscrowError::NotRentExempt.into());
+    }
+    let counter = CounterOffer::unpack_unchecked(&counter_account.data.borrow())?;
+    if counter.is_initialized() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    check_not_paused(&unpack_config(
+        next_account_info(account_info_iter)?,
+        program_id,
+    )?)?;
+
+    let counter = CounterOffer {
+        is_initialized: true,
+        escrow_pubkey: *escrow_account.key,
+        taker_pubkey: *taker.key,
+        tmp_token1_pubkey: *tmp_token1.key,
+        taker_token0_pubkey: *taker_token0.key,
+        taker_token1_pubkey: *taker_token1.key,
+        amount0,
+        amount1: token::unpack_token_account(tmp_token1)?.amount,
+    };
+    CounterOffer::pack(counter, &mut counter_account.data.borrow_mut())?;
+
+    hand_over_vault(token_program, tmp_token1, taker, &[], program_id)
+}
+
+fn process_accept_counter(
+    accounts: &[AccountInfo],
+    maker_token1_expected_amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+
+    if !maker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pda_tmp_token0 = next_account_info(account_info_iter)?;
+    let maker_token1 = next_account_info(account_info_iter)?;
+    let escrow_account = next_account_info(account_info_iter)?;
+    if escrow_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let market_pubkey = {
+        let escrow_data = escrow_account.data.borrow();
+        let escrow_info = PodEscrow::load(&escrow_data)?;
+        if escrow_info.header.tmp_token0_pubkey != pda_tmp_token0.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if escrow_info.header.maker_pubkey != maker.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if escrow_info.header.maker_token1_pubkey != maker_token1.key.to_bytes() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        check_not_expired(escrow_info.expires_at)?;
+        escrow_info.market_pubkey
+    };
+
+    let counter_account = next_account_info(account_info_iter)?;
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pda_tmp_token1 = next_account_info(account_info_iter)?;
+    let taker_token0 = next_account_info(account_info_iter)?;
+    let taker = next_account_info(account_info_iter)?;
+
+    let counter = CounterOffer::unpack(&counter_account.data.borrow())?;
+    if counter.escrow_pubkey != *escrow_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if counter.tmp_token1_pubkey != *pda_tmp_token1.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if counter.taker_token0_pubkey != *taker_token0.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if counter.taker_pubkey != *taker.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+    check_not_paused(&unpack_config(
+        next_account_info(account_info_iter)?,
+        program_id,
+    )?)?;
+
+    // both sides are protected from the other changing the amounts
+    let pda_tmp_token0_data = token::unpack_token_account(pda_tmp_token0)?;
+    if pda_tmp_token0_data.amount != counter.amount0 {
+        return Err(EscrowError::ExpectedAmountMismatch.into());
+    }
+    let pda_tmp_token1_data = token::unpack_token_account(pda_tmp_token1)?;
+    if pda_tmp_token1_data.amount != maker_token1_expected_amount {
+        return Err(EscrowError::ExpectedAmountMismatch.into());
+    }
+
+    delist_escrow(account_info_iter, market_pubkey, escrow_account.key)?;
+
+    settle_escrow(
+        &Settlement {
+            escrow_account,
+            maker,
+            taker,
+            vault0: pda_tmp_token0,
+            mint0: None,
+            taker_token0,
+            amount0: pda_tmp_token0_data.amount,
+            token1_source: Token1Source::Vault(pda_tmp_token1),
+            mint1: None,
+            maker_token1,
+            amount1: pda_tmp_token1_data.amount,
+            referrer_token1: None,
+            referral_fee: 0,
+            close_vault0: true,
+            close_escrow: true,
+        },
+        token_program,
+        pda_account,
+        bump_seed,
+    )?;
+
+    msg!("Closing the counter-offer account...");
+    close_info_account(counter_account, taker)?;
+
+    Ok(())
+}
+
+fn process_reject_counter(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let maker = next_account_info(account_info_iter)?;
+
+    if !maker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let escrow_account = next_account_info(account_info_iter)?;
+    if escrow_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let escrow_data = escrow_account.data.borrow();
+    let escrow_info = PodEscrow::load(&escrow_data)?;
+    if escrow_info.header.maker_pubkey != maker.key.to_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let taker = next_account_info(account_info_iter)?;
+    let counter_account = next_account_info(account_info_iter)?;
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let counter = CounterOffer::unpack(&counter_account.data.borrow())?;
+    if counter.escrow_pubkey != *escrow_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    return_counter(
+        account_info_iter,
+        taker,
+        counter_account,
+        &counter,
+        program_id,
+    )
+}
+
+fn process_withdraw_counter(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let taker = next_account_info(account_info_iter)?;
+
+    if !taker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let counter_account = next_account_info(account_info_iter)?;
+    if counter_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let counter = CounterOffer::unpack(&counter_account.data.borrow())?;
+
+    return_counter(
+        account_info_iter,
+        taker,
+        counter_account,
+        &counter,
+        program_id,
+    )
+}
+
+/// Returns a counter-offer's token1 to the taker and closes it, the vault, taker_token1,
+/// token program and PDA are the next accounts
+fn return_counter<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    account_info_iter: &mut I,
+    taker: &'a AccountInfo<'b>,
+    counter_account: &'a AccountInfo<'b>,
+    counter: &CounterOffer,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if counter.taker_pubkey != *taker.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let pda_tmp_token1 = next_account_info(account_info_iter)?;
+    if counter.tmp_token1_pubkey != *pda_tmp_token1.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let taker_token1 = next_account_info(account_info_iter)?;
+    if counter.taker_token1_pubkey != *taker_token1.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    let pda_tmp_token1_data = token::unpack_token_account(pda_tmp_token1)?;
+    msg!("Calling the token program to return the counter-offer's token1s to the taker...");
+    transfer_from_vault(
+        token_program,
+        pda_tmp_token1,
+        None,
+        taker_token1,
+        pda_account,
+        bump_seed,
+        pda_tmp_token1_data.amount,
+    )?;
+    close_vault(token_program, pda_tmp_token1, taker, pda_account, bump_seed)?;
+
+    msg!("Closing the counter-offer account...");
+    close_info_account(counter_account, taker)
+}
+
+/// Locks the collateral in a vault of the PDA and mints the option's only token to the writer,
+/// no premium changes hands here
+fn process_write_option(
+    accounts: &[AccountInfo],
+    kind: OptionKind,
+    exercise_amount: u64,
+    expires_at: UnixTimestamp,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let writer = next_account_info(account_info_iter)?;
+
+    if !writer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let tmp_collateral = next_account_info(account_info_iter)?;
+    let writer_payment = next_account_info(account_info_iter)?;
+    if *writer_payment.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let option_mint = next_account_info(account_info_iter)?;
+    let writer_option_token = next_account_info(account_info_iter)?;
+
+    let option_info_account = next_account_info(account_info_iter)?;
+    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    if !rent.is_exempt(
+        option_info_account.lamports(),
+        option_info_account.data_len(),
+    ) {
+        return Err(EscrowError::NotRentExempt.into());
+    }
+    let option_info = CoveredOption::unpack_unchecked(&option_info_account.data.borrow())?;
+    if option_info.is_initialized() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+    check_not_paused(&unpack_config(
+        next_account_info(account_info_iter)?,
+        program_id,
+    )?)?;
+
+    if exercise_amount == 0 || token::unpack_token_account(tmp_collateral)?.amount == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    // only a token no one but the PDA ever minted or can freeze stands for this option's collateral
+    let mint = token::unpack_mint(option_mint)?;
+    if mint.mint_authority != COption::Some(pda)
+        || mint.freeze_authority.is_some()
+        || mint.supply != 0
+        || mint.decimals != 0
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let option_info = CoveredOption {
+        is_initialized: true,
+        writer_pubkey: *writer.key,
+        kind,
+        collateral_vault_pubkey: *tmp_collateral.key,
+        writer_payment_pubkey: *writer_payment.key,
+        option_mint_pubkey: *option_mint.key,
+        exercise_amount,
+        expires_at,
+    };
+    CoveredOption::pack(option_info, &mut option_info_account.data.borrow_mut())?;
+
+    hand_over_vault(token_program, tmp_collateral, writer, &[], program_id)?;
+
+    let mint_ix = token::mint_to(
+        token_program.key,
+        option_mint.key,
+        writer_option_token.key,
+        pda_account.key,
+        1,
+    )?;
+    msg!("Calling the token program to mint the option token to the writer...");
+    invoke_signed(
+        &mint_ix,
+        &[
+            option_mint.clone(),
+            writer_option_token.clone(),
+            pda_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[PDA_SEED, &[bump_seed]]],
+    )?;
+
+    let authority_ix =
+        token::remove_mint_authority(token_program.key, option_mint.key, pda_account.key)?;
+    msg!("Calling the token program to fix the option token's supply...");
+    invoke_signed(
+        &authority_ix,
+        &[
+            option_mint.clone(),
+            pda_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[PDA_SEED, &[bump_seed]]],
+    )
+}
+
+fn process_exercise_option(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let holder = next_account_info(account_info_iter)?;
+
+    if !holder.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let holder_option_token = next_account_info(account_info_iter)?;
+    let holder_payment = next_account_info(account_info_iter)?;
+    let holder_collateral = next_account_info(account_info_iter)?;
+    let option_mint = next_account_info(account_info_iter)?;
+    let pda_collateral_vault = next_account_info(account_info_iter)?;
+    let writer_payment = next_account_info(account_info_iter)?;
+    let writer = next_account_info(account_info_iter)?;
+    let option_info_account = next_account_info(account_info_iter)?;
+    if option_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let option_info = CoveredOption::unpack(&option_info_account.data.borrow())?;
+    if option_info.option_mint_pubkey != *option_mint.key
+        || option_info.collateral_vault_pubkey != *pda_collateral_vault.key
+        || option_info.writer_payment_pubkey != *writer_payment.key
+        || option_info.writer_pubkey != *writer.key
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // the writer can reclaim from expires_at on, so exercising must come strictly before it
+    if Clock::get()?.unix_timestamp >= option_info.expires_at {
+        return Err(EscrowError::TimeoutExpired.into());
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+    check_not_paused(&unpack_config(
+        next_account_info(account_info_iter)?,
+        program_id,
+    )?)?;
+
+    // the mint checks the option token's mint, the holder signed as its owner
+    let burn_ix = token::burn(
+        token_program.key,
+        holder_option_token.key,
+        option_mint.key,
+        holder.key,
+        1,
+    )?;
+    msg!("Calling the token program to burn the option token...");
+    invoke(
+        &burn_ix,
+        &[
+            holder_option_token.clone(),
+            option_mint.clone(),
+            holder.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    msg!("Calling the token program to transfer the exercise amount to the writer...");
+    transfer_from_owner(
+        token_program,
+        holder_payment,
+        None,
+        writer_payment,
+        holder,
+        &[],
+        option_info.exercise_amount,
+    )?;
+
+    let pda_collateral_vault_data = token::unpack_token_account(pda_collateral_vault)?;
+    msg!("Calling the token program to transfer the collateral to the holder...");
+    transfer_from_vault(
+        token_program,
+        pda_collateral_vault,
+        None,
+        holder_collateral,
+        pda_account,
+        bump_seed,
+        pda_collateral_vault_data.amount,
+    )?;
+
+    close_vault(
+        token_program,
+        pda_collateral_vault,
+        writer,
+        pda_account,
+        bump_seed,
+    )?;
+
+    msg!("Closing the option account...");
+    close_info_account(option_info_account, writer)
+}
+
+fn process_reclaim_option_collateral(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let writer = next_account_info(account_info_iter)?;
+
+    if !writer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pda_collateral_vault = next_account_info(account_info_iter)?;
+    let writer_collateral = next_account_info(account_info_iter)?;
+    let option_info_account = next_account_info(account_info_iter)?;
+    if option_info_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let option_info = CoveredOption::unpack(&option_info_account.data.borrow())?;
+    if option_info.collateral_vault_pubkey != *pda_collateral_vault.key
+        || option_info.writer_pubkey != *writer.key
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if Clock::get()?.unix_timestamp < option_info.expires_at {
+        return Err(EscrowError::TimeoutNotExpired.into());
+    }
+
+    let token_program = next_account_info(account_info_iter)?;
+    token::check_classic_token_program(token_program)?;
+    let (_pda, bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let pda_account = next_account_info(account_info_iter)?;
+
+    let pda_collateral_vault_data = token::unpack_token_account(pda_collateral_vault)?;
+    msg!("Calling the token program to transfer the collateral back to the writer...");
+    transfer_from_vault(
+        token_program,
+        pda_collateral_vault,
+        None,
+        writer_collateral,
+        pda_account,
+        bump_seed,
+        pda_collateral_vault_data.amount,
+    )?;
+
+    close_vault(
+        token_program,
+        pda_collateral_vault,
+        writer,
+        pda_account,
+        bump_seed,
+    )?;
+
+    msg!("Closing the option account...");
+    close_info_account(option_info_account, writer)
+}
+
+/// Makes the PDA the vault's owner, signers are those of a multisig owner
+fn hand_over_vault<'a>(
+    token_program: &AccountInfo<'a>,
+    vault: &AccountInfo<'a>,
+    owner: &AccountInfo<'a>,
+    signers: &[AccountInfo<'a>],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    // this pda will control all vaults
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+
+    let owner_change_ix = token::set_owner(
+        token_program.key,
+        vault.key,
+        &pda,
+        owner.key,
+        &signer_pubkeys(signers),
+    )?;
+
+    msg!("Calling the token program to transfer token account ownership...");
+    invoke(
+        &owner_change_ix,
+        &authority_account_infos(vec![vault.clone()], owner, signers, token_program),
+    )
+}
+
+/// Reads the signers of an authority, none if it signed itself or m of them if it is an
+/// spl-token multisig, which are passed on to the token program
+fn next_authority_signers<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    account_info_iter: &mut I,
+    authority: &AccountInfo<'b>,
+) -> Result<Vec<AccountInfo<'b>>, ProgramError> {
+    if authority.is_signer {
+        return Ok(Vec::new());
+    }
+    let multisig =
+        token::unpack_multisig(authority)?.ok_or(ProgramError::MissingRequiredSignature)?;
+    let signers = account_info_iter
+        .take(multisig.m as usize)
+        .cloned()
+        .collect::<Vec<_>>();
+    token::check_multisig_signers(&multisig, &signers)?;
+    Ok(signers)
+}
+
+fn signer_pubkeys<'a>(signers: &'a [AccountInfo]) -> Vec<&'a Pubkey> {
+    signers.iter().map(|signer| signer.key).collect()
+}
+
+/// The accounts of a token instruction authorized by authority, followed by its multisig signers
+fn authority_account_infos<'a>(
+    mut account_infos: Vec<AccountInfo<'a>>,
+    authority: &AccountInfo<'a>,
+    signers: &[AccountInfo<'a>],
+    token_program: &AccountInfo<'a>,
+) -> Vec<AccountInfo<'a>> {
+    account_infos.push(authority.clone());
+    account_infos.extend_from_slice(signers);
+    account_infos.push(token_program.clone());
+    account_infos
+}
+
+/// Approves the PDA to take amount out of the signing owner's token account on top of what
+/// it is approved for already, so several non-custodial escrows can share the account
+fn approve_pda<'a>(
+    token_program: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    owner: &AccountInfo<'a>,
+    signers: &[AccountInfo<'a>],
+    amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    let account_data = token::unpack_token_account(account)?;
+    let approved_amount = match account_data.delegate {
+        COption::Some(delegate) if delegate == pda => account_data.delegated_amount,
+        _ => 0,
+    };
+    let approve_ix = token::approve(
+        token_program.key,
+        account.key,
+        &pda,
+        owner.key,
+        &signer_pubkeys(signers),
+        approved_amount
+            .checked_add(amount)
+            .ok_or(EscrowError::AmountOverflow)?,
+    )?;
+
+    msg!("Calling the token program to approve the PDA...");
+    invoke(
+        &approve_ix,
+        &authority_account_infos(vec![account.clone()], owner, signers, token_program),
+    )
+}
+
+/// Takes back the signing owner's approval of amount for the PDA, leaving that of other escrows
+fn reduce_pda_approval<'a>(
+    token_program: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    account_data: &TokenAccount,
+    owner: &AccountInfo<'a>,
+    signers: &[AccountInfo<'a>],
+    amount: u64,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[PDA_SEED], program_id);
+    // the maker already revoked it or approved someone else
+    if account_data.delegate != COption::Some(pda) {
+        return Ok(());
+    }
+    let remaining_amount = account_data.delegated_amount.saturating_sub(amount);
+    let signer_pubkeys = signer_pubkeys(signers);
+    let ix = if remaining_amount == 0 {
+        token::revoke(token_program.key, account.key, owner.key, &signer_pubkeys)?
+    } else {
+        token::approve(
+            token_program.key,
+            account.key,
+            &pda,
+            owner.key,
+            &signer_pubkeys,
+            remaining_amount,
+        )?
+    };
+
+    msg!("Calling the token program to reduce the PDA's approval...");
+    invoke(
+        &ix,
+        &authority_account_infos(vec![account.clone()], owner, signers, token_program),
+    )
+}
+
+/// Fails with DelegationRevoked unless the PDA may still take amount out of the account
+fn check_delegation(account_data: &TokenAccount, pda: &Pubkey, amount: u64) -> ProgramResult {
+    if account_data.delegate != COption::Some(*pda) || account_data.delegated_amount < amount {
+        return Err(EscrowError::DelegationRevoked.into());
+    }
+    if account_data.amount < amount {
+        return Err(ProgramError::InsufficientFunds);
+    }
+    Ok(())
+}
+
+/// Transfers tokens out of an account on behalf of its owner, who signed the transaction
+/// or, for a multisig owner, whose signers did. With the mint given it is a transfer_checked,
+/// which Token-2022 requires.
+fn transfer_from_owner<'a>(
+    token_program: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    mint: Option<&AccountInfo<'a>>,
+    destination: &AccountInfo<'a>,
+    owner: &AccountInfo<'a>,
+    signers: &[AccountInfo<'a>],
+    amount: u64,
+) -> ProgramResult {
+    let transfer_ix = token::transfer(
+        token_program.key,
+        source.key,
+        mint_with_decimals(mint)?
+            .as_ref()
+            .map(|(mint, decimals)| (*mint, *decimals)),
+        destination.key,
+        owner.key,
+        &signer_pubkeys(signers),
+        amount,
+    )?;
+    let mut account_infos = vec![source.clone(), destination.clone()];
+    if let Some(mint) = mint {
+        account_infos.push(mint.clone());
+    }
+    // owner is the data.owner & signer and signer exctension allows us to send on their behalf
+    invoke(
+        &transfer_ix,
+        &authority_account_infos(account_infos, owner, signers, token_program),
+    )
+}
+
+/// Transfers tokens out of a vault owned by the PDA or an account it is the delegate of.
+/// With the mint given it is a transfer_checked, which Token-2022 requires.
+fn transfer_from_vault<'a>(
+    token_program: &AccountInfo<'a>,
+    vault: &AccountInfo<'a>,
+    mint: Option<&AccountInfo<'a>>,
+    destination: &AccountInfo<'a>,
+    pda_account: &AccountInfo<'a>,
+    bump_seed: u8,
+    amount: u64,
+) -> ProgramResult {
+    let transfer_ix = token::transfer(
+        token_program.key,
+        vault.key,
+        mint_with_decimals(mint)?
+            .as_ref()
+            .map(|(mint, decimals)| (*mint, *decimals)),
+        destination.key,
+        // we can sign as pda as we created pda
+        pda_account.key,
+        &[],
+        amount,
+    )?;
+    let mut account_infos = vec![vault.clone(), destination.clone()];
+    if let Some(mint) = mint {
+        account_infos.push(mint.clone());
+    }
+    account_infos.push(pda_account.clone());
+    account_infos.push(token_program.clone());
+    invoke_signed(&transfer_ix, &account_infos, &[&[PDA_SEED, &[bump_seed]]])
+}
+
+/// The key and decimals transfer_checked needs of a mint
+fn mint_with_decimals<'a>(
+    mint: Option<&'a AccountInfo>,
+) -> Result<Option<(&'a Pubkey, u8)>, ProgramError> {
+    match mint {
+        Some(mint) => Ok(Some((mint.key, token::unpack_mint(mint)?.decimals))),
+        None => Ok(None),
+    }
+}
+
+/// What a vault holds when it is swept before close_vault: everything, as tokens anyone sent
+/// to it on top of the escrowed amount must not keep it from closing
+fn vault_balance(vault: &AccountInfo) -> Result<u64, ProgramError> {
+    Ok(token::unpack_token_account(vault)?.amount)
+}
+
+/// Closes an empty vault owned by the PDA, its rent goes to `destination`
+fn close_vault<'a>(
+    token_program: &AccountInfo<'a>,
+    vault: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    pda_account: &AccountInfo<'a>,
+    bump_seed: u8,
+) -> ProgramResult {
+    let close_ix = token::close_account(
+        token_program.key,
+        vault.key,
+        destination.key,
+        pda_account.key,
+    )?;
+    msg!("Calling the token program to close pda's temp account...");
+    invoke_signed(
+        &close_ix,
+        &[
+            vault.clone(),
+            destination.clone(),
+            pda_account.clone(),
+            token_program.clone(),
+        ],
+        &[&[PDA_SEED, &[bump_seed]]],
+    )
+}
+
+/// Fails unless account is a classic token account of the native mint, which is wrapped SOL
+fn check_native_account(token_program: &AccountInfo, account: &AccountInfo) -> ProgramResult {
+    token::check_classic_token_program(token_program)?;
+    if token::unpack_token_account(account)?.mint != spl_token::native_mint::id() {
+        return Err(EscrowError::NotNativeMint.into());
+    }
+    Ok(())
+}
+
+/// Moves lamports from the signing owner into their native mint account and syncs its token amount
+fn wrap_sol<'a>(
+    system_program: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    owner: &AccountInfo<'a>,
+    native_account: &AccountInfo<'a>,
+    lamports: u64,
+) -> ProgramResult {
+    invoke(
+        &system_instruction::transfer(owner.key, native_account.key, lamports),
+        &[
+            owner.clone(),
+            native_account.clone(),
+            system_program.clone(),
+        ],
+    )?;
+    invoke(
+        &token::sync_native(token_program.key, native_account.key)?,
+        &[native_account.clone(), token_program.clone()],
+    )
+}
+
+/// Closes the signing owner's native mint account, its tokens and rent go to the owner as SOL
+fn unwrap_sol<'a>(
+    token_program: &AccountInfo<'a>,
+    native_account: &AccountInfo<'a>,
+    owner: &AccountInfo<'a>,
+) -> ProgramResult {
+    let close_ix =
+        token::close_account(token_program.key, native_account.key, owner.key, owner.key)?;
+    invoke(
+        &close_ix,
+        &[
+            native_account.clone(),
+            owner.clone(),
+            owner.clone(),
+            token_program.clone(),
+        ],
+    )
+}
+
+/// Reads the protocol config from its PDA, defaults while it has not been created
+fn unpack_config(
+    config_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<Config, ProgramError> {
+    let (config_pubkey, _bump_seed) = Config::find_address(program_id);
+    if config_pubkey != *config_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if config_account.owner != program_id {
+        return Ok(Config {
+            is_initialized: false,
+            admin_pubkey: Pubkey::default(),
+            max_referral_fee_bps: 0,
+            is_paused: false,
+        });
+    }
+    Config::unpack(&config_account.data.borrow())
+}
+
+/// Fails unless the mint policy at its PDA allows all mints, every mint is allowed while it
+/// has not been created
+fn check_mints_allowed(
+    mint_policy_account: &AccountInfo,
+    mints: &[Pubkey],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (mint_policy_pubkey, _bump_seed) = MintPolicy::find_address(program_id);
+    if mint_policy_pubkey != *mint_policy_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if mint_policy_account.owner != program_id {
+        return Ok(());
+    }
+    let mint_policy_data = mint_policy_account.data.borrow();
+    let mint_policy = PodMintPolicy::load(&mint_policy_data)?;
+    if !mints.iter().all(|mint| mint_policy.allows(mint)) {
+        return Err(EscrowError::MintNotAllowed.into());
+    }
+    Ok(())
+}
+
+/// Fails unless the attestation is the subject's unexpired one by the issuer
+fn check_attestation(
+    attestation_account: &AccountInfo,
+    issuer: &Pubkey,
+    subject: &Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let (attestation_pubkey, _bump_seed) = Attestation::find_address(issuer, subject, program_id);
+    if attestation_pubkey != *attestation_account.key {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    // a revoked attestation is closed and no longer the program's
+    if attestation_account.owner != program_id {
+        return Err(EscrowError::AttestationRequired.into());
+    }
+    let attestation = Attestation::unpack(&attestation_account.data.borrow())?;
+    if attestation.issuer_pubkey != *issuer || attestation.subject_pubkey != *subject {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if Clock::get()?.unix_timestamp >= attestation.expires_at {
+        return Err(EscrowError::AttestationExpired.into());
+    }
+    Ok(())
+}
+
+/// Fails while the admin paused creating and taking escrows
+fn check_not_paused(config: &Config) -> ProgramResult {
+    if config.is_paused {
+        return Err(EscrowError::ProgramPaused.into());
+    }
+    Ok(())
+}
+
+/// Fails if the escrow has expired, the account lists of the exchanges have no room for the clock sysvar
+fn check_not_expired(expires_at: Option<UnixTimestamp>) -> ProgramResult {
+    if let Some(expires_at) = expires_at {
+        if Clock::get()?.unix_timestamp >= expires_at {
+            return Err(EscrowError::TimeoutExpired.into());
+        }
+    }
+    Ok(())
+}
+
+/// Removes a listed escrow from its market, which is the next account. Escrows evicted
+/// from a full market are no longer in it, but still need it passed.
+fn delist_escrow<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+    account_info_iter: &mut I,
+    market_pubkey: Option<Pubkey>,
+    escrow_pubkey: &Pubkey,
+) -> ProgramResult {
+    if let Some(market_pubkey) = market_pubkey {
+        let market_account = next_account_info(account_info_iter)?;
+        if *market_account.key != market_pubkey {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        PodMarket::load_mut(&mut market_account.data.borrow_mut())?.remove(escrow_pubkey);
+    }
+    Ok(())
+}
+
+/// Creates a program owned account at a PDA with the given signer seeds, paid by payer.
+/// Lamports someone sent to the address beforehand don't prevent the creation.
+fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    pda_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    rent: &Rent,
+    space: usize,
+    program_id: &Pubkey,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    let required_lamports = rent.minimum_balance(space);
+    if pda_account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                pda_account.key,
+                required_lamports,
+                space as u64,
+                program_id,
+            ),
+            &[payer.clone(), pda_account.clone(), system_program.clone()],
+            &[seeds],
+        );
+    }
+
+    // create_account fails on funded addresses, so top up, allocate and assign separately
+    let missing_lamports = required_lamports.saturating_sub(pda_account.lamports());
+    if missing_lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, pda_account.key, missing_lamports),
+            &[payer.clone(), pda_account.clone(), system_program.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(pda_account.key, space as u64),
+        &[pda_account.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(pda_account.key, program_id),
+        &[pda_account.clone(), system_program.clone()],
+        &[seeds],
+    )
+}
+
+/// Closes one of the program's info accounts, its rent goes to `receiver`
+fn close_info_account(info_account: &AccountInfo, receiver: &AccountInfo) -> ProgramResult {
+    // we can directly write to receiver's account as long as we only increase the SOL balance
+    **receiver.lamports.borrow_mut() = receiver
         .lamports()
-        .checked_add(escrow_account.lamports())
+        .checked_add(info_account.lamports())
         .ok_or(EscrowError::AmountOverflow)?;
     // important to null the data here, as someone could piggy-back another instruction after this one
     // 0 lamports does not immediately drop the account data due to insufficient rent, only after tx
-    **escrow_account.lamports.borrow_mut() = 0;
-    *escrow_account.data.borrow_mut() = &mut [];
+    **info_account.lamports.borrow_mut() = 0;
+    *info_account.data.borrow_mut() = &mut [];
 
     Ok(())
 }
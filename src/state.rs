@@ -1,33 +1,308 @@
 use solana_program::{
+    clock::UnixTimestamp,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
 
+use crate::error::EscrowError;
+
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
+use std::mem::size_of;
+
+/// Kind of a program owned account, stored in place of the is_initialized flag.
+/// Escrows are variable-length, so the size alone can't tell the kinds apart.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccountKind {
+    /// escrow of the legacy layout, see Escrow::unpack_legacy
+    Escrow = 1,
+    Htlc = 2,
+    ArbiterEscrow = 3,
+    MilestoneEscrow = 4,
+    Vesting = 5,
+    Market = 6,
+    Config = 7,
+    CounterOffer = 8,
+    CoveredOption = 9,
+    MintPolicy = 10,
+    Attestation = 11,
+    /// escrow serialized with Borsh
+    BorshEscrow = 12,
+}
+
+/// Reads the is_initialized flag of an account of the given kind, 0 means uninitialized
+fn unpack_is_initialized(src: &[u8; 1], kind: AccountKind) -> Result<bool, ProgramError> {
+    match src[0] {
+        0 => Ok(false),
+        tag if tag == kind as u8 => Ok(true),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+fn pack_is_initialized(is_initialized: bool, kind: AccountKind) -> u8 {
+    if is_initialized {
+        kind as u8
+    } else {
+        0
+    }
+}
+
+/// Little-endian u64 of the packed layouts, which has no alignment so Pod structs can be laid
+/// over account data at any offset
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct PodU64([u8; 8]);
+
+impl From<u64> for PodU64 {
+    fn from(value: u64) -> Self {
+        PodU64(value.to_le_bytes())
+    }
+}
+
+impl From<PodU64> for u64 {
+    fn from(value: PodU64) -> Self {
+        u64::from_le_bytes(value.0)
+    }
+}
+
+/// Borrows the Pod struct T laid over the start of an initialized account of the given kind,
+/// the zero-copy counterpart of Pack::unpack
+fn load_pod<T: Pod>(src: &[u8], kind: AccountKind) -> Result<&T, ProgramError> {
+    let src = src
+        .get(..size_of::<T>())
+        .ok_or(ProgramError::InvalidAccountData)?;
+    match src[0] {
+        0 => Err(ProgramError::UninitializedAccount),
+        tag if tag == kind as u8 => Ok(bytemuck::from_bytes(src)),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Mutable counterpart of load_pod
+fn load_pod_mut<T: Pod>(dst: &mut [u8], kind: AccountKind) -> Result<&mut T, ProgramError> {
+    let dst = dst
+        .get_mut(..size_of::<T>())
+        .ok_or(ProgramError::InvalidAccountData)?;
+    match dst[0] {
+        0 => Err(ProgramError::UninitializedAccount),
+        tag if tag == kind as u8 => Ok(bytemuck::from_bytes_mut(dst)),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Maximum number of deposit vaults an escrow can hold on top of tmp_token0
+pub const MAX_BASKET_VAULTS: usize = 7;
+/// Maximum number of legs an escrow can ask for on top of maker_token1
+pub const MAX_EXTRA_ASKS: usize = 7;
+
+/// A leg of the trade the taker pays on top of maker_token1_expected_amount
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct Ask {
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// maker owned account of mint that will receive the tokens from the taker
+    pub recipient_pubkey: Pubkey,
+}
+
+impl Ask {
+    pub const LEN: usize = 72;
+}
+
+/// An ask for token1 quoted relative to a price feed, see the oracle module for the feed's
+/// layout, instead of the fixed maker_token1_expected_amount, which becomes the least the maker
+/// accepts
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct OracleQuote {
+    pub price_feed_pubkey: Pubkey,
+    /// basis points added to the feed's price, taken off if negative, above -BPS_DENOMINATOR
+    pub spread_bps: i16,
+    /// seconds the feed's price may be old on exchange
+    pub max_age: u32,
+    /// widest confidence interval of the feed's price accepted on exchange, in basis points of the price
+    pub max_confidence_bps: u16,
+}
+
+impl OracleQuote {
+    pub const LEN: usize = 40;
 
+    pub fn unpack_from_slice(src: &[u8; OracleQuote::LEN]) -> Self {
+        let (price_feed_pubkey, spread_bps, max_age, max_confidence_bps) =
+            array_refs![src, 32, 2, 4, 2];
+        OracleQuote {
+            price_feed_pubkey: Pubkey::new_from_array(*price_feed_pubkey),
+            spread_bps: i16::from_le_bytes(*spread_bps),
+            max_age: u32::from_le_bytes(*max_age),
+            max_confidence_bps: u16::from_le_bytes(*max_confidence_bps),
+        }
+    }
+}
+
+/// Refilling of a standing order, which pulls another tranche from the maker's funding account
+/// into its vault after each exchange instead of closing
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct Refill {
+    /// maker owned account of token0 the PDA is approved to take the tranches from
+    pub funding_pubkey: Pubkey,
+    /// amount of each refill, the vault's balance when the escrow was created
+    pub tranche_amount: u64,
+    /// amount the escrow may still pull from the funding account
+    pub remaining_amount: u64,
+}
+
+impl Refill {
+    pub const LEN: usize = 48;
+}
+
+/// length of the escrow's legacy layout, the original escrow without any of the optional parts
+pub const LEGACY_ESCROW_LEN: usize = 105;
+
+#[derive(Default, BorshSerialize, BorshDeserialize, BorshSchema)]
 pub struct Escrow {
+    /// stored as the account's kind in front of the serialized escrow
+    #[borsh_skip]
     pub is_initialized: bool,
     pub maker_pubkey: Pubkey,
     pub tmp_token0_pubkey: Pubkey,
     /// maker owned account that will receive the tokens from the taker
     pub maker_token1_pubkey: Pubkey,
     pub maker_token1_expected_amount: u64,
+    /// further deposit vaults, usually of other mints, the taker receives together with tmp_token0
+    pub basket_vault_pubkeys: Vec<Pubkey>,
+    /// further legs, usually of other mints, the taker pays together with maker_token1_expected_amount
+    pub extra_asks: Vec<Ask>,
+    /// market the escrow was listed in, it has to be passed to remove the escrow again
+    pub market_pubkey: Option<Pubkey>,
+    /// unix timestamp from which on the escrow can no longer be taken and anyone can return it
+    pub expires_at: Option<UnixTimestamp>,
+    /// whether maker_token1 is a wrapped SOL account owned by the PDA, which is closed to the maker
+    /// once it received the taker's tokens, so the maker gets SOL
+    pub unwrap_token1: bool,
+    /// amount of tmp_token0 the PDA is approved to take for a non-custodial escrow, whose
+    /// tmp_token0 is the maker's regular token account that is never handed over
+    pub delegated_amount: Option<u64>,
+    /// price feed the ask for token1 is quoted against on exchange
+    pub oracle_quote: Option<OracleQuote>,
+    /// funding of a standing order that stays open until it can't be refilled anymore
+    pub refill: Option<Refill>,
+    /// issuer the taker needs a valid attestation of to take the escrow
+    pub required_issuer: Option<Pubkey>,
 }
 
-// Sealed = Solana's Sized
-impl Sealed for Escrow {}
-
 impl IsInitialized for Escrow {
     fn is_initialized(&self) -> bool {
         self.is_initialized
     }
 }
 
-impl Pack for Escrow {
-    const LEN: usize = 105;
-    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let src = array_ref![src, 0, Escrow::LEN];
+// the escrow is variable-length so it cannot implement Pack, which requires a fixed LEN.
+// The account starts with its kind followed by the Borsh serialized escrow. Escrows created
+// before keep the legacy layout of the original 105 bytes, which has none of the optional parts.
+impl Escrow {
+    /// length of the account data of a plain escrow
+    pub const LEN: usize = 120;
+    /// account data a listed escrow needs on top of packed_len
+    pub const MARKET_SECTION_LEN: usize = 32;
+    /// account data an escrow with an expiry needs on top of packed_len
+    pub const EXPIRY_SECTION_LEN: usize = 8;
+    /// account data an escrow unwrapping token1 needs on top of packed_len
+    pub const UNWRAP_SECTION_LEN: usize = 0;
+    /// account data a non-custodial escrow needs on top of packed_len
+    pub const DELEGATION_SECTION_LEN: usize = 8;
+    /// account data an oracle-priced escrow needs on top of packed_len
+    pub const ORACLE_SECTION_LEN: usize = OracleQuote::LEN;
+    /// account data a standing order needs on top of packed_len
+    pub const REFILL_SECTION_LEN: usize = Refill::LEN;
+    /// account data an escrow for attested takers only needs on top of packed_len
+    pub const ATTESTATION_SECTION_LEN: usize = 32;
+
+    /// length of the account data needed for an escrow with basket_len basket vaults and
+    /// extra_ask_len extra asks
+    pub fn packed_len(basket_len: usize, extra_ask_len: usize) -> usize {
+        Escrow::LEN + basket_len * 32 + extra_ask_len * Ask::LEN
+    }
+
+    pub fn get_packed_len(&self) -> usize {
+        let mut len = Escrow::packed_len(self.basket_vault_pubkeys.len(), self.extra_asks.len());
+        if self.market_pubkey.is_some() {
+            len += Escrow::MARKET_SECTION_LEN;
+        }
+        if self.expires_at.is_some() {
+            len += Escrow::EXPIRY_SECTION_LEN;
+        }
+        if self.unwrap_token1 {
+            len += Escrow::UNWRAP_SECTION_LEN;
+        }
+        if self.delegated_amount.is_some() {
+            len += Escrow::DELEGATION_SECTION_LEN;
+        }
+        if self.oracle_quote.is_some() {
+            len += Escrow::ORACLE_SECTION_LEN;
+        }
+        if self.refill.is_some() {
+            len += Escrow::REFILL_SECTION_LEN;
+        }
+        if self.required_issuer.is_some() {
+            len += Escrow::ATTESTATION_SECTION_LEN;
+        }
+        len
+    }
+
+    /// whether the escrow trades its tmp_token0 vault for a fixed amount of token1 once only to
+    /// anyone, without basket vaults, extra asks, unwrapping token1, a delegation, an oracle quote,
+    /// refills or a required attestation. It may still be listed in a market or expire.
+    pub fn is_plain(&self) -> bool {
+        self.basket_vault_pubkeys.is_empty()
+            && self.extra_asks.is_empty()
+            && !self.unwrap_token1
+            && self.delegated_amount.is_none()
+            && self.oracle_quote.is_none()
+            && self.refill.is_none()
+            && self.required_issuer.is_none()
+    }
+
+    /// Unpacks an initialized escrow
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let value = Escrow::unpack_unchecked(input)?;
+        if value.is_initialized() {
+            Ok(value)
+        } else {
+            Err(ProgramError::UninitializedAccount)
+        }
+    }
+
+    /// Unpacks an escrow of either layout without checking that it is initialized
+    pub fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        let (&kind, src) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidAccountData)?;
+        match kind {
+            0 => Ok(Escrow::default()),
+            kind if kind == AccountKind::Escrow as u8 => Escrow::unpack_legacy(input),
+            kind if kind == AccountKind::BorshEscrow as u8 => {
+                // the account may have been created larger, the rest is left zeroed
+                let mut escrow = Escrow::deserialize(&mut &src[..])
+                    .map_err(|_| ProgramError::InvalidAccountData)?;
+                if escrow.basket_vault_pubkeys.len() > MAX_BASKET_VAULTS
+                    || escrow.extra_asks.len() > MAX_EXTRA_ASKS
+                {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                escrow.is_initialized = true;
+                Ok(escrow)
+            }
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Unpacks an escrow of the legacy layout without checking that it is initialized
+    pub fn unpack_legacy(input: &[u8]) -> Result<Self, ProgramError> {
+        if input.len() != LEGACY_ESCROW_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let src = array_ref![input, 0, LEGACY_ESCROW_LEN];
         let (
             is_initialized,
             maker_pubkey,
@@ -35,11 +310,7 @@ impl Pack for Escrow {
             maker_token1_pubkey,
             maker_token1_expected_amount,
         ) = array_refs![src, 1, 32, 32, 32, 8];
-        let is_initialized = match is_initialized {
-            [0] => false,
-            [1] => true,
-            _ => return Err(ProgramError::InvalidAccountData),
-        };
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::Escrow)?;
 
         Ok(Escrow {
             is_initialized,
@@ -47,11 +318,42 @@ impl Pack for Escrow {
             tmp_token0_pubkey: Pubkey::new_from_array(*tmp_token0_pubkey),
             maker_token1_pubkey: Pubkey::new_from_array(*maker_token1_pubkey),
             maker_token1_expected_amount: u64::from_le_bytes(*maker_token1_expected_amount),
+            ..Escrow::default()
         })
     }
 
-    fn pack_into_slice(&self, dst: &mut [u8]) {
-        let dst = array_mut_ref![dst, 0, Escrow::LEN];
+    /// Packs the escrow into an account that must be at least get_packed_len long. An escrow
+    /// of the legacy layout keeps it, the account it was created in is only large enough for it
+    pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.first() == Some(&(AccountKind::Escrow as u8)) {
+            return Escrow::pack_legacy(src, dst);
+        }
+        let data = src
+            .try_to_vec()
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        if dst.len() < 1 + data.len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        let (kind, rest) = dst.split_at_mut(1);
+        kind[0] = pack_is_initialized(src.is_initialized, AccountKind::BorshEscrow);
+        let (escrow, unused) = rest.split_at_mut(data.len());
+        escrow.copy_from_slice(&data);
+        for byte in unused.iter_mut() {
+            *byte = 0;
+        }
+        Ok(())
+    }
+
+    /// Packs the escrow in the legacy layout, for tools still writing it. Only a plain escrow
+    /// that is neither listed nor expires fits it
+    pub fn pack_legacy(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if !src.is_plain() || src.market_pubkey.is_some() || src.expires_at.is_some() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if dst.len() != LEGACY_ESCROW_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let dst = array_mut_ref![dst, 0, LEGACY_ESCROW_LEN];
         let (
             is_initialized_dst,
             maker_pubkey_dst,
@@ -60,10 +362,1425 @@ impl Pack for Escrow {
             expected_amount_dst,
         ) = mut_array_refs![dst, 1, 32, 32, 32, 8];
 
-        is_initialized_dst[0] = self.is_initialized as u8;
+        is_initialized_dst[0] = pack_is_initialized(src.is_initialized, AccountKind::Escrow);
+        maker_pubkey_dst.copy_from_slice(src.maker_pubkey.as_ref());
+        tmp_token0_pubkey_dst.copy_from_slice(src.tmp_token0_pubkey.as_ref());
+        maker_token1_pubkey_dst.copy_from_slice(src.maker_token1_pubkey.as_ref());
+        *expected_amount_dst = src.maker_token1_expected_amount.to_le_bytes();
+        Ok(())
+    }
+}
+
+/// Ask in place in an escrow account, laid out as Borsh serializes it
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct PodAsk {
+    pub mint: [u8; 32],
+    pub amount: PodU64,
+    pub recipient_pubkey: [u8; 32],
+}
+
+// fails to compile if the view and the packed layout disagree in size
+const _: [(); Ask::LEN] = [(); size_of::<PodAsk>()];
+
+/// Refill in place in a standing order's account, so refilling only writes remaining_amount
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct PodRefill {
+    pub funding_pubkey: [u8; 32],
+    pub tranche_amount: PodU64,
+    pub remaining_amount: PodU64,
+}
+
+const _: [(); Refill::LEN] = [(); size_of::<PodRefill>()];
+
+impl From<PodRefill> for Refill {
+    fn from(refill: PodRefill) -> Self {
+        Refill {
+            funding_pubkey: Pubkey::new_from_array(refill.funding_pubkey),
+            tranche_amount: refill.tranche_amount.into(),
+            remaining_amount: refill.remaining_amount.into(),
+        }
+    }
+}
+
+/// The start both escrow layouts share, the kind followed by the fields of the original layout
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct PodEscrowHeader {
+    pub kind: u8,
+    pub maker_pubkey: [u8; 32],
+    pub tmp_token0_pubkey: [u8; 32],
+    pub maker_token1_pubkey: [u8; 32],
+    pub maker_token1_expected_amount: PodU64,
+}
+
+const _: [(); LEGACY_ESCROW_LEN] = [(); size_of::<PodEscrowHeader>()];
+
+/// Offsets of an escrow's optional parts in its account data, the vectors' with their length
+#[derive(Default)]
+struct EscrowSections {
+    basket: (usize, usize),
+    extra_asks: (usize, usize),
+    market: Option<usize>,
+    expiry: Option<usize>,
+    unwrap_token1: bool,
+    delegation: Option<usize>,
+    oracle_quote: Option<usize>,
+    refill: Option<usize>,
+    required_issuer: Option<usize>,
+}
+
+/// Walks the Borsh serialized escrow behind the header without deserializing it
+struct BorshCursor<'a> {
+    src: &'a [u8],
+    offset: usize,
+}
+
+impl BorshCursor<'_> {
+    /// offset of the next len bytes, which it skips
+    fn take(&mut self, len: usize) -> Result<usize, ProgramError> {
+        let offset = self.offset;
+        self.offset = offset
+            .checked_add(len)
+            .filter(|end| *end <= self.src.len())
+            .ok_or(ProgramError::InvalidAccountData)?;
+        Ok(offset)
+    }
+
+    /// a flag of an Option or a bool, which Borsh only allows to be 0 or 1
+    fn flag(&mut self) -> Result<bool, ProgramError> {
+        match self.src[self.take(1)?] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// offset and length of a Vec of at most max items of item_len bytes
+    fn vec(&mut self, item_len: usize, max: usize) -> Result<(usize, usize), ProgramError> {
+        let offset = self.take(4)?;
+        let len = u32::from_le_bytes(*array_ref![self.src, offset, 4]) as usize;
+        if len > max {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok((self.take(len * item_len)?, len))
+    }
+
+    /// offset of an Option's value of len bytes if it is Some
+    fn option(&mut self, len: usize) -> Result<Option<usize>, ProgramError> {
+        if self.flag()? {
+            Ok(Some(self.take(len)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl EscrowSections {
+    /// Finds the optional parts of an initialized escrow of either layout, with the same
+    /// checks as Escrow::unpack
+    fn find(src: &[u8]) -> Result<Self, ProgramError> {
+        match src.first() {
+            Some(0) => Err(ProgramError::UninitializedAccount),
+            _ if src.len() < LEGACY_ESCROW_LEN => Err(ProgramError::InvalidAccountData),
+            // the legacy layout has none
+            Some(&kind) if kind == AccountKind::Escrow as u8 && src.len() == LEGACY_ESCROW_LEN => {
+                Ok(EscrowSections::default())
+            }
+            Some(&kind) if kind == AccountKind::BorshEscrow as u8 => {
+                let mut cursor = BorshCursor {
+                    src,
+                    offset: LEGACY_ESCROW_LEN,
+                };
+                Ok(EscrowSections {
+                    basket: cursor.vec(32, MAX_BASKET_VAULTS)?,
+                    extra_asks: cursor.vec(Ask::LEN, MAX_EXTRA_ASKS)?,
+                    market: cursor.option(32)?,
+                    expiry: cursor.option(8)?,
+                    unwrap_token1: cursor.flag()?,
+                    delegation: cursor.option(8)?,
+                    oracle_quote: cursor.option(OracleQuote::LEN)?,
+                    refill: cursor.option(Refill::LEN)?,
+                    required_issuer: cursor.option(32)?,
+                })
+            }
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Zero-copy view of an escrow account of either layout. The header is laid over the account
+/// data and the basket vaults, extra asks and refill are borrowed where they are found, so
+/// reading an escrow doesn't deserialize it into vectors like Escrow::unpack
+pub struct PodEscrow<'a> {
+    pub header: &'a PodEscrowHeader,
+    pub basket_vault_pubkeys: &'a [[u8; 32]],
+    pub extra_asks: &'a [PodAsk],
+    pub market_pubkey: Option<Pubkey>,
+    pub expires_at: Option<UnixTimestamp>,
+    pub unwrap_token1: bool,
+    pub delegated_amount: Option<u64>,
+    pub oracle_quote: Option<OracleQuote>,
+    pub refill: Option<&'a PodRefill>,
+    pub required_issuer: Option<Pubkey>,
+}
+
+impl<'a> PodEscrow<'a> {
+    /// Same as Escrow::unpack
+    pub fn load(src: &'a [u8]) -> Result<Self, ProgramError> {
+        let sections = EscrowSections::find(src)?;
+        let pubkey = |offset| Pubkey::new_from_array(*array_ref![src, offset, 32]);
+        let u64_at = |offset| u64::from_le_bytes(*array_ref![src, offset, 8]);
+        let (basket_offset, basket_len) = sections.basket;
+        let (extra_asks_offset, extra_asks_len) = sections.extra_asks;
+        Ok(PodEscrow {
+            header: bytemuck::from_bytes(&src[..LEGACY_ESCROW_LEN]),
+            basket_vault_pubkeys: bytemuck::cast_slice(
+                &src[basket_offset..basket_offset + basket_len * 32],
+            ),
+            extra_asks: bytemuck::cast_slice(
+                &src[extra_asks_offset..extra_asks_offset + extra_asks_len * Ask::LEN],
+            ),
+            market_pubkey: sections.market.map(pubkey),
+            expires_at: sections
+                .expiry
+                .map(|offset| u64_at(offset) as UnixTimestamp),
+            unwrap_token1: sections.unwrap_token1,
+            delegated_amount: sections.delegation.map(u64_at),
+            oracle_quote: sections.oracle_quote.map(|offset| {
+                OracleQuote::unpack_from_slice(array_ref![src, offset, OracleQuote::LEN])
+            }),
+            refill: sections
+                .refill
+                .map(|offset| bytemuck::from_bytes(&src[offset..offset + Refill::LEN])),
+            required_issuer: sections.required_issuer.map(pubkey),
+        })
+    }
+
+    /// Borrows the refill of a standing order to update it in place, None for other escrows
+    pub fn load_refill_mut(dst: &mut [u8]) -> Result<Option<&mut PodRefill>, ProgramError> {
+        let sections = EscrowSections::find(dst)?;
+        Ok(sections
+            .refill
+            .map(move |offset| bytemuck::from_bytes_mut(&mut dst[offset..offset + Refill::LEN])))
+    }
+
+    /// Same as Escrow::is_plain
+    pub fn is_plain(&self) -> bool {
+        self.basket_vault_pubkeys.is_empty()
+            && self.extra_asks.is_empty()
+            && !self.unwrap_token1
+            && self.delegated_amount.is_none()
+            && self.oracle_quote.is_none()
+            && self.refill.is_none()
+            && self.required_issuer.is_none()
+    }
+}
+
+/// seed of the market PDA, followed by the token0 and token1 mints
+pub const MARKET_SEED: &[u8] = b"market";
+/// Maximum number of escrows listed in a market
+pub const MAX_MARKET_ENTRIES: usize = 32;
+
+/// A listed escrow, offering amount0 of the market's token0 for amount1 of its token1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MarketEntry {
+    pub escrow_pubkey: Pubkey,
+    pub amount0: u64,
+    pub amount1: u64,
+}
+
+impl MarketEntry {
+    pub const LEN: usize = 48;
+
+    /// whether a taker pays less token1 per token0 than for other, amount0 must not be 0
+    pub fn is_better_than(&self, other: &MarketEntry) -> bool {
+        // amount1 / amount0 < other.amount1 / other.amount0 without dividing
+        (self.amount1 as u128) * (other.amount0 as u128)
+            < (other.amount1 as u128) * (self.amount0 as u128)
+    }
+}
+
+/// Order book of the escrows offering token0 for token1, at the PDA of
+/// [MARKET_SEED, token0 mint, token1 mint]. Entries are sorted best price first,
+/// escrows offering the same price in the order they were listed.
+pub struct Market {
+    pub is_initialized: bool,
+    pub mint0: Pubkey,
+    pub mint1: Pubkey,
+    /// at most MAX_MARKET_ENTRIES
+    pub entries: Vec<MarketEntry>,
+}
+
+impl Market {
+    pub fn find_address(mint0: &Pubkey, mint1: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[MARKET_SEED, mint0.as_ref(), mint1.as_ref()], program_id)
+    }
+
+    /// Lists an escrow at its price. A full market makes room by evicting its worst entry,
+    /// which is returned, if the new one is better, otherwise listing fails.
+    /// Evicted escrows can still be taken, they are just no longer listed.
+    pub fn insert(&mut self, entry: MarketEntry) -> Result<Option<MarketEntry>, ProgramError> {
+        let position = self
+            .entries
+            .iter()
+            .position(|listed| entry.is_better_than(listed))
+            .unwrap_or(self.entries.len());
+        if position >= MAX_MARKET_ENTRIES {
+            return Err(EscrowError::MarketFull.into());
+        }
+        let evicted = if self.entries.len() >= MAX_MARKET_ENTRIES {
+            self.entries.pop()
+        } else {
+            None
+        };
+        self.entries.insert(position, entry);
+        Ok(evicted)
+    }
+
+    /// Removes an escrow, returns whether it was listed
+    pub fn remove(&mut self, escrow_pubkey: &Pubkey) -> bool {
+        match self
+            .entries
+            .iter()
+            .position(|listed| listed.escrow_pubkey == *escrow_pubkey)
+        {
+            Some(position) => {
+                self.entries.remove(position);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Sealed for Market {}
+
+impl IsInitialized for Market {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Market {
+    const LEN: usize = 66 + MarketEntry::LEN * MAX_MARKET_ENTRIES;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Market::LEN];
+        let (is_initialized, mint0, mint1, entry_count, entries_src) =
+            array_refs![src, 1, 32, 32, 1, MarketEntry::LEN * MAX_MARKET_ENTRIES];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::Market)?;
+        let entry_count = entry_count[0] as usize;
+        if entry_count > MAX_MARKET_ENTRIES {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let entries = entries_src
+            .chunks(MarketEntry::LEN)
+            .take(entry_count)
+            .map(|src| {
+                let src = array_ref![src, 0, MarketEntry::LEN];
+                let (escrow_pubkey, amount0, amount1) = array_refs![src, 32, 8, 8];
+                MarketEntry {
+                    escrow_pubkey: Pubkey::new_from_array(*escrow_pubkey),
+                    amount0: u64::from_le_bytes(*amount0),
+                    amount1: u64::from_le_bytes(*amount1),
+                }
+            })
+            .collect();
+
+        Ok(Market {
+            is_initialized,
+            mint0: Pubkey::new_from_array(*mint0),
+            mint1: Pubkey::new_from_array(*mint1),
+            entries,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Market::LEN];
+        let (is_initialized_dst, mint0_dst, mint1_dst, entry_count_dst, entries_dst) =
+            mut_array_refs![dst, 1, 32, 32, 1, MarketEntry::LEN * MAX_MARKET_ENTRIES];
+
+        is_initialized_dst[0] = pack_is_initialized(self.is_initialized, AccountKind::Market);
+        mint0_dst.copy_from_slice(self.mint0.as_ref());
+        mint1_dst.copy_from_slice(self.mint1.as_ref());
+        entry_count_dst[0] = self.entries.len() as u8;
+        for (i, dst) in entries_dst.chunks_mut(MarketEntry::LEN).enumerate() {
+            let dst = array_mut_ref![dst, 0, MarketEntry::LEN];
+            match self.entries.get(i) {
+                Some(entry) => {
+                    let (escrow_pubkey_dst, amount0_dst, amount1_dst) =
+                        mut_array_refs![dst, 32, 8, 8];
+                    escrow_pubkey_dst.copy_from_slice(entry.escrow_pubkey.as_ref());
+                    *amount0_dst = entry.amount0.to_le_bytes();
+                    *amount1_dst = entry.amount1.to_le_bytes();
+                }
+                // keep the unused slots zeroed so readers can't mistake them for entries
+                None => *dst = [0; MarketEntry::LEN],
+            }
+        }
+    }
+}
+
+/// MarketEntry in place in a market account
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct PodMarketEntry {
+    pub escrow_pubkey: [u8; 32],
+    pub amount0: PodU64,
+    pub amount1: PodU64,
+}
+
+impl From<MarketEntry> for PodMarketEntry {
+    fn from(entry: MarketEntry) -> Self {
+        PodMarketEntry {
+            escrow_pubkey: entry.escrow_pubkey.to_bytes(),
+            amount0: entry.amount0.into(),
+            amount1: entry.amount1.into(),
+        }
+    }
+}
+
+impl From<PodMarketEntry> for MarketEntry {
+    fn from(entry: PodMarketEntry) -> Self {
+        MarketEntry {
+            escrow_pubkey: Pubkey::new_from_array(entry.escrow_pubkey),
+            amount0: entry.amount0.into(),
+            amount1: entry.amount1.into(),
+        }
+    }
+}
+
+/// Zero-copy view of a market account with the layout of Market::pack, so listing and
+/// delisting an escrow only moves the entries behind it instead of repacking the order book
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct PodMarket {
+    pub kind: u8,
+    pub mint0: [u8; 32],
+    pub mint1: [u8; 32],
+    pub entry_count: u8,
+    pub entries: [PodMarketEntry; MAX_MARKET_ENTRIES],
+}
+
+// fails to compile if the view and the packed layout disagree in size
+const _: [(); Market::LEN] = [(); size_of::<PodMarket>()];
+
+impl PodMarket {
+    pub fn load(src: &[u8]) -> Result<&Self, ProgramError> {
+        let market: &Self = load_pod(src, AccountKind::Market)?;
+        if market.entry_count as usize > MAX_MARKET_ENTRIES {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(market)
+    }
+
+    pub fn load_mut(dst: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        let market: &mut Self = load_pod_mut(dst, AccountKind::Market)?;
+        if market.entry_count as usize > MAX_MARKET_ENTRIES {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(market)
+    }
+
+    /// the listed entries, best price first
+    pub fn entries(&self) -> &[PodMarketEntry] {
+        &self.entries[..self.entry_count as usize]
+    }
+
+    /// Same as Market::insert
+    pub fn insert(&mut self, entry: MarketEntry) -> Result<Option<MarketEntry>, ProgramError> {
+        let mut len = self.entry_count as usize;
+        let position = self
+            .entries()
+            .iter()
+            .position(|listed| entry.is_better_than(&MarketEntry::from(*listed)))
+            .unwrap_or(len);
+        if position >= MAX_MARKET_ENTRIES {
+            return Err(EscrowError::MarketFull.into());
+        }
+        // a full market drops its last entry, which the rotation moves to position
+        let evicted = if len >= MAX_MARKET_ENTRIES {
+            Some(self.entries[len - 1].into())
+        } else {
+            len += 1;
+            None
+        };
+        self.entries[position..len].rotate_right(1);
+        self.entries[position] = entry.into();
+        self.entry_count = len as u8;
+        Ok(evicted)
+    }
+
+    /// Same as Market::remove
+    pub fn remove(&mut self, escrow_pubkey: &Pubkey) -> bool {
+        let len = self.entry_count as usize;
+        match self
+            .entries()
+            .iter()
+            .position(|listed| listed.escrow_pubkey == escrow_pubkey.to_bytes())
+        {
+            Some(position) => {
+                self.entries[position..len].rotate_left(1);
+                // keep the unused slots zeroed like Market::pack does
+                self.entries[len - 1] = PodMarketEntry::zeroed();
+                self.entry_count -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// seed of the protocol config PDA
+pub const CONFIG_SEED: &[u8] = b"config";
+/// Denominator of fees given in basis points
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Protocol wide settings at the PDA of [CONFIG_SEED], an uninitialized config has all defaults
+pub struct Config {
+    pub is_initialized: bool,
+    /// the only account that can change the config
+    pub admin_pubkey: Pubkey,
+    /// highest share of the token1 leg, in basis points, an exchange may pay to a referrer
+    pub max_referral_fee_bps: u16,
+    /// emergency stop of creating and taking escrows, cancels and expiries keep working
+    pub is_paused: bool,
+}
+
+impl Config {
+    pub fn find_address(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+    }
+}
+
+impl Sealed for Config {}
+
+impl IsInitialized for Config {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Config {
+    const LEN: usize = 36;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Config::LEN];
+        let (is_initialized, admin_pubkey, max_referral_fee_bps, is_paused) =
+            array_refs![src, 1, 32, 2, 1];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::Config)?;
+        let is_paused = match is_paused {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(Config {
+            is_initialized,
+            admin_pubkey: Pubkey::new_from_array(*admin_pubkey),
+            max_referral_fee_bps: u16::from_le_bytes(*max_referral_fee_bps),
+            is_paused,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Config::LEN];
+        let (is_initialized_dst, admin_pubkey_dst, max_referral_fee_bps_dst, is_paused_dst) =
+            mut_array_refs![dst, 1, 32, 2, 1];
+
+        is_initialized_dst[0] = pack_is_initialized(self.is_initialized, AccountKind::Config);
+        admin_pubkey_dst.copy_from_slice(self.admin_pubkey.as_ref());
+        *max_referral_fee_bps_dst = self.max_referral_fee_bps.to_le_bytes();
+        is_paused_dst[0] = self.is_paused as u8;
+    }
+}
+
+/// seed of the mint policy PDA
+pub const MINT_POLICY_SEED: &[u8] = b"mint_policy";
+/// Maximum number of mints a mint policy lists
+pub const MAX_POLICY_MINTS: usize = 64;
+
+/// Whether the mints of a mint policy are the only ones allowed or the ones blocked
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub enum PolicyMode {
+    Allowlist = 0,
+    Blocklist = 1,
+}
+
+impl PolicyMode {
+    pub fn from_u8(value: u8) -> Result<Self, ProgramError> {
+        match value {
+            0 => Ok(PolicyMode::Allowlist),
+            1 => Ok(PolicyMode::Blocklist),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Mints escrows may deposit and ask for, at the PDA of [MINT_POLICY_SEED] and changed by the
+/// config's admin. Without a policy every mint is allowed.
+pub struct MintPolicy {
+    pub is_initialized: bool,
+    pub mode: PolicyMode,
+    /// at most MAX_POLICY_MINTS
+    pub mints: Vec<Pubkey>,
+}
+
+impl MintPolicy {
+    pub fn find_address(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[MINT_POLICY_SEED], program_id)
+    }
+
+    /// whether escrows may trade mint
+    pub fn allows(&self, mint: &Pubkey) -> bool {
+        if !self.is_initialized {
+            return true;
+        }
+        let is_listed = self.mints.contains(mint);
+        match self.mode {
+            PolicyMode::Allowlist => is_listed,
+            PolicyMode::Blocklist => !is_listed,
+        }
+    }
+}
+
+impl Sealed for MintPolicy {}
+
+impl IsInitialized for MintPolicy {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for MintPolicy {
+    const LEN: usize = 3 + 32 * MAX_POLICY_MINTS;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, MintPolicy::LEN];
+        let (is_initialized, mode, mint_count, mints_src) =
+            array_refs![src, 1, 1, 1, 32 * MAX_POLICY_MINTS];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::MintPolicy)?;
+        let mint_count = mint_count[0] as usize;
+        if mint_count > MAX_POLICY_MINTS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(MintPolicy {
+            is_initialized,
+            mode: PolicyMode::from_u8(mode[0])?,
+            mints: mints_src
+                .chunks(32)
+                .take(mint_count)
+                .map(|mint| Pubkey::new_from_array(*array_ref![mint, 0, 32]))
+                .collect(),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, MintPolicy::LEN];
+        let (is_initialized_dst, mode_dst, mint_count_dst, mints_dst) =
+            mut_array_refs![dst, 1, 1, 1, 32 * MAX_POLICY_MINTS];
+
+        is_initialized_dst[0] = pack_is_initialized(self.is_initialized, AccountKind::MintPolicy);
+        mode_dst[0] = self.mode as u8;
+        mint_count_dst[0] = self.mints.len() as u8;
+        for (i, dst) in mints_dst.chunks_mut(32).enumerate() {
+            match self.mints.get(i) {
+                Some(mint) => dst.copy_from_slice(mint.as_ref()),
+                // keep the unused slots zeroed like the market's
+                None => dst.copy_from_slice(&[0; 32]),
+            }
+        }
+    }
+}
+
+/// Zero-copy view of a mint policy account with the layout of MintPolicy::pack, so checking
+/// an escrow's mints doesn't copy the whole list
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct PodMintPolicy {
+    pub kind: u8,
+    pub mode: u8,
+    pub mint_count: u8,
+    pub mints: [[u8; 32]; MAX_POLICY_MINTS],
+}
+
+// fails to compile if the view and the packed layout disagree in size
+const _: [(); MintPolicy::LEN] = [(); size_of::<PodMintPolicy>()];
+
+impl PodMintPolicy {
+    pub fn load(src: &[u8]) -> Result<&Self, ProgramError> {
+        let mint_policy: &Self = load_pod(src, AccountKind::MintPolicy)?;
+        mint_policy.check()?;
+        Ok(mint_policy)
+    }
+
+    pub fn load_mut(dst: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        let mint_policy: &mut Self = load_pod_mut(dst, AccountKind::MintPolicy)?;
+        mint_policy.check()?;
+        Ok(mint_policy)
+    }
+
+    fn check(&self) -> Result<(), ProgramError> {
+        PolicyMode::from_u8(self.mode)?;
+        if self.mint_count as usize > MAX_POLICY_MINTS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// the listed mints
+    pub fn mints(&self) -> &[[u8; 32]] {
+        &self.mints[..self.mint_count as usize]
+    }
+
+    /// Same as MintPolicy::allows of an initialized policy
+    pub fn allows(&self, mint: &Pubkey) -> bool {
+        let is_listed = self.mints().contains(&mint.to_bytes());
+        if self.mode == PolicyMode::Allowlist as u8 {
+            is_listed
+        } else {
+            !is_listed
+        }
+    }
+
+    /// Appends a mint, fails if the list is full
+    pub fn push(&mut self, mint: &Pubkey) -> Result<(), ProgramError> {
+        let len = self.mint_count as usize;
+        if len >= MAX_POLICY_MINTS {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.mints[len] = mint.to_bytes();
+        self.mint_count += 1;
+        Ok(())
+    }
+
+    /// Removes the mint at position, keeping the order of the others like Vec::remove
+    pub fn remove(&mut self, position: usize) {
+        let len = self.mint_count as usize;
+        self.mints[position..len].rotate_left(1);
+        // keep the unused slots zeroed like MintPolicy::pack does
+        self.mints[len - 1] = [0; 32];
+        self.mint_count -= 1;
+    }
+}
+
+/// A taker's offer of a different amount of token1 for a plain escrow's token0, locked in a
+/// vault until the maker accepts or rejects it or the taker withdraws it
+pub struct CounterOffer {
+    pub is_initialized: bool,
+    pub escrow_pubkey: Pubkey,
+    pub taker_pubkey: Pubkey,
+    /// vault of the offered token1 owned by the PDA
+    pub tmp_token1_pubkey: Pubkey,
+    /// taker owned account that will receive token0 if the maker accepts
+    pub taker_token0_pubkey: Pubkey,
+    /// taker owned account that gets token1 back if the offer is rejected or withdrawn
+    pub taker_token1_pubkey: Pubkey,
+    /// the amount of token0 the taker expects from the escrow's vault
+    pub amount0: u64,
+    /// the amount of token1 locked in tmp_token1 when the offer was made
+    pub amount1: u64,
+}
+
+impl Sealed for CounterOffer {}
+
+impl IsInitialized for CounterOffer {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for CounterOffer {
+    const LEN: usize = 177;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, CounterOffer::LEN];
+        let (
+            is_initialized,
+            escrow_pubkey,
+            taker_pubkey,
+            tmp_token1_pubkey,
+            taker_token0_pubkey,
+            taker_token1_pubkey,
+            amount0,
+            amount1,
+        ) = array_refs![src, 1, 32, 32, 32, 32, 32, 8, 8];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::CounterOffer)?;
+
+        Ok(CounterOffer {
+            is_initialized,
+            escrow_pubkey: Pubkey::new_from_array(*escrow_pubkey),
+            taker_pubkey: Pubkey::new_from_array(*taker_pubkey),
+            tmp_token1_pubkey: Pubkey::new_from_array(*tmp_token1_pubkey),
+            taker_token0_pubkey: Pubkey::new_from_array(*taker_token0_pubkey),
+            taker_token1_pubkey: Pubkey::new_from_array(*taker_token1_pubkey),
+            amount0: u64::from_le_bytes(*amount0),
+            amount1: u64::from_le_bytes(*amount1),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, CounterOffer::LEN];
+        let (
+            is_initialized_dst,
+            escrow_pubkey_dst,
+            taker_pubkey_dst,
+            tmp_token1_pubkey_dst,
+            taker_token0_pubkey_dst,
+            taker_token1_pubkey_dst,
+            amount0_dst,
+            amount1_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 32, 32, 8, 8];
+
+        is_initialized_dst[0] = pack_is_initialized(self.is_initialized, AccountKind::CounterOffer);
+        escrow_pubkey_dst.copy_from_slice(self.escrow_pubkey.as_ref());
+        taker_pubkey_dst.copy_from_slice(self.taker_pubkey.as_ref());
+        tmp_token1_pubkey_dst.copy_from_slice(self.tmp_token1_pubkey.as_ref());
+        taker_token0_pubkey_dst.copy_from_slice(self.taker_token0_pubkey.as_ref());
+        taker_token1_pubkey_dst.copy_from_slice(self.taker_token1_pubkey.as_ref());
+        *amount0_dst = self.amount0.to_le_bytes();
+        *amount1_dst = self.amount1.to_le_bytes();
+    }
+}
+
+/// Side of a covered option, the collateral is the underlying for a call and the strike for a put
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub enum OptionKind {
+    Call = 0,
+    Put = 1,
+}
+
+impl OptionKind {
+    pub fn from_u8(value: u8) -> Result<Self, ProgramError> {
+        match value {
+            0 => Ok(OptionKind::Call),
+            1 => Ok(OptionKind::Put),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Covered option, whoever holds its single option token can burn it before expires_at to
+/// receive the collateral in exchange for exercise_amount, paid to the writer. Once expired
+/// the writer takes the collateral back.
+pub struct CoveredOption {
+    pub is_initialized: bool,
+    pub writer_pubkey: Pubkey,
+    pub kind: OptionKind,
+    /// vault holding the underlying of a call or the strike of a put
+    pub collateral_vault_pubkey: Pubkey,
+    /// writer owned account that will receive the strike of a call or the underlying of a put
+    pub writer_payment_pubkey: Pubkey,
+    /// mint of the option token, its supply of 1 was minted by the PDA, which gave up minting
+    pub option_mint_pubkey: Pubkey,
+    /// amount the holder pays into writer_payment to exercise
+    pub exercise_amount: u64,
+    /// unix timestamp from which on the option can no longer be exercised and the writer can reclaim
+    pub expires_at: UnixTimestamp,
+}
+
+impl Sealed for CoveredOption {}
+
+impl IsInitialized for CoveredOption {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for CoveredOption {
+    const LEN: usize = 146;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, CoveredOption::LEN];
+        let (
+            is_initialized,
+            writer_pubkey,
+            kind,
+            collateral_vault_pubkey,
+            writer_payment_pubkey,
+            option_mint_pubkey,
+            exercise_amount,
+            expires_at,
+        ) = array_refs![src, 1, 32, 1, 32, 32, 32, 8, 8];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::CoveredOption)?;
+
+        Ok(CoveredOption {
+            is_initialized,
+            writer_pubkey: Pubkey::new_from_array(*writer_pubkey),
+            kind: OptionKind::from_u8(kind[0])?,
+            collateral_vault_pubkey: Pubkey::new_from_array(*collateral_vault_pubkey),
+            writer_payment_pubkey: Pubkey::new_from_array(*writer_payment_pubkey),
+            option_mint_pubkey: Pubkey::new_from_array(*option_mint_pubkey),
+            exercise_amount: u64::from_le_bytes(*exercise_amount),
+            expires_at: i64::from_le_bytes(*expires_at),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, CoveredOption::LEN];
+        let (
+            is_initialized_dst,
+            writer_pubkey_dst,
+            kind_dst,
+            collateral_vault_pubkey_dst,
+            writer_payment_pubkey_dst,
+            option_mint_pubkey_dst,
+            exercise_amount_dst,
+            expires_at_dst,
+        ) = mut_array_refs![dst, 1, 32, 1, 32, 32, 32, 8, 8];
+
+        is_initialized_dst[0] =
+            pack_is_initialized(self.is_initialized, AccountKind::CoveredOption);
+        writer_pubkey_dst.copy_from_slice(self.writer_pubkey.as_ref());
+        kind_dst[0] = self.kind as u8;
+        collateral_vault_pubkey_dst.copy_from_slice(self.collateral_vault_pubkey.as_ref());
+        writer_payment_pubkey_dst.copy_from_slice(self.writer_payment_pubkey.as_ref());
+        option_mint_pubkey_dst.copy_from_slice(self.option_mint_pubkey.as_ref());
+        *exercise_amount_dst = self.exercise_amount.to_le_bytes();
+        *expires_at_dst = self.expires_at.to_le_bytes();
+    }
+}
+
+/// seed of attestation PDAs
+pub const ATTESTATION_SEED: &[u8] = b"attestation";
+
+/// An issuer's attestation of a subject, typically of passing KYC, valid until expires_at or
+/// until the issuer revokes it, at the PDA of [ATTESTATION_SEED, issuer, subject]
+pub struct Attestation {
+    pub is_initialized: bool,
+    pub issuer_pubkey: Pubkey,
+    pub subject_pubkey: Pubkey,
+    pub expires_at: UnixTimestamp,
+}
+
+impl Attestation {
+    pub fn find_address(issuer: &Pubkey, subject: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[ATTESTATION_SEED, issuer.as_ref(), subject.as_ref()],
+            program_id,
+        )
+    }
+}
+
+impl Sealed for Attestation {}
+
+impl IsInitialized for Attestation {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Attestation {
+    const LEN: usize = 73;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Attestation::LEN];
+        let (is_initialized, issuer_pubkey, subject_pubkey, expires_at) =
+            array_refs![src, 1, 32, 32, 8];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::Attestation)?;
+
+        Ok(Attestation {
+            is_initialized,
+            issuer_pubkey: Pubkey::new_from_array(*issuer_pubkey),
+            subject_pubkey: Pubkey::new_from_array(*subject_pubkey),
+            expires_at: i64::from_le_bytes(*expires_at),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Attestation::LEN];
+        let (is_initialized_dst, issuer_pubkey_dst, subject_pubkey_dst, expires_at_dst) =
+            mut_array_refs![dst, 1, 32, 32, 8];
+
+        is_initialized_dst[0] = pack_is_initialized(self.is_initialized, AccountKind::Attestation);
+        issuer_pubkey_dst.copy_from_slice(self.issuer_pubkey.as_ref());
+        subject_pubkey_dst.copy_from_slice(self.subject_pubkey.as_ref());
+        *expires_at_dst = self.expires_at.to_le_bytes();
+    }
+}
+
+/// Hash function the preimage of a hash time-locked escrow is checked with
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub enum HashKind {
+    Sha256 = 0,
+    Keccak256 = 1,
+}
+
+impl HashKind {
+    pub fn from_u8(value: u8) -> Result<Self, ProgramError> {
+        match value {
+            0 => Ok(HashKind::Sha256),
+            1 => Ok(HashKind::Keccak256),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    pub fn hash(&self, preimage: &[u8]) -> [u8; 32] {
+        match self {
+            HashKind::Sha256 => solana_program::hash::hashv(&[preimage]).to_bytes(),
+            HashKind::Keccak256 => solana_program::keccak::hashv(&[preimage]).to_bytes(),
+        }
+    }
+}
+
+/// Hash time-locked escrow, the tokens in tmp_token0 go to recipient_token0 for whoever reveals
+/// the preimage of hashlock before timeout, afterwards the maker can take them back
+pub struct Htlc {
+    pub is_initialized: bool,
+    pub maker_pubkey: Pubkey,
+    pub tmp_token0_pubkey: Pubkey,
+    /// account that will receive the tokens once the preimage is revealed
+    pub recipient_token0_pubkey: Pubkey,
+    pub hash_kind: HashKind,
+    pub hashlock: [u8; 32],
+    /// unix timestamp from which on the preimage is no longer accepted and the maker can refund
+    pub timeout: UnixTimestamp,
+}
+
+// Sealed = Solana's Sized
+impl Sealed for Htlc {}
+
+impl IsInitialized for Htlc {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Htlc {
+    const LEN: usize = 138;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Htlc::LEN];
+        let (
+            is_initialized,
+            maker_pubkey,
+            tmp_token0_pubkey,
+            recipient_token0_pubkey,
+            hash_kind,
+            hashlock,
+            timeout,
+        ) = array_refs![src, 1, 32, 32, 32, 1, 32, 8];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::Htlc)?;
+
+        Ok(Htlc {
+            is_initialized,
+            maker_pubkey: Pubkey::new_from_array(*maker_pubkey),
+            tmp_token0_pubkey: Pubkey::new_from_array(*tmp_token0_pubkey),
+            recipient_token0_pubkey: Pubkey::new_from_array(*recipient_token0_pubkey),
+            // an uninitialized account is all zeroes which is a valid hash kind
+            hash_kind: HashKind::from_u8(hash_kind[0])?,
+            hashlock: *hashlock,
+            timeout: i64::from_le_bytes(*timeout),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Htlc::LEN];
+        let (
+            is_initialized_dst,
+            maker_pubkey_dst,
+            tmp_token0_pubkey_dst,
+            recipient_token0_pubkey_dst,
+            hash_kind_dst,
+            hashlock_dst,
+            timeout_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 1, 32, 8];
+
+        is_initialized_dst[0] = pack_is_initialized(self.is_initialized, AccountKind::Htlc);
         maker_pubkey_dst.copy_from_slice(self.maker_pubkey.as_ref());
         tmp_token0_pubkey_dst.copy_from_slice(self.tmp_token0_pubkey.as_ref());
-        maker_token1_pubkey_dst.copy_from_slice(self.maker_token1_pubkey.as_ref());
-        *expected_amount_dst = self.maker_token1_expected_amount.to_le_bytes();
+        recipient_token0_pubkey_dst.copy_from_slice(self.recipient_token0_pubkey.as_ref());
+        hash_kind_dst[0] = self.hash_kind as u8;
+        *hashlock_dst = self.hashlock;
+        *timeout_dst = self.timeout.to_le_bytes();
+    }
+}
+
+/// Three-party escrow, the payer releases to the payee, the payee refunds the payer,
+/// and once either of them raised a dispute only the arbiter can split the funds
+pub struct ArbiterEscrow {
+    pub is_initialized: bool,
+    pub payer_pubkey: Pubkey,
+    pub tmp_token0_pubkey: Pubkey,
+    /// payer owned account receiving refunds
+    pub payer_token0_pubkey: Pubkey,
+    /// the payee's main account that is allowed to refund
+    pub payee_pubkey: Pubkey,
+    /// payee owned account receiving the released funds
+    pub payee_token0_pubkey: Pubkey,
+    pub arbiter_pubkey: Pubkey,
+    pub is_disputed: bool,
+}
+
+impl Sealed for ArbiterEscrow {}
+
+impl IsInitialized for ArbiterEscrow {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for ArbiterEscrow {
+    const LEN: usize = 194;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, ArbiterEscrow::LEN];
+        let (
+            is_initialized,
+            payer_pubkey,
+            tmp_token0_pubkey,
+            payer_token0_pubkey,
+            payee_pubkey,
+            payee_token0_pubkey,
+            arbiter_pubkey,
+            is_disputed,
+        ) = array_refs![src, 1, 32, 32, 32, 32, 32, 32, 1];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::ArbiterEscrow)?;
+        let is_disputed = match is_disputed {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(ArbiterEscrow {
+            is_initialized,
+            payer_pubkey: Pubkey::new_from_array(*payer_pubkey),
+            tmp_token0_pubkey: Pubkey::new_from_array(*tmp_token0_pubkey),
+            payer_token0_pubkey: Pubkey::new_from_array(*payer_token0_pubkey),
+            payee_pubkey: Pubkey::new_from_array(*payee_pubkey),
+            payee_token0_pubkey: Pubkey::new_from_array(*payee_token0_pubkey),
+            arbiter_pubkey: Pubkey::new_from_array(*arbiter_pubkey),
+            is_disputed,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, ArbiterEscrow::LEN];
+        let (
+            is_initialized_dst,
+            payer_pubkey_dst,
+            tmp_token0_pubkey_dst,
+            payer_token0_pubkey_dst,
+            payee_pubkey_dst,
+            payee_token0_pubkey_dst,
+            arbiter_pubkey_dst,
+            is_disputed_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 32, 32, 32, 1];
+
+        is_initialized_dst[0] =
+            pack_is_initialized(self.is_initialized, AccountKind::ArbiterEscrow);
+        payer_pubkey_dst.copy_from_slice(self.payer_pubkey.as_ref());
+        tmp_token0_pubkey_dst.copy_from_slice(self.tmp_token0_pubkey.as_ref());
+        payer_token0_pubkey_dst.copy_from_slice(self.payer_token0_pubkey.as_ref());
+        payee_pubkey_dst.copy_from_slice(self.payee_pubkey.as_ref());
+        payee_token0_pubkey_dst.copy_from_slice(self.payee_token0_pubkey.as_ref());
+        arbiter_pubkey_dst.copy_from_slice(self.arbiter_pubkey.as_ref());
+        is_disputed_dst[0] = self.is_disputed as u8;
+    }
+}
+
+/// Maximum number of milestones a milestone escrow can hold
+pub const MAX_MILESTONES: usize = 8;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MilestoneStatus {
+    Pending = 0,
+    Approved = 1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Milestone {
+    pub amount: u64,
+    pub status: MilestoneStatus,
+}
+
+impl Default for Milestone {
+    fn default() -> Self {
+        Milestone {
+            amount: 0,
+            status: MilestoneStatus::Pending,
+        }
+    }
+}
+
+/// Escrow releasing the vault to the payee in tranches, one per milestone the payer approves
+pub struct MilestoneEscrow {
+    pub is_initialized: bool,
+    pub payer_pubkey: Pubkey,
+    pub tmp_token0_pubkey: Pubkey,
+    /// payer owned account receiving the unapproved remainder on cancel
+    pub payer_token0_pubkey: Pubkey,
+    /// payee owned account receiving the approved tranches
+    pub payee_token0_pubkey: Pubkey,
+    /// only the first milestone_count milestones are in use
+    pub milestone_count: u8,
+    pub milestones: [Milestone; MAX_MILESTONES],
+}
+
+impl MilestoneEscrow {
+    /// the milestones in use
+    pub fn active_milestones(&self) -> &[Milestone] {
+        &self.milestones[..self.milestone_count as usize]
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.active_milestones()
+            .iter()
+            .all(|milestone| milestone.status == MilestoneStatus::Approved)
+    }
+}
+
+impl Sealed for MilestoneEscrow {}
+
+impl IsInitialized for MilestoneEscrow {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for MilestoneEscrow {
+    const LEN: usize = 202;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, MilestoneEscrow::LEN];
+        let (
+            is_initialized,
+            payer_pubkey,
+            tmp_token0_pubkey,
+            payer_token0_pubkey,
+            payee_token0_pubkey,
+            milestone_count,
+            milestones_src,
+        ) = array_refs![src, 1, 32, 32, 32, 32, 1, 9 * MAX_MILESTONES];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::MilestoneEscrow)?;
+        if milestone_count[0] as usize > MAX_MILESTONES {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut milestones = [Milestone::default(); MAX_MILESTONES];
+        for (milestone, src) in milestones.iter_mut().zip(milestones_src.chunks(9)) {
+            let src = array_ref![src, 0, 9];
+            let (amount, status) = array_refs![src, 8, 1];
+            milestone.amount = u64::from_le_bytes(*amount);
+            milestone.status = match status {
+                [0] => MilestoneStatus::Pending,
+                [1] => MilestoneStatus::Approved,
+                _ => return Err(ProgramError::InvalidAccountData),
+            };
+        }
+
+        Ok(MilestoneEscrow {
+            is_initialized,
+            payer_pubkey: Pubkey::new_from_array(*payer_pubkey),
+            tmp_token0_pubkey: Pubkey::new_from_array(*tmp_token0_pubkey),
+            payer_token0_pubkey: Pubkey::new_from_array(*payer_token0_pubkey),
+            payee_token0_pubkey: Pubkey::new_from_array(*payee_token0_pubkey),
+            milestone_count: milestone_count[0],
+            milestones,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, MilestoneEscrow::LEN];
+        let (
+            is_initialized_dst,
+            payer_pubkey_dst,
+            tmp_token0_pubkey_dst,
+            payer_token0_pubkey_dst,
+            payee_token0_pubkey_dst,
+            milestone_count_dst,
+            milestones_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 32, 1, 9 * MAX_MILESTONES];
+
+        is_initialized_dst[0] =
+            pack_is_initialized(self.is_initialized, AccountKind::MilestoneEscrow);
+        payer_pubkey_dst.copy_from_slice(self.payer_pubkey.as_ref());
+        tmp_token0_pubkey_dst.copy_from_slice(self.tmp_token0_pubkey.as_ref());
+        payer_token0_pubkey_dst.copy_from_slice(self.payer_token0_pubkey.as_ref());
+        payee_token0_pubkey_dst.copy_from_slice(self.payee_token0_pubkey.as_ref());
+        milestone_count_dst[0] = self.milestone_count;
+        for (milestone, dst) in self.milestones.iter().zip(milestones_dst.chunks_mut(9)) {
+            let dst = array_mut_ref![dst, 0, 9];
+            let (amount_dst, status_dst) = mut_array_refs![dst, 8, 1];
+            *amount_dst = milestone.amount.to_le_bytes();
+            status_dst[0] = milestone.status as u8;
+        }
+    }
+}
+
+/// Milestone in place in a milestone escrow account
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct PodMilestone {
+    pub amount: PodU64,
+    pub status: u8,
+}
+
+/// Zero-copy view of a milestone escrow account with the layout of MilestoneEscrow::pack, so
+/// approving a milestone only writes its status
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct PodMilestoneEscrow {
+    pub kind: u8,
+    pub payer_pubkey: [u8; 32],
+    pub tmp_token0_pubkey: [u8; 32],
+    pub payer_token0_pubkey: [u8; 32],
+    pub payee_token0_pubkey: [u8; 32],
+    pub milestone_count: u8,
+    pub milestones: [PodMilestone; MAX_MILESTONES],
+}
+
+// fails to compile if the view and the packed layout disagree in size
+const _: [(); MilestoneEscrow::LEN] = [(); size_of::<PodMilestoneEscrow>()];
+
+impl PodMilestoneEscrow {
+    pub fn load(src: &[u8]) -> Result<&Self, ProgramError> {
+        let escrow: &Self = load_pod(src, AccountKind::MilestoneEscrow)?;
+        escrow.check()?;
+        Ok(escrow)
+    }
+
+    pub fn load_mut(dst: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        let escrow: &mut Self = load_pod_mut(dst, AccountKind::MilestoneEscrow)?;
+        escrow.check()?;
+        Ok(escrow)
+    }
+
+    fn check(&self) -> Result<(), ProgramError> {
+        if self.milestone_count as usize > MAX_MILESTONES {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let is_valid_status = |milestone: &PodMilestone| {
+            milestone.status == MilestoneStatus::Pending as u8
+                || milestone.status == MilestoneStatus::Approved as u8
+        };
+        if !self.milestones.iter().all(is_valid_status) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /// the milestones in use
+    pub fn active_milestones(&self) -> &[PodMilestone] {
+        &self.milestones[..self.milestone_count as usize]
+    }
+
+    /// Same as MilestoneEscrow::is_settled
+    pub fn is_settled(&self) -> bool {
+        self.active_milestones()
+            .iter()
+            .all(|milestone| milestone.status == MilestoneStatus::Approved as u8)
+    }
+}
+
+/// Linear vesting of the vault to the beneficiary between start and end, nothing vests before the cliff
+pub struct Vesting {
+    pub is_initialized: bool,
+    pub funder_pubkey: Pubkey,
+    pub tmp_token0_pubkey: Pubkey,
+    /// funder owned account receiving the unvested remainder on revoke
+    pub funder_token0_pubkey: Pubkey,
+    /// beneficiary owned account receiving the vested tokens
+    pub beneficiary_token0_pubkey: Pubkey,
+    pub start_ts: UnixTimestamp,
+    pub cliff_ts: UnixTimestamp,
+    pub end_ts: UnixTimestamp,
+    /// vault balance at initialization
+    pub total_amount: u64,
+    pub withdrawn_amount: u64,
+    pub is_revocable: bool,
+}
+
+impl Vesting {
+    /// amount vested at the given time, including what was already withdrawn
+    pub fn vested_amount(&self, now: UnixTimestamp) -> u64 {
+        if now < self.cliff_ts {
+            return 0;
+        }
+        if now >= self.end_ts {
+            return self.total_amount;
+        }
+        // start <= cliff <= now < end is checked on init, so elapsed isn't negative and duration
+        // isn't 0. A schedule spanning more than i64::MAX seconds saturates both, which keeps
+        // elapsed <= duration
+        let elapsed = now.saturating_sub(self.start_ts) as u128;
+        let duration = self.end_ts.saturating_sub(self.start_ts) as u128;
+        (self.total_amount as u128 * elapsed / duration) as u64
+    }
+}
+
+impl Sealed for Vesting {}
+
+impl IsInitialized for Vesting {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for Vesting {
+    const LEN: usize = 170;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, Vesting::LEN];
+        let (
+            is_initialized,
+            funder_pubkey,
+            tmp_token0_pubkey,
+            funder_token0_pubkey,
+            beneficiary_token0_pubkey,
+            start_ts,
+            cliff_ts,
+            end_ts,
+            total_amount,
+            withdrawn_amount,
+            is_revocable,
+        ) = array_refs![src, 1, 32, 32, 32, 32, 8, 8, 8, 8, 8, 1];
+        let is_initialized = unpack_is_initialized(is_initialized, AccountKind::Vesting)?;
+        let is_revocable = match is_revocable {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(Vesting {
+            is_initialized,
+            funder_pubkey: Pubkey::new_from_array(*funder_pubkey),
+            tmp_token0_pubkey: Pubkey::new_from_array(*tmp_token0_pubkey),
+            funder_token0_pubkey: Pubkey::new_from_array(*funder_token0_pubkey),
+            beneficiary_token0_pubkey: Pubkey::new_from_array(*beneficiary_token0_pubkey),
+            start_ts: i64::from_le_bytes(*start_ts),
+            cliff_ts: i64::from_le_bytes(*cliff_ts),
+            end_ts: i64::from_le_bytes(*end_ts),
+            total_amount: u64::from_le_bytes(*total_amount),
+            withdrawn_amount: u64::from_le_bytes(*withdrawn_amount),
+            is_revocable,
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, Vesting::LEN];
+        let (
+            is_initialized_dst,
+            funder_pubkey_dst,
+            tmp_token0_pubkey_dst,
+            funder_token0_pubkey_dst,
+            beneficiary_token0_pubkey_dst,
+            start_ts_dst,
+            cliff_ts_dst,
+            end_ts_dst,
+            total_amount_dst,
+            withdrawn_amount_dst,
+            is_revocable_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 32, 8, 8, 8, 8, 8, 1];
+
+        is_initialized_dst[0] = pack_is_initialized(self.is_initialized, AccountKind::Vesting);
+        funder_pubkey_dst.copy_from_slice(self.funder_pubkey.as_ref());
+        tmp_token0_pubkey_dst.copy_from_slice(self.tmp_token0_pubkey.as_ref());
+        funder_token0_pubkey_dst.copy_from_slice(self.funder_token0_pubkey.as_ref());
+        beneficiary_token0_pubkey_dst.copy_from_slice(self.beneficiary_token0_pubkey.as_ref());
+        *start_ts_dst = self.start_ts.to_le_bytes();
+        *cliff_ts_dst = self.cliff_ts.to_le_bytes();
+        *end_ts_dst = self.end_ts.to_le_bytes();
+        *total_amount_dst = self.total_amount.to_le_bytes();
+        *withdrawn_amount_dst = self.withdrawn_amount.to_le_bytes();
+        is_revocable_dst[0] = self.is_revocable as u8;
     }
 }
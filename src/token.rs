@@ -0,0 +1,401 @@
+//! Support for both the classic token program and Token-2022.
+//!
+//! Token-2022 keeps the classic instruction and state layouts, mints and accounts may just be
+//! longer. Past the classic length they hold an account type byte at offset 165 followed by
+//! the extensions, each a u16 type, a u16 length and the extension's data.
+
+use crate::error::EscrowError;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use spl_token::{
+    instruction::MAX_SIGNERS,
+    state::{Account as TokenAccount, Mint, Multisig},
+};
+
+/// The Token-2022 program
+pub mod token_2022 {
+    solana_program::declare_id!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+}
+
+/// The associated token account program
+pub mod associated_token {
+    solana_program::declare_id!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+}
+
+/// offset of the account type of Token-2022 mints and accounts with extensions
+const ACCOUNT_TYPE_OFFSET: usize = TokenAccount::LEN;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+
+/// Token-2022 extension types the escrow has to know about
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+const EXTENSION_MEMO_TRANSFER: u16 = 8;
+const EXTENSION_NON_TRANSFERABLE: u16 = 9;
+const EXTENSION_PERMANENT_DELEGATE: u16 = 12;
+const EXTENSION_TRANSFER_HOOK: u16 = 14;
+
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == spl_token::id() || *program_id == token_2022::id()
+}
+
+pub fn check_token_program(token_program: &AccountInfo) -> ProgramResult {
+    if !is_token_program(token_program.key) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// For instructions that only support the classic token program
+pub fn check_classic_token_program(token_program: &AccountInfo) -> ProgramResult {
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+pub fn is_token_2022(token_program: &AccountInfo) -> bool {
+    *token_program.key == token_2022::id()
+}
+
+/// Unpacks the classic part of an initialized token account of either program
+pub fn unpack_token_account(account: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    if !is_token_program(account.owner) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = account.data.borrow();
+    check_account_type(
+        account.owner,
+        &data,
+        TokenAccount::LEN,
+        ACCOUNT_TYPE_ACCOUNT,
+    )?;
+    let token_account = TokenAccount::unpack_from_slice(&data[..TokenAccount::LEN])?;
+    if !token_account.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(token_account)
+}
+
+/// Unpacks the classic part of an initialized mint of either program
+pub fn unpack_mint(mint: &AccountInfo) -> Result<Mint, ProgramError> {
+    if !is_token_program(mint.owner) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = mint.data.borrow();
+    check_account_type(mint.owner, &data, Mint::LEN, ACCOUNT_TYPE_MINT)?;
+    let mint = Mint::unpack_from_slice(&data[..Mint::LEN])?;
+    if !mint.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(mint)
+}
+
+/// Reads a multisig of either token program, none if the account isn't one
+pub fn unpack_multisig(account: &AccountInfo) -> Result<Option<Multisig>, ProgramError> {
+    if !is_token_program(account.owner) || account.data_len() != Multisig::LEN {
+        return Ok(None);
+    }
+    let multisig = Multisig::unpack_from_slice(&account.data.borrow())?;
+    if !multisig.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(Some(multisig))
+}
+
+/// Fails unless m distinct signers of the multisig signed, counted like the token program does
+pub fn check_multisig_signers(multisig: &Multisig, signers: &[AccountInfo]) -> ProgramResult {
+    let mut matched = [false; MAX_SIGNERS];
+    let mut num_signers = 0;
+    for signer in signers.iter() {
+        for (position, key) in multisig
+            .signers
+            .iter()
+            .take(multisig.n as usize)
+            .enumerate()
+        {
+            if key == signer.key && !matched[position] {
+                if !signer.is_signer {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+                matched[position] = true;
+                num_signers += 1;
+            }
+        }
+    }
+    if num_signers < multisig.m {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+/// Classic accounts have exactly the classic length, Token-2022 ones may carry extensions
+fn check_account_type(
+    owner: &Pubkey,
+    data: &[u8],
+    classic_len: usize,
+    account_type: u8,
+) -> ProgramResult {
+    if data.len() == classic_len {
+        return Ok(());
+    }
+    if *owner == token_2022::id()
+        && data.len() > ACCOUNT_TYPE_OFFSET
+        && data[ACCOUNT_TYPE_OFFSET] == account_type
+    {
+        return Ok(());
+    }
+    Err(ProgramError::InvalidAccountData)
+}
+
+/// Iterates over the (type, data) of the extensions of a Token-2022 mint or account
+fn extensions(data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut rest = data.get(ACCOUNT_TYPE_OFFSET + 1..).unwrap_or(&[]);
+    std::iter::from_fn(move || {
+        if rest.len() < 4 {
+            return None;
+        }
+        let extension_type = u16::from_le_bytes([rest[0], rest[1]]);
+        let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        // type 0 is the zeroed space not taken by an extension yet
+        if extension_type == 0 || rest.len() < 4 + len {
+            return None;
+        }
+        let value = &rest[4..4 + len];
+        rest = &rest[4 + len..];
+        Some((extension_type, value))
+    })
+}
+
+/// Rejects mints whose extensions would change the amounts moved or block the escrow's transfers
+pub fn check_mint_extensions(mint: &AccountInfo) -> ProgramResult {
+    if *mint.owner != token_2022::id() {
+        return Ok(());
+    }
+    let data = mint.data.borrow();
+    for (extension_type, _) in extensions(&data) {
+        match extension_type {
+            EXTENSION_TRANSFER_FEE_CONFIG => return Err(EscrowError::TransferFeeMint.into()),
+            EXTENSION_NON_TRANSFERABLE => return Err(EscrowError::NonTransferableMint.into()),
+            // a permanent delegate could empty the vault, hooks need accounts the escrow doesn't pass
+            EXTENSION_PERMANENT_DELEGATE | EXTENSION_TRANSFER_HOOK => {
+                return Err(EscrowError::UnsupportedMintExtension.into())
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Rejects accounts that only accept transfers preceded by a memo, which a CPI can't provide
+pub fn check_recipient_extensions(account: &AccountInfo) -> ProgramResult {
+    if *account.owner != token_2022::id() {
+        return Ok(());
+    }
+    let data = account.data.borrow();
+    for (extension_type, value) in extensions(&data) {
+        if extension_type == EXTENSION_MEMO_TRANSFER && value.first() == Some(&1) {
+            return Err(EscrowError::MemoTransferRequired.into());
+        }
+    }
+    Ok(())
+}
+
+/// The spl_token builders only accept the classic program id, Token-2022 shares the instruction layouts
+fn for_token_program(mut instruction: Instruction, token_program_id: &Pubkey) -> Instruction {
+    instruction.program_id = *token_program_id;
+    instruction
+}
+
+/// A transfer, transfer_checked if the mint is given, which Token-2022 mints require.
+/// The signers are those of a multisig authority, empty for any other.
+pub fn transfer(
+    token_program_id: &Pubkey,
+    source: &Pubkey,
+    mint: Option<(&Pubkey, u8)>,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    signers: &[&Pubkey],
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let instruction = match mint {
+        Some((mint, decimals)) => spl_token::instruction::transfer_checked(
+            &spl_token::id(),
+            source,
+            mint,
+            destination,
+            authority,
+            signers,
+            amount,
+            decimals,
+        )?,
+        None => spl_token::instruction::transfer(
+            &spl_token::id(),
+            source,
+            destination,
+            authority,
+            signers,
+            amount,
+        )?,
+    };
+    Ok(for_token_program(instruction, token_program_id))
+}
+
+pub fn set_owner(
+    token_program_id: &Pubkey,
+    account: &Pubkey,
+    new_owner: &Pubkey,
+    owner: &Pubkey,
+    signers: &[&Pubkey],
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::set_authority(
+        &spl_token::id(),
+        account,
+        Some(new_owner),
+        spl_token::instruction::AuthorityType::AccountOwner,
+        owner,
+        signers,
+    )?;
+    Ok(for_token_program(instruction, token_program_id))
+}
+
+/// Approves delegate to transfer amount out of account on behalf of its owner
+pub fn approve(
+    token_program_id: &Pubkey,
+    account: &Pubkey,
+    delegate: &Pubkey,
+    owner: &Pubkey,
+    signers: &[&Pubkey],
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::approve(
+        &spl_token::id(),
+        account,
+        delegate,
+        owner,
+        signers,
+        amount,
+    )?;
+    Ok(for_token_program(instruction, token_program_id))
+}
+
+pub fn revoke(
+    token_program_id: &Pubkey,
+    account: &Pubkey,
+    owner: &Pubkey,
+    signers: &[&Pubkey],
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::revoke(&spl_token::id(), account, owner, signers)?;
+    Ok(for_token_program(instruction, token_program_id))
+}
+
+pub fn close_account(
+    token_program_id: &Pubkey,
+    account: &Pubkey,
+    destination: &Pubkey,
+    owner: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let instruction =
+        spl_token::instruction::close_account(&spl_token::id(), account, destination, owner, &[])?;
+    Ok(for_token_program(instruction, token_program_id))
+}
+
+pub fn mint_to(
+    token_program_id: &Pubkey,
+    mint: &Pubkey,
+    account: &Pubkey,
+    mint_authority: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::mint_to(
+        &spl_token::id(),
+        mint,
+        account,
+        mint_authority,
+        &[],
+        amount,
+    )?;
+    Ok(for_token_program(instruction, token_program_id))
+}
+
+/// Fixes the mint's supply for good by removing its mint authority
+pub fn remove_mint_authority(
+    token_program_id: &Pubkey,
+    mint: &Pubkey,
+    mint_authority: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::set_authority(
+        &spl_token::id(),
+        mint,
+        None,
+        spl_token::instruction::AuthorityType::MintTokens,
+        mint_authority,
+        &[],
+    )?;
+    Ok(for_token_program(instruction, token_program_id))
+}
+
+pub fn burn(
+    token_program_id: &Pubkey,
+    account: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let instruction =
+        spl_token::instruction::burn(&spl_token::id(), account, mint, owner, &[], amount)?;
+    Ok(for_token_program(instruction, token_program_id))
+}
+
+/// Updates the token amount of a native mint account to its lamports above rent
+pub fn sync_native(
+    token_program_id: &Pubkey,
+    account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let instruction = spl_token::instruction::sync_native(&spl_token::id(), account)?;
+    Ok(for_token_program(instruction, token_program_id))
+}
+
+/// The associated token account of wallet for mint, of either token program
+pub fn get_associated_token_address(
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[wallet.as_ref(), token_program_id.as_ref(), mint.as_ref()],
+        &associated_token::id(),
+    )
+    .0
+}
+
+/// Creates the associated token account of wallet for mint, fails if it already exists
+pub fn create_associated_token_account(
+    payer: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: associated_token::id(),
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(
+                get_associated_token_address(wallet, mint, token_program_id),
+                false,
+            ),
+            AccountMeta::new_readonly(*wallet, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*token_program_id, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+        ],
+        data: Vec::new(),
+    }
+}
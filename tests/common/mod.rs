@@ -1,6 +1,7 @@
 // https://github.com/hashblock/solana-cli-program-template/blob/main/tests/common/mod.rs
 use {
-    solana_program::pubkey::Pubkey,
+    solana_program::{bpf_loader_upgradeable, pubkey::Pubkey, rent::Rent},
+    solana_sdk::account::{Account, AccountSharedData},
     solana_validator::test_validator::TestValidatorGenesis,
     std::{path::PathBuf, str::FromStr},
 };
@@ -26,3 +27,53 @@ pub fn clean_ledger_setup_validator(program_key: &Pubkey) -> TestValidatorGenesi
     }
     setup_validator(program_key)
 }
+
+/// Setup the test validator with the program deployed by the upgradeable loader, for the
+/// instructions only its upgrade authority may send
+pub fn clean_ledger_setup_upgradeable_validator(
+    program_key: &Pubkey,
+    upgrade_authority: &Pubkey,
+) -> TestValidatorGenesis {
+    if PathBuf::from_str(LEDGER_PATH).unwrap().exists() {
+        std::fs::remove_dir_all(LEDGER_PATH).unwrap();
+    }
+    let mut test_validator = TestValidatorGenesis::default();
+    test_validator.ledger_path(LEDGER_PATH);
+    for (address, account) in upgradeable_program_accounts(program_key, upgrade_authority) {
+        test_validator.add_account(address, AccountSharedData::from(account));
+    }
+    test_validator
+}
+
+/// The program account and the ProgramData account holding the program's ELF with
+/// upgrade_authority as its upgrade authority, as the upgradeable loader deploys a program
+pub fn upgradeable_program_accounts(
+    program_key: &Pubkey,
+    upgrade_authority: &Pubkey,
+) -> Vec<(Pubkey, Account)> {
+    let program_path = solana_program_test::find_file(&format!("{}.so", PROG_NAME))
+        .unwrap_or_else(|| panic!("Unable to locate program {}", PROG_NAME));
+    let (program_data_key, _) =
+        Pubkey::find_program_address(&[program_key.as_ref()], &bpf_loader_upgradeable::id());
+
+    // bincode serialized UpgradeableLoaderState::Program and ::ProgramData, the latter at slot 0
+    let mut program = 2u32.to_le_bytes().to_vec();
+    program.extend_from_slice(program_data_key.as_ref());
+    let mut program_data = 3u32.to_le_bytes().to_vec();
+    program_data.extend_from_slice(&0u64.to_le_bytes());
+    program_data.push(1);
+    program_data.extend_from_slice(upgrade_authority.as_ref());
+    program_data.extend_from_slice(&solana_program_test::read_file(program_path));
+
+    let account = |data: Vec<u8>, executable: bool| Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: bpf_loader_upgradeable::id(),
+        executable,
+        rent_epoch: 0,
+    };
+    vec![
+        (*program_key, account(program, true)),
+        (program_data_key, account(program_data, false)),
+    ]
+}
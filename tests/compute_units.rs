@@ -0,0 +1,478 @@
+#![cfg(feature = "test-bpf")]
+//! Measures the compute units every benchmarked instruction consumes, writes a report to
+//! target/compute_units.txt and fails if an instruction exceeds its budget in
+//! tests/compute_budgets.txt. New instructions need a benchmark here and a budget there.
+
+pub mod common;
+
+use solana_escrow::{
+    instruction::{
+        add_policy_mint, cancel_escrow, claim_htlc, exchange, init_config, init_escrow, init_htlc,
+        init_market, init_mint_policy, issue_attestation, remove_policy_mint, revoke_attestation,
+        set_admin, set_paused,
+    },
+    state::{Escrow, HashKind, Htlc, PolicyMode},
+};
+use solana_program::{hash::hashv, instruction::Instruction, program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::{BanksClient, ProgramTest};
+use solana_sdk::{
+    hash::Hash,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+const INITIAL_MINT_AMOUNT: u64 = 10_000;
+const BUDGETS: &str = include_str!("compute_budgets.txt");
+
+/// Keeps the runtime's "Program <id> consumed <x> of <y> compute units" log lines, which
+/// solana-program-test only hands to the logger
+struct ComputeUnitLogger {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl log::Log for ComputeUnitLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let line = record.args().to_string();
+        if line.contains(" consumed ") {
+            self.lines.lock().unwrap().push(line);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+struct Measurement {
+    instruction: &'static str,
+    consumed: u64,
+    limit: u64,
+}
+
+struct Bench {
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: Hash,
+    program_id: Pubkey,
+    lines: Arc<Mutex<Vec<String>>>,
+    measurements: Vec<Measurement>,
+}
+
+impl Bench {
+    /// Deploys the program with the upgradeable loader, so upgrade_authority can send InitConfig
+    async fn start(upgrade_authority: &Pubkey) -> Self {
+        // installed before solana-program-test sets up its own logger, which then stays unused
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        log::set_boxed_logger(Box::new(ComputeUnitLogger {
+            lines: lines.clone(),
+        }))
+        .unwrap();
+
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::default();
+        for (address, account) in
+            common::upgradeable_program_accounts(&program_id, upgrade_authority)
+        {
+            program_test.add_account(address, account);
+        }
+        let (banks_client, payer, recent_blockhash) = program_test.start().await;
+        log::set_max_level(log::LevelFilter::Debug);
+        Bench {
+            banks_client,
+            payer,
+            recent_blockhash,
+            program_id,
+            lines,
+            measurements: Vec::new(),
+        }
+    }
+
+    /// Processes a setup transaction that isn't measured
+    async fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), TransactionError> {
+        let mut transaction = Transaction::new_with_payer(instructions, Some(&self.payer.pubkey()));
+        let mut all_signers = vec![&self.payer];
+        all_signers.extend_from_slice(signers);
+        transaction.sign(&all_signers, self.recent_blockhash);
+        self.banks_client
+            .process_transaction(transaction)
+            .await
+            .map_err(|err| err.unwrap())
+    }
+
+    /// Processes the instruction on its own and records the compute units the escrow program
+    /// consumed for it, CPIs included
+    async fn measure(
+        &mut self,
+        name: &'static str,
+        instruction: Instruction,
+        signers: &[&Keypair],
+    ) {
+        assert!(
+            self.measurements.iter().all(|m| m.instruction != name),
+            "{} is measured twice",
+            name
+        );
+        self.lines.lock().unwrap().clear();
+        self.process(&[instruction], signers)
+            .await
+            .unwrap_or_else(|err| panic!("{} failed: {:?}", name, err));
+
+        // the escrow program finishes after the programs it invokes, so its line comes last
+        let program_id = self.program_id.to_string();
+        let (consumed, limit) = self
+            .lines
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|line| parse_consumed(line, &program_id))
+            .last()
+            .unwrap_or_else(|| {
+                panic!(
+                    "no compute units logged for {}, run the benchmark with cargo test-bpf",
+                    name
+                )
+            });
+        self.measurements.push(Measurement {
+            instruction: name,
+            consumed,
+            limit,
+        });
+    }
+
+    async fn create_mint(&mut self, mint_owner: &Keypair) -> Pubkey {
+        let mint = Keypair::new();
+        let rent = self.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            system_instruction::create_account(
+                &self.payer.pubkey(),
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &mint_owner.pubkey(),
+                None,
+                0,
+            )
+            .unwrap(),
+        ];
+        self.process(&instructions, &[&mint]).await.unwrap();
+        mint.pubkey()
+    }
+
+    /// Creates a token account of mint owned by owner and mints amount to it
+    async fn create_token_account(
+        &mut self,
+        mint: &Pubkey,
+        mint_owner: &Keypair,
+        owner: &Pubkey,
+        amount: u64,
+    ) -> Pubkey {
+        let account = Keypair::new();
+        let rent = self.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            system_instruction::create_account(
+                &self.payer.pubkey(),
+                &account.pubkey(),
+                rent.minimum_balance(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                &account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+            spl_token::instruction::mint_to(
+                &spl_token::id(),
+                mint,
+                &account.pubkey(),
+                &mint_owner.pubkey(),
+                &[],
+                amount,
+            )
+            .unwrap(),
+        ];
+        self.process(&instructions, &[&account, mint_owner])
+            .await
+            .unwrap();
+        account.pubkey()
+    }
+
+    /// Creates a zeroed account of the escrow program to initialize
+    async fn create_info_account(&mut self, space: usize) -> Pubkey {
+        let info_account = Keypair::new();
+        let rent = self.banks_client.get_rent().await.unwrap();
+        let instruction = system_instruction::create_account(
+            &self.payer.pubkey(),
+            &info_account.pubkey(),
+            rent.minimum_balance(space),
+            space as u64,
+            &self.program_id,
+        );
+        self.process(&[instruction], &[&info_account])
+            .await
+            .unwrap();
+        info_account.pubkey()
+    }
+}
+
+/// Reads (consumed, limit) from a "Program <id> consumed <x> of <y> compute units" line
+fn parse_consumed(line: &str, program_id: &str) -> Option<(u64, u64)> {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    match words.as_slice() {
+        ["Program", id, "consumed", consumed, "of", limit, "compute", "units"]
+            if *id == program_id =>
+        {
+            Some((consumed.parse().ok()?, limit.parse().ok()?))
+        }
+        _ => None,
+    }
+}
+
+/// Reads the "<instruction> <budget>" lines, skipping blank lines and # comments
+fn parse_budgets(budgets: &str) -> BTreeMap<&str, u64> {
+    budgets
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut words = line.split_whitespace();
+            let instruction = words.next().unwrap();
+            let budget = words
+                .next()
+                .and_then(|budget| budget.parse().ok())
+                .unwrap_or_else(|| panic!("invalid budget line: {}", line));
+            (instruction, budget)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_compute_units() {
+    let admin = Keypair::new();
+    let mut bench = Bench::start(&admin.pubkey()).await;
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let mint_owner = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+
+    let funding =
+        system_instruction::transfer(&bench.payer.pubkey(), &admin.pubkey(), 1_000_000_000);
+    bench.process(&[funding], &[]).await.unwrap();
+
+    let mint0 = bench.create_mint(&mint_owner).await;
+    let mint1 = bench.create_mint(&mint_owner).await;
+    let maker_token0 = bench
+        .create_token_account(&mint0, &mint_owner, &maker.pubkey(), INITIAL_MINT_AMOUNT)
+        .await;
+    let maker_token1 = bench
+        .create_token_account(&mint1, &mint_owner, &maker.pubkey(), 0)
+        .await;
+    let taker_token0 = bench
+        .create_token_account(&mint0, &mint_owner, &taker.pubkey(), 0)
+        .await;
+    let taker_token1 = bench
+        .create_token_account(&mint1, &mint_owner, &taker.pubkey(), INITIAL_MINT_AMOUNT)
+        .await;
+    let (pda, _bump_seed) = Pubkey::find_program_address(&[b"escrow"], &bench.program_id);
+    let program_id = bench.program_id;
+
+    // admin instructions
+    let instruction = init_config(&program_id, &admin.pubkey(), 100).unwrap();
+    bench.measure("InitConfig", instruction, &[&admin]).await;
+    let instruction = set_paused(&program_id, &admin.pubkey(), false).unwrap();
+    bench.measure("SetPaused", instruction, &[&admin]).await;
+    // hand the role over and back, the admin sends the remaining admin instructions
+    let new_admin = Keypair::new();
+    let instruction = set_admin(&program_id, &admin.pubkey(), &new_admin.pubkey()).unwrap();
+    bench
+        .measure("SetAdmin", instruction, &[&admin, &new_admin])
+        .await;
+    let instruction = set_admin(&program_id, &new_admin.pubkey(), &admin.pubkey()).unwrap();
+    bench
+        .process(&[instruction], &[&new_admin, &admin])
+        .await
+        .unwrap();
+    let instruction =
+        init_mint_policy(&program_id, &admin.pubkey(), PolicyMode::Blocklist).unwrap();
+    bench
+        .measure("InitMintPolicy", instruction, &[&admin])
+        .await;
+    let blocked_mint = bench.create_mint(&mint_owner).await;
+    let instruction = add_policy_mint(&program_id, &admin.pubkey(), &blocked_mint).unwrap();
+    bench.measure("AddPolicyMint", instruction, &[&admin]).await;
+    let instruction = remove_policy_mint(&program_id, &admin.pubkey(), &blocked_mint).unwrap();
+    bench
+        .measure("RemovePolicyMint", instruction, &[&admin])
+        .await;
+
+    let instruction = init_market(&program_id, &bench.payer.pubkey(), &mint0, &mint1).unwrap();
+    bench.measure("InitMarket", instruction, &[]).await;
+
+    // a plain escrow that is taken
+    let tmp_token0 = bench
+        .create_token_account(&mint0, &mint_owner, &maker.pubkey(), escrow_token0_amount)
+        .await;
+    let escrow_info = bench.create_info_account(Escrow::LEN).await;
+    let instruction = init_escrow(
+        &program_id,
+        &maker.pubkey(),
+        &tmp_token0,
+        &maker_token1,
+        &escrow_info,
+        &spl_token::id(),
+        escrow_token1_amount,
+    )
+    .unwrap();
+    bench.measure("InitEscrow", instruction, &[&maker]).await;
+    let instruction = exchange(
+        &program_id,
+        &taker.pubkey(),
+        &taker_token1,
+        &taker_token0,
+        &tmp_token0,
+        &maker.pubkey(),
+        &maker_token1,
+        &escrow_info,
+        &spl_token::id(),
+        &pda,
+        escrow_token0_amount,
+    )
+    .unwrap();
+    bench.measure("Exchange", instruction, &[&taker]).await;
+
+    // a plain escrow that is cancelled
+    let tmp_token0 = bench
+        .create_token_account(&mint0, &mint_owner, &maker.pubkey(), escrow_token0_amount)
+        .await;
+    let escrow_info = bench.create_info_account(Escrow::LEN).await;
+    let instruction = init_escrow(
+        &program_id,
+        &maker.pubkey(),
+        &tmp_token0,
+        &maker_token1,
+        &escrow_info,
+        &spl_token::id(),
+        escrow_token1_amount,
+    )
+    .unwrap();
+    bench.process(&[instruction], &[&maker]).await.unwrap();
+    let instruction = cancel_escrow(
+        &program_id,
+        &maker.pubkey(),
+        &tmp_token0,
+        &maker_token0,
+        &escrow_info,
+        &spl_token::id(),
+        &pda,
+        None,
+        &[],
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    bench.measure("CancelEscrow", instruction, &[&maker]).await;
+
+    // attestations
+    let instruction =
+        issue_attestation(&program_id, &admin.pubkey(), &taker.pubkey(), i64::MAX).unwrap();
+    bench
+        .measure("IssueAttestation", instruction, &[&admin])
+        .await;
+    let instruction = revoke_attestation(&program_id, &admin.pubkey(), &taker.pubkey()).unwrap();
+    bench
+        .measure("RevokeAttestation", instruction, &[&admin])
+        .await;
+
+    // a hash time-locked escrow that is claimed
+    let preimage = b"benchmark secret".to_vec();
+    let tmp_token0 = bench
+        .create_token_account(&mint0, &mint_owner, &maker.pubkey(), escrow_token0_amount)
+        .await;
+    let htlc_info = bench.create_info_account(Htlc::LEN).await;
+    let instruction = init_htlc(
+        &program_id,
+        &maker.pubkey(),
+        &tmp_token0,
+        &taker_token0,
+        &htlc_info,
+        &spl_token::id(),
+        HashKind::Sha256,
+        hashv(&[&preimage]).to_bytes(),
+        i64::MAX,
+    )
+    .unwrap();
+    bench.measure("InitHtlc", instruction, &[&maker]).await;
+    let instruction = claim_htlc(
+        &program_id,
+        &tmp_token0,
+        &taker_token0,
+        &maker.pubkey(),
+        &htlc_info,
+        &spl_token::id(),
+        &pda,
+        preimage,
+    )
+    .unwrap();
+    bench.measure("ClaimHtlc", instruction, &[]).await;
+
+    // every measured instruction needs a budget and every budget a measurement
+    let mut budgets = parse_budgets(BUDGETS);
+    let mut report = format!(
+        "{:<20} {:>10} {:>10} {:>10}\n",
+        "instruction", "consumed", "budget", "limit"
+    );
+    let mut violations = Vec::new();
+    for measurement in bench.measurements.iter() {
+        let budget = budgets.remove(measurement.instruction);
+        report.push_str(&format!(
+            "{:<20} {:>10} {:>10} {:>10}\n",
+            measurement.instruction,
+            measurement.consumed,
+            budget.map_or("-".to_string(), |budget| budget.to_string()),
+            measurement.limit,
+        ));
+        match budget {
+            None => violations.push(format!("{} has no budget", measurement.instruction)),
+            Some(budget) if measurement.consumed > budget => violations.push(format!(
+                "{} consumed {} compute units, over its budget of {}",
+                measurement.instruction, measurement.consumed, budget
+            )),
+            Some(_) => {}
+        }
+    }
+    for instruction in budgets.keys() {
+        violations.push(format!(
+            "{} has a budget but isn't benchmarked",
+            instruction
+        ));
+    }
+
+    let report_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/compute_units.txt");
+    fs::create_dir_all(report_path.parent().unwrap()).unwrap();
+    fs::write(&report_path, &report).unwrap();
+    println!("{}", report);
+
+    assert!(violations.is_empty(), "{}", violations.join("\n"));
+}
@@ -0,0 +1,22 @@
+#![cfg(feature = "test-bpf")]
+//! Checks that idl/solana_escrow.json matches the layouts generated from the schemas. Run with
+//! UPDATE_IDL set to regenerate it.
+
+use solana_escrow::idl::idl_json;
+use std::{env, fs, path::Path};
+
+const IDL: &str = include_str!("../idl/solana_escrow.json");
+
+#[test]
+fn test_idl_up_to_date() {
+    let idl = idl_json();
+    if env::var_os("UPDATE_IDL").is_some() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("idl/solana_escrow.json");
+        fs::write(path, &idl).unwrap();
+        return;
+    }
+    assert!(
+        idl == IDL,
+        "idl/solana_escrow.json is out of date, regenerate it with UPDATE_IDL=1 cargo test-bpf --test idl"
+    );
+}
@@ -2,17 +2,38 @@
 
 pub mod common;
 
-use common::clean_ledger_setup_validator;
-use solana_client::rpc_client::RpcClient;
+use common::{clean_ledger_setup_upgradeable_validator, clean_ledger_setup_validator};
+use solana_client::{client_error::ClientError, rpc_client::RpcClient};
 use solana_escrow::{
-    instruction::{exchange, init_escrow},
-    state::Escrow,
+    instruction::{
+        accept_counter, add_policy_mint, approve_milestone, batch_exchange, cancel_escrow,
+        cancel_milestone_escrow, cancel_multisig_escrow, claim_htlc, create_recipient_account,
+        exchange, exchange_basket, exchange_gated, exchange_multi_asset, exchange_multisig,
+        exchange_oracle, exchange_sol, exchange_standing_order, exchange_with_atas,
+        exercise_option, expire_escrow, init_arbiter_escrow, init_ata_escrow, init_basket_escrow,
+        init_config, init_delegated_escrow, init_escrow, init_gated_escrow, init_htlc, init_market,
+        init_milestone_escrow, init_mint_policy, init_multi_asset_escrow, init_multisig_escrow,
+        init_oracle_escrow, init_sol_escrow, init_standing_order, init_vesting, issue_attestation,
+        match_escrows, propose_counter, raise_dispute, reclaim_option_collateral, reject_counter,
+        remove_policy_mint, resolve_dispute, revoke_attestation, revoke_vesting, set_admin,
+        set_paused, withdraw_counter, withdraw_vested, write_option, BatchExchangeEscrow,
+        EscrowInstruction,
+    },
+    oracle::PriceFeed,
+    state::{
+        AccountKind, ArbiterEscrow, CounterOffer, CoveredOption, Escrow, HashKind, Htlc, Market,
+        MilestoneEscrow, OptionKind, OracleQuote, PolicyMode, Refill, Vesting, LEGACY_ESCROW_LEN,
+        MAX_EXTRA_ASKS,
+    },
+    token::get_associated_token_address,
 };
 use solana_program::{
+    hash::hashv,
     program_pack::Pack, // required if we want to use the Pack trait functions on spl_token's Account
     pubkey::Pubkey,
 };
 use solana_sdk::{
+    account::{Account, AccountSharedData},
     hash::Hash,
     signature::{Keypair, Signer},
     system_instruction,
@@ -20,24 +41,3523 @@ use solana_sdk::{
 };
 use assert_matches::*;
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
 const INITIAL_MINT_AMOUNT: u64 = 10_000;
 #[test]
-fn test_escrow() {
+fn test_escrow() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    // maker puts up escrow_token0_amount, wants to trade for escrow_token1_amount
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+
+    // 0. create two tokens (mint0/1) and mint some tokens to maker & taker's wallet accounts
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    // 1. create maker's tmp token0 balance account and sends escrow amount to it
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+
+    // 2. create empty account owned by escrow program and call solana_escrow's InitEscrow entrypoint
+    let escrow_info_pubkey = start_escrow(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &prog_key,
+        &maker,
+        &tmp_token0,
+        &maker_token1,
+        escrow_token1_amount,
+    );
+
+    // 3. finish escrow by calling solana_escrow's Exchange entrypoint
+    finish_escrow(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &prog_key,
+        &escrow_info_pubkey,
+        &taker,
+        &taker_token0,
+        &taker_token1,
+        escrow_token0_amount,
+    );
+
+    // maker send token0 and received token1. check if all balances are correct
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - escrow_token1_amount);
+}
+
+#[test]
+fn test_legacy_layouts() {
+    let prog_key = Pubkey::new_unique();
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    // an escrow of the layout before Borsh, written into the genesis with its vault
+    let maker = Keypair::new();
+    let legacy_mint = Pubkey::new_unique();
+    let legacy_vault = Pubkey::new_unique();
+    let legacy_maker_token0 = Pubkey::new_unique();
+    let legacy_escrow = Pubkey::new_unique();
+    let legacy_amount: u64 = 100;
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: Some(Pubkey::new_unique()).into(),
+            supply: legacy_amount,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: None.into(),
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    let token_account_data = |owner: &Pubkey, amount: u64| {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(
+            spl_token::state::Account {
+                mint: legacy_mint,
+                owner: *owner,
+                amount,
+                state: spl_token::state::AccountState::Initialized,
+                ..spl_token::state::Account::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        data
+    };
+    let mut escrow_data = vec![0; LEGACY_ESCROW_LEN];
+    Escrow::pack_legacy(
+        Escrow {
+            is_initialized: true,
+            maker_pubkey: maker.pubkey(),
+            tmp_token0_pubkey: legacy_vault,
+            maker_token1_pubkey: Pubkey::new_unique(),
+            maker_token1_expected_amount: 200,
+            ..Escrow::default()
+        },
+        &mut escrow_data,
+    )
+    .unwrap();
+    let mut validator_genesis = clean_ledger_setup_validator(&prog_key);
+    for (pubkey, data, owner) in vec![
+        (legacy_mint, mint_data, spl_token::id()),
+        (
+            legacy_vault,
+            token_account_data(&pda_pubkey, legacy_amount),
+            spl_token::id(),
+        ),
+        (
+            legacy_maker_token0,
+            token_account_data(&maker.pubkey(), 0),
+            spl_token::id(),
+        ),
+        (legacy_escrow, escrow_data, prog_key),
+    ] {
+        validator_genesis.add_account(
+            pubkey,
+            AccountSharedData::from(Account {
+                lamports: 1_000_000_000,
+                data,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+            }),
+        );
+    }
+    let (test_validator, payer) = validator_genesis.start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    // the legacy escrow can still be cancelled, the legacy layout only has InitEscrow and Exchange
+    // so CancelEscrow is sent with Borsh
+    let cancel = cancel_escrow(
+        &prog_key,
+        &maker.pubkey(),
+        &legacy_vault,
+        &legacy_maker_token0,
+        &legacy_escrow,
+        &spl_token::id(),
+        &pda_pubkey,
+        None,
+        &[],
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    assert!(EscrowInstruction::unpack(&cancel.data)
+        .unwrap()
+        .pack_legacy()
+        .is_err());
+    let mut transaction = Transaction::new_with_payer(&[cancel], Some(&payer.pubkey()));
+    transaction.sign(&[&payer, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    let balance = get_token_balance(&rpc_client, &legacy_maker_token0);
+    assert_eq!(balance, legacy_amount);
+
+    // legacy instructions create and take new escrows, which are stored with Borsh
+    let taker = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+
+    let escrow_info = Keypair::new();
+    let mut init = init_escrow(
+        &prog_key,
+        &maker.pubkey(),
+        &tmp_token0,
+        &maker_token1,
+        &escrow_info.pubkey(),
+        &spl_token::id(),
+        escrow_token1_amount,
+    )
+    .unwrap();
+    init.data = EscrowInstruction::unpack(&init.data)
+        .unwrap()
+        .pack_legacy()
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                rpc_client
+                    .get_minimum_balance_for_rent_exemption(Escrow::LEN)
+                    .unwrap(),
+                Escrow::LEN as u64,
+                &prog_key,
+            ),
+            init,
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    let escrow_info_account = rpc_client.get_account(&escrow_info.pubkey()).unwrap();
+    assert_eq!(escrow_info_account.data[0], AccountKind::BorshEscrow as u8);
+
+    let mut take = exchange(
+        &prog_key,
+        &taker.pubkey(),
+        &taker_token1,
+        &taker_token0,
+        &tmp_token0,
+        &maker.pubkey(),
+        &maker_token1,
+        &escrow_info.pubkey(),
+        &spl_token::id(),
+        &pda_pubkey,
+        escrow_token0_amount,
+    )
+    .unwrap();
+    take.data = EscrowInstruction::unpack(&take.data)
+        .unwrap()
+        .pack_legacy()
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(&[take], Some(&payer.pubkey()));
+    transaction.sign(&[&payer, &taker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token1_amount);
+}
+
+#[test]
+fn test_batch_exchange() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    // two offers of (token0, token1) amounts the taker sweeps at once
+    let offers: [(u64, u64); 2] = [(100, 200), (50, 120)];
+
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let mut escrows = Vec::new();
+    for (escrow_token0_amount, escrow_token1_amount) in offers.iter() {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            *escrow_token0_amount,
+        );
+        let escrow_info = start_escrow(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &prog_key,
+            &maker,
+            &tmp_token0,
+            &maker_token1,
+            *escrow_token1_amount,
+        );
+        escrows.push(BatchExchangeEscrow {
+            pda_tmp_token0: tmp_token0,
+            maker: maker.pubkey(),
+            maker_token1,
+            escrow_info,
+            market: None,
+            amount: *escrow_token0_amount,
+        });
+    }
+    let total_token0_amount: u64 = offers.iter().map(|offer| offer.0).sum();
+    let total_token1_amount: u64 = offers.iter().map(|offer| offer.1).sum();
+
+    // a cap below the total fails the whole batch, the exact total settles all escrows
+    for (max_total_amount, should_succeed) in vec![
+        (total_token1_amount - 1, false),
+        (total_token1_amount, true),
+    ] {
+        let mut transaction = Transaction::new_with_payer(
+            &[batch_exchange(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                &spl_token::id(),
+                &pda_pubkey,
+                &escrows,
+                max_total_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        assert_eq!(
+            rpc_client
+                .send_and_confirm_transaction(&transaction)
+                .is_ok(),
+            should_succeed
+        );
+    }
+
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - total_token0_amount);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + total_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + total_token0_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - total_token1_amount);
+}
+
+#[test]
+fn test_match() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    // maker_a offers token0 for token1, maker_b offers token1 for token0
+    let maker_a = Keypair::new();
+    let maker_b = Keypair::new();
+    let (mint0, mint1, _mint_owner, maker_a_token0, maker_b_token0, maker_a_token1, maker_b_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker_a, &maker_b);
+    let cranker_token0 = create_token_account(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &payer.pubkey(),
+    );
+    let cranker_token1 = create_token_account(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint1,
+        &payer.pubkey(),
+    );
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    // maker_a gives 100 token0 for 200 token1
+    let tmp_token_a = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker_a,
+        &maker_a_token0,
+        100,
+    );
+    let escrow_a = start_escrow(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &prog_key,
+        &maker_a,
+        &tmp_token_a,
+        &maker_a_token1,
+        200,
+    );
+    // maker_b offers (token1, token0) amounts, the first one wants more token0 than maker_a gives
+    let mut offers_b = Vec::new();
+    for (token1_amount, token0_amount) in [(150, 120), (250, 90)].iter() {
+        let tmp_token_b = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint1,
+            &maker_b,
+            &maker_b_token1,
+            *token1_amount,
+        );
+        let escrow_b = start_escrow(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &prog_key,
+            &maker_b,
+            &tmp_token_b,
+            &maker_b_token0,
+            *token0_amount,
+        );
+        offers_b.push((escrow_b, tmp_token_b));
+    }
+
+    // the same offer asking for an extra token on top, which Match doesn't settle
+    let tmp_token_b = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint1,
+        &maker_b,
+        &maker_b_token1,
+        250,
+    );
+    let escrow_info = Keypair::new();
+    let state_space: u64 = Escrow::packed_len(0, 1) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_multi_asset_escrow(
+                &prog_key,
+                &maker_b.pubkey(),
+                &tmp_token_b,
+                &[],
+                &maker_b_token0,
+                &[(maker_b_token1, 1)],
+                &escrow_info.pubkey(),
+                &spl_token::id(),
+                None,
+                None,
+                None,
+                90,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker_b], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    offers_b.push((escrow_info.pubkey(), tmp_token_b));
+
+    let settle = |escrow_b: &Pubkey, tmp_token_b: &Pubkey| {
+        let mut transaction = Transaction::new_with_payer(
+            &[match_escrows(
+                &prog_key,
+                &escrow_a,
+                &tmp_token_a,
+                &maker_a.pubkey(),
+                &maker_a_token1,
+                escrow_b,
+                tmp_token_b,
+                &maker_b.pubkey(),
+                &maker_b_token0,
+                &spl_token::id(),
+                &pda_pubkey,
+                Some((&cranker_token0, &cranker_token1)),
+                None,
+                None,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // an escrow can't be matched against itself
+    let mut transaction = Transaction::new_with_payer(
+        &[match_escrows(
+            &prog_key,
+            &escrow_a,
+            &tmp_token_a,
+            &maker_a.pubkey(),
+            &maker_a_token1,
+            &escrow_a,
+            &tmp_token_a,
+            &maker_a.pubkey(),
+            &maker_a_token1,
+            &spl_token::id(),
+            &pda_pubkey,
+            None,
+            None,
+            None,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert!(rpc_client
+        .send_and_confirm_transaction(&transaction)
+        .is_err());
+    // 100 token0 don't cover the 120 the first offer asks for
+    assert!(settle(&offers_b[0].0, &offers_b[0].1).is_err());
+    // the extra ask makes the third offer unsupported although the prices cross
+    assert!(settle(&offers_b[2].0, &offers_b[2].1).is_err());
+    // the second one crosses, each maker gets what they asked for and the cranker the rest
+    assert_matches!(settle(&offers_b[1].0, &offers_b[1].1), Ok(_));
+
+    assert!(rpc_client.get_account(&escrow_a).is_err());
+    assert!(rpc_client.get_account(&offers_b[1].0).is_err());
+    assert!(rpc_client.get_account(&offers_b[0].0).is_ok());
+    assert!(rpc_client.get_account(&offers_b[2].0).is_ok());
+
+    let balance = get_token_balance(&rpc_client, &maker_a_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + 200);
+    let balance = get_token_balance(&rpc_client, &maker_b_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + 90);
+    let balance = get_token_balance(&rpc_client, &cranker_token0);
+    assert_eq!(balance, 100 - 90);
+    let balance = get_token_balance(&rpc_client, &cranker_token1);
+    assert_eq!(balance, 250 - 200);
+}
+
+#[test]
+fn test_market() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    // (token0, token1) amounts, the second offer is the cheaper one
+    let offers: [(u64, u64); 2] = [(100, 200), (100, 150)];
+
+    let (mint0, mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let (market_pubkey, _) = Market::find_address(&mint0, &mint1, &prog_key);
+    let mut transaction = Transaction::new_with_payer(
+        &[init_market(&prog_key, &payer.pubkey(), &mint0, &mint1).unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let state_space: u64 = (Escrow::packed_len(0, 0) + Escrow::MARKET_SECTION_LEN) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut escrows = Vec::new();
+    for (escrow_token0_amount, escrow_token1_amount) in offers.iter() {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            *escrow_token0_amount,
+        );
+        let escrow_info = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &escrow_info.pubkey(),
+                    account_lamports,
+                    state_space,
+                    &prog_key,
+                ),
+                init_multi_asset_escrow(
+                    &prog_key,
+                    &maker.pubkey(),
+                    &tmp_token0,
+                    &[],
+                    &maker_token1,
+                    &[],
+                    &escrow_info.pubkey(),
+                    &spl_token::id(),
+                    None,
+                    Some(&market_pubkey),
+                    None,
+                    *escrow_token1_amount,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+        assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+        escrows.push((escrow_info.pubkey(), tmp_token0));
+    }
+
+    // the cheaper offer is listed first although it was listed last
+    let market_account = rpc_client.get_account(&market_pubkey).unwrap();
+    let market = Market::unpack(market_account.data.as_slice()).unwrap();
+    let listed: Vec<Pubkey> = market.entries.iter().map(|e| e.escrow_pubkey).collect();
+    assert_eq!(listed, vec![escrows[1].0, escrows[0].0]);
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    // the maker cancels the cheaper offer and gets the tokens back
+    let mut transaction = Transaction::new_with_payer(
+        &[cancel_escrow(
+            &prog_key,
+            &maker.pubkey(),
+            &escrows[1].1,
+            &maker_token0,
+            &escrows[1].0,
+            &spl_token::id(),
+            &pda_pubkey,
+            None,
+            &[],
+            None,
+            Some(&market_pubkey),
+            None,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    // the taker takes the remaining one, leaving the market empty
+    let mut transaction = Transaction::new_with_payer(
+        &[exchange_multi_asset(
+            &prog_key,
+            &taker.pubkey(),
+            &taker_token1,
+            &taker_token0,
+            &escrows[0].1,
+            &[],
+            &maker.pubkey(),
+            &maker_token1,
+            &[],
+            &escrows[0].0,
+            &spl_token::id(),
+            &pda_pubkey,
+            None,
+            Some(&market_pubkey),
+            None,
+            offers[0].0,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &taker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let market_account = rpc_client.get_account(&market_pubkey).unwrap();
+    let market = Market::unpack(market_account.data.as_slice()).unwrap();
+    assert!(market.entries.is_empty());
+
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - offers[0].0);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + offers[0].1);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + offers[0].0);
+}
+
+#[test]
+fn test_referral_fee() {
+    let prog_key = Pubkey::new_unique();
+    let admin = Keypair::new();
+    let (test_validator, payer) =
+        clean_ledger_setup_upgradeable_validator(&prog_key, &admin.pubkey()).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+    let max_referral_fee_bps: u16 = 100;
+
+    let (mint0, mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    // only the program's upgrade authority can create the config and become its admin
+    let create_config_by = |signer: &Keypair| {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::transfer(&payer.pubkey(), &signer.pubkey(), 1_000_000_000),
+                init_config(&prog_key, &signer.pubkey(), max_referral_fee_bps).unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, signer], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    assert!(create_config_by(&taker).is_err());
+    assert_matches!(create_config_by(&admin), Ok(_));
+
+    // the partner wallet's token1 account
+    let referrer = Keypair::new();
+    let referrer_token1 = Keypair::new();
+    let state_space = spl_token::state::Account::LEN;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space)
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &referrer_token1.pubkey(),
+                account_lamports,
+                state_space as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                &referrer_token1.pubkey(),
+                &mint1,
+                &referrer.pubkey(),
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &referrer_token1], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let mut escrows = Vec::new();
+    for _ in 0..2 {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            escrow_token0_amount,
+        );
+        let escrow_info = start_escrow(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &prog_key,
+            &maker,
+            &tmp_token0,
+            &maker_token1,
+            escrow_token1_amount,
+        );
+        escrows.push((escrow_info, tmp_token0));
+    }
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let take = |(escrow_info, tmp_token0): &(Pubkey, Pubkey),
+                referrer_token1: &Pubkey,
+                referral_fee_bps: u16| {
+        let mut transaction = Transaction::new_with_payer(
+            &[exchange_multi_asset(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                tmp_token0,
+                &[],
+                &maker.pubkey(),
+                &maker_token1,
+                &[],
+                escrow_info,
+                &spl_token::id(),
+                &pda_pubkey,
+                None,
+                None,
+                Some((referrer_token1, referral_fee_bps)),
+                escrow_token0_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // a fee above the config's cap fails, the cap itself goes through
+    let referrer_token1 = referrer_token1.pubkey();
+    assert!(take(&escrows[0], &referrer_token1, max_referral_fee_bps + 1).is_err());
+    assert_matches!(
+        take(&escrows[0], &referrer_token1, max_referral_fee_bps),
+        Ok(_)
+    );
+
+    // 1% of the token1 leg goes to the referrer on top of the asked amount
+    let referral_fee = escrow_token1_amount * max_referral_fee_bps as u64 / 10_000;
+    let balance = get_token_balance(&rpc_client, &referrer_token1);
+    assert_eq!(balance, referral_fee);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT - escrow_token1_amount - referral_fee
+    );
+
+    // a taker naming their own account as referrer only pays the fee to themselves,
+    // the maker still receives the whole asked amount
+    assert_matches!(
+        take(&escrows[1], &taker_token1, max_referral_fee_bps),
+        Ok(_)
+    );
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + 2 * escrow_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT - 2 * escrow_token1_amount - referral_fee
+    );
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + 2 * escrow_token0_amount);
+}
+
+#[test]
+fn test_emergency_pause() {
+    let prog_key = Pubkey::new_unique();
+    let admin = Keypair::new();
+    let (test_validator, payer) =
+        clean_ledger_setup_upgradeable_validator(&prog_key, &admin.pubkey()).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::transfer(&payer.pubkey(), &admin.pubkey(), 1_000_000_000),
+            init_config(&prog_key, &admin.pubkey(), 0).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &admin], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    // two escrows created before the pause
+    let mut escrows = Vec::new();
+    for _ in 0..2 {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            escrow_token0_amount,
+        );
+        let escrow_info = start_escrow(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &prog_key,
+            &maker,
+            &tmp_token0,
+            &maker_token1,
+            escrow_token1_amount,
+        );
+        escrows.push((escrow_info, tmp_token0));
+    }
+
+    let set_paused_by = |signer: &Keypair, is_paused: bool| {
+        let mut transaction = Transaction::new_with_payer(
+            &[set_paused(&prog_key, &signer.pubkey(), is_paused).unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, signer], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let take = |(escrow_info, tmp_token0): &(Pubkey, Pubkey)| {
+        let mut transaction = Transaction::new_with_payer(
+            &[exchange(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                tmp_token0,
+                &maker.pubkey(),
+                &maker_token1,
+                escrow_info,
+                &spl_token::id(),
+                &pda_pubkey,
+                escrow_token0_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // only the admin can pause
+    assert!(set_paused_by(&maker, true).is_err());
+    assert_matches!(set_paused_by(&admin, true), Ok(_));
+
+    // while paused no escrow can be created or taken
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+    let escrow_info = Keypair::new();
+    let state_space = Escrow::LEN;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space)
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space as u64,
+                &prog_key,
+            ),
+            init_escrow(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &maker_token1,
+                &escrow_info.pubkey(),
+                &spl_token::id(),
+                escrow_token1_amount,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert!(rpc_client
+        .send_and_confirm_transaction(&transaction)
+        .is_err());
+    assert!(take(&escrows[0]).is_err());
+
+    // but the maker can still get out
+    let mut transaction = Transaction::new_with_payer(
+        &[cancel_escrow(
+            &prog_key,
+            &maker.pubkey(),
+            &escrows[1].1,
+            &maker_token0,
+            &escrows[1].0,
+            &spl_token::id(),
+            &pda_pubkey,
+            None,
+            &[],
+            None,
+            None,
+            None,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    assert!(rpc_client.get_account(&escrows[1].0).is_err());
+
+    // and trading resumes once unpaused
+    assert_matches!(set_paused_by(&admin, false), Ok(_));
+    assert_matches!(take(&escrows[0]), Ok(_));
+
+    // only the admin can hand the role over, after which the old admin can no longer pause
+    let new_admin = Keypair::new();
+    let set_admin_by = |signer: &Keypair| {
+        let mut transaction = Transaction::new_with_payer(
+            &[set_admin(&prog_key, &signer.pubkey(), &new_admin.pubkey()).unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, signer, &new_admin], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    assert!(set_admin_by(&maker).is_err());
+    assert_matches!(set_admin_by(&admin), Ok(_));
+    assert!(set_paused_by(&admin, true).is_err());
+    assert_matches!(set_paused_by(&new_admin, true), Ok(_));
+
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - 2 * escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token1_amount);
+}
+
+#[test]
+fn test_pause_gates_trades() {
+    let prog_key = Pubkey::new_unique();
+    let admin = Keypair::new();
+    let (test_validator, payer) =
+        clean_ledger_setup_upgradeable_validator(&prog_key, &admin.pubkey()).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    // every trade is 100 token0 for 200 token1
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    let (mint0, mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::transfer(&payer.pubkey(), &admin.pubkey(), 1_000_000_000),
+            init_config(&prog_key, &admin.pubkey(), 0).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &admin], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    // the maker's escrows opened before the pause: one for the batch, one for the match,
+    // one for the counter-offers
+    let mut escrows = Vec::new();
+    for _ in 0..3 {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            escrow_token0_amount,
+        );
+        let escrow_info = start_escrow(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &prog_key,
+            &maker,
+            &tmp_token0,
+            &maker_token1,
+            escrow_token1_amount,
+        );
+        escrows.push((escrow_info, tmp_token0));
+    }
+    // the taker's escrow crossing the maker's second one
+    let tmp_token1 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint1,
+        &taker,
+        &taker_token1,
+        escrow_token1_amount,
+    );
+    let crossing_escrow = start_escrow(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &prog_key,
+        &taker,
+        &tmp_token1,
+        &taker_token0,
+        escrow_token0_amount,
+    );
+
+    let propose = |escrow_info: &Pubkey| {
+        // the same helper locks the taker's token1
+        let tmp_token1 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint1,
+            &taker,
+            &taker_token1,
+            escrow_token1_amount,
+        );
+        let counter_info = Keypair::new();
+        let state_space = CounterOffer::LEN;
+        let account_lamports = rpc_client
+            .get_minimum_balance_for_rent_exemption(state_space)
+            .unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &counter_info.pubkey(),
+                    account_lamports,
+                    state_space as u64,
+                    &prog_key,
+                ),
+                propose_counter(
+                    &prog_key,
+                    &taker.pubkey(),
+                    &tmp_token1,
+                    &taker_token1,
+                    &taker_token0,
+                    escrow_info,
+                    &counter_info.pubkey(),
+                    &spl_token::id(),
+                    escrow_token0_amount,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &counter_info, &taker], recent_blockhash);
+        (
+            rpc_client.send_and_confirm_transaction(&transaction),
+            counter_info.pubkey(),
+            tmp_token1,
+        )
+    };
+    let (result, counter_info, counter_tmp_token1) = propose(&escrows[2].0);
+    assert_matches!(result, Ok(_));
+
+    // a call on 100 token0 at a strike of 200 token1 the maker writes and hands to the taker
+    let tmp_collateral = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+    let write = |tmp_collateral: &Pubkey| {
+        write_covered_option(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &prog_key,
+            &maker,
+            tmp_collateral,
+            &maker_token1,
+            OptionKind::Call,
+            escrow_token1_amount,
+            now + 3600,
+        )
+    };
+    let (option_info, option_mint, writer_option_token) = write(&tmp_collateral).unwrap();
+    let holder_option_token = create_token_account(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &option_mint,
+        &taker.pubkey(),
+    );
+    let mut transaction = Transaction::new_with_payer(
+        &[spl_token::instruction::transfer(
+            &spl_token::id(),
+            &writer_option_token,
+            &holder_option_token,
+            &maker.pubkey(),
+            &[],
+            1,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let set_paused_to = |is_paused: bool| {
+        let mut transaction = Transaction::new_with_payer(
+            &[set_paused(&prog_key, &admin.pubkey(), is_paused).unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &admin], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    let batch = || {
+        let mut transaction = Transaction::new_with_payer(
+            &[batch_exchange(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                &spl_token::id(),
+                &pda_pubkey,
+                &[BatchExchangeEscrow {
+                    pda_tmp_token0: escrows[0].1,
+                    maker: maker.pubkey(),
+                    maker_token1,
+                    escrow_info: escrows[0].0,
+                    market: None,
+                    amount: escrow_token0_amount,
+                }],
+                escrow_token1_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    let settle = || {
+        let mut transaction = Transaction::new_with_payer(
+            &[match_escrows(
+                &prog_key,
+                &escrows[1].0,
+                &escrows[1].1,
+                &maker.pubkey(),
+                &maker_token1,
+                &crossing_escrow,
+                &tmp_token1,
+                &taker.pubkey(),
+                &taker_token0,
+                &spl_token::id(),
+                &pda_pubkey,
+                None,
+                None,
+                None,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    let accept = || {
+        let mut transaction = Transaction::new_with_payer(
+            &[accept_counter(
+                &prog_key,
+                &maker.pubkey(),
+                &escrows[2].1,
+                &maker_token1,
+                &escrows[2].0,
+                &counter_info,
+                &counter_tmp_token1,
+                &taker_token0,
+                &taker.pubkey(),
+                &spl_token::id(),
+                &pda_pubkey,
+                None,
+                escrow_token1_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &maker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    let exercise = || {
+        let mut transaction = Transaction::new_with_payer(
+            &[exercise_option(
+                &prog_key,
+                &taker.pubkey(),
+                &holder_option_token,
+                &taker_token1,
+                &taker_token0,
+                &option_mint,
+                &tmp_collateral,
+                &maker_token1,
+                &maker.pubkey(),
+                &option_info,
+                &pda_pubkey,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // while paused nothing opens or fills a trade
+    assert_matches!(set_paused_to(true), Ok(_));
+    let new_tmp_collateral = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+    assert!(batch().is_err());
+    assert!(settle().is_err());
+    assert!(propose(&escrows[2].0).0.is_err());
+    assert!(accept().is_err());
+    assert!(write(&new_tmp_collateral).is_err());
+    assert!(exercise().is_err());
+
+    // and each of them goes through again once unpaused
+    assert_matches!(set_paused_to(false), Ok(_));
+    assert_matches!(batch(), Ok(_));
+    assert_matches!(settle(), Ok(_));
+    assert_matches!(propose(&escrows[2].0).0, Ok(_));
+    assert_matches!(accept(), Ok(_));
+    assert_matches!(write(&new_tmp_collateral), Ok(_));
+    assert_matches!(exercise(), Ok(_));
+
+    for (escrow_info, _tmp_token0) in escrows.iter() {
+        assert!(rpc_client.get_account(escrow_info).is_err());
+    }
+    assert!(rpc_client.get_account(&crossing_escrow).is_err());
+    assert!(rpc_client.get_account(&option_info).is_err());
+    // the batch, the match, the counter-offer and the exercise each moved 100 token0 to the taker
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + 4 * escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + 4 * escrow_token1_amount);
+}
+
+#[test]
+fn test_mint_policy() {
+    let prog_key = Pubkey::new_unique();
+    let admin = Keypair::new();
+    let (test_validator, payer) =
+        clean_ledger_setup_upgradeable_validator(&prog_key, &admin.pubkey()).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+
+    let (mint0, mint1, _mint_owner, maker_token0, _taker_token0, maker_token1, _taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    // an allowlist of only the deposited mint
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::transfer(&payer.pubkey(), &admin.pubkey(), 1_000_000_000),
+            init_config(&prog_key, &admin.pubkey(), 0).unwrap(),
+            init_mint_policy(&prog_key, &admin.pubkey(), PolicyMode::Allowlist).unwrap(),
+            add_policy_mint(&prog_key, &admin.pubkey(), &mint0).unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &admin], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let try_start_escrow = || {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            escrow_token0_amount,
+        );
+        let escrow_info = Keypair::new();
+        let state_space = Escrow::LEN;
+        let account_lamports = rpc_client
+            .get_minimum_balance_for_rent_exemption(state_space)
+            .unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &escrow_info.pubkey(),
+                    account_lamports,
+                    state_space as u64,
+                    &prog_key,
+                ),
+                init_escrow(
+                    &prog_key,
+                    &maker.pubkey(),
+                    &tmp_token0,
+                    &maker_token1,
+                    &escrow_info.pubkey(),
+                    &spl_token::id(),
+                    escrow_token1_amount,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    let update_policy_by = |signer: &Keypair, instruction| {
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        transaction.sign(&[&payer, signer], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // the asked mint isn't allowed yet
+    assert!(try_start_escrow().is_err());
+
+    // only the admin can allow it, and only once
+    assert!(update_policy_by(
+        &maker,
+        add_policy_mint(&prog_key, &maker.pubkey(), &mint1).unwrap()
+    )
+    .is_err());
+    assert_matches!(
+        update_policy_by(
+            &admin,
+            add_policy_mint(&prog_key, &admin.pubkey(), &mint1).unwrap()
+        ),
+        Ok(_)
+    );
+    assert!(update_policy_by(
+        &admin,
+        add_policy_mint(&prog_key, &admin.pubkey(), &mint1).unwrap()
+    )
+    .is_err());
+    assert_matches!(try_start_escrow(), Ok(_));
+
+    // removing the deposited mint blocks new escrows again
+    assert_matches!(
+        update_policy_by(
+            &admin,
+            remove_policy_mint(&prog_key, &admin.pubkey(), &mint0).unwrap()
+        ),
+        Ok(_)
+    );
+    assert!(try_start_escrow().is_err());
+}
+
+#[test]
+fn test_multisig_authorities() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+
+    let (mint0, mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    // treasuries of maker and taker, each a 2-of-3 multisig
+    let maker_signers = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let taker_signers = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let pubkeys = |signers: &[Keypair]| signers.iter().map(|s| s.pubkey()).collect::<Vec<_>>();
+    let (maker_signer_pubkeys, taker_signer_pubkeys) =
+        (pubkeys(&maker_signers), pubkeys(&taker_signers));
+    let maker_multisig = create_multisig(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &maker_signer_pubkeys.iter().collect::<Vec<_>>(),
+        2,
+    );
+    let taker_multisig = create_multisig(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &taker_signer_pubkeys.iter().collect::<Vec<_>>(),
+        2,
+    );
+
+    let treasury_token0 = create_token_account(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker_multisig,
+    );
+    let treasury_token1 = create_token_account(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint1,
+        &maker_multisig,
+    );
+    let taker_treasury_token0 = create_token_account(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &taker_multisig,
+    );
+    let taker_treasury_token1 = create_token_account(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint1,
+        &taker_multisig,
+    );
+
+    // two tmp accounts of the maker's treasury and the taker's treasury funds
+    let mut tmp_token0s = Vec::new();
+    for _ in 0..2 {
+        let tmp_token0 = create_token_account(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker_multisig,
+        );
+        let mut transaction = Transaction::new_with_payer(
+            &[spl_token::instruction::transfer(
+                &spl_token::id(),
+                &maker_token0,
+                &tmp_token0,
+                &maker.pubkey(),
+                &[],
+                escrow_token0_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &maker], recent_blockhash);
+        assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+        tmp_token0s.push(tmp_token0);
+    }
+    let mut transaction = Transaction::new_with_payer(
+        &[spl_token::instruction::transfer(
+            &spl_token::id(),
+            &taker_token1,
+            &taker_treasury_token1,
+            &taker.pubkey(),
+            &[],
+            escrow_token1_amount,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &taker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let state_space = Escrow::LEN;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space)
+        .unwrap();
+    let init = |tmp_token0: &Pubkey, signers: &[&Keypair]| {
+        let escrow_info = Keypair::new();
+        let signer_pubkeys = signers.iter().map(|s| s.pubkey()).collect::<Vec<_>>();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &escrow_info.pubkey(),
+                    account_lamports,
+                    state_space as u64,
+                    &prog_key,
+                ),
+                init_multisig_escrow(
+                    &prog_key,
+                    &maker_multisig,
+                    &signer_pubkeys.iter().collect::<Vec<_>>(),
+                    tmp_token0,
+                    &treasury_token1,
+                    &escrow_info.pubkey(),
+                    escrow_token1_amount,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        let mut keypairs = vec![&payer, &escrow_info];
+        keypairs.extend_from_slice(signers);
+        transaction.sign(&keypairs, recent_blockhash);
+        rpc_client
+            .send_and_confirm_transaction(&transaction)
+            .map(|_| escrow_info.pubkey())
+    };
+
+    // one signer is not enough, two are
+    assert!(init(&tmp_token0s[0], &[&maker_signers[0]]).is_err());
+    let escrow_info = init(&tmp_token0s[0], &[&maker_signers[0], &maker_signers[1]]).unwrap();
+    let cancelled_escrow_info =
+        init(&tmp_token0s[1], &[&maker_signers[1], &maker_signers[2]]).unwrap();
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let take = |signers: &[&Keypair]| {
+        let signer_pubkeys = signers.iter().map(|s| s.pubkey()).collect::<Vec<_>>();
+        let mut transaction = Transaction::new_with_payer(
+            &[exchange_multisig(
+                &prog_key,
+                &taker_multisig,
+                &signer_pubkeys.iter().collect::<Vec<_>>(),
+                &taker_treasury_token1,
+                &taker_treasury_token0,
+                &tmp_token0s[0],
+                &maker_multisig,
+                &treasury_token1,
+                &escrow_info,
+                &pda_pubkey,
+                escrow_token0_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        let mut keypairs = vec![&payer];
+        keypairs.extend_from_slice(signers);
+        transaction.sign(&keypairs, recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    // a maker's signer can't stand in for the taker's
+    assert!(take(&[&taker_signers[0], &maker_signers[0]]).is_err());
+    assert_matches!(take(&[&taker_signers[0], &taker_signers[2]]), Ok(_));
+
+    let mut transaction = Transaction::new_with_payer(
+        &[cancel_multisig_escrow(
+            &prog_key,
+            &maker_multisig,
+            &[&maker_signer_pubkeys[0], &maker_signer_pubkeys[2]],
+            &tmp_token0s[1],
+            &treasury_token0,
+            &cancelled_escrow_info,
+            &pda_pubkey,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(
+        &[&payer, &maker_signers[0], &maker_signers[2]],
+        recent_blockhash,
+    );
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    assert!(rpc_client.get_account(&cancelled_escrow_info).is_err());
+
+    let balance = get_token_balance(&rpc_client, &treasury_token0);
+    assert_eq!(balance, escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &treasury_token1);
+    assert_eq!(balance, escrow_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_treasury_token0);
+    assert_eq!(balance, escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &taker_treasury_token1);
+    assert_eq!(balance, 0);
+}
+
+#[test]
+fn test_sol_escrow() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let sol_amount: u64 = 1_000_000;
+    let token_amount: u64 = 200;
+
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    // maker and taker wrap SOL of their own, the payer pays for everything else
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::transfer(&payer.pubkey(), &maker.pubkey(), 10 * sol_amount),
+            system_instruction::transfer(&payer.pubkey(), &taker.pubkey(), 10 * sol_amount),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    // 1. the maker offers SOL for token1, the taker receives it as SOL
+    let tmp_native = create_native_account(&rpc_client, &recent_blockhash, &payer, &maker.pubkey());
+    let escrow_info = Keypair::new();
+    let state_space = Escrow::packed_len(0, 0) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_sol_escrow(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_native,
+                &maker_token1,
+                &escrow_info.pubkey(),
+                sol_amount,
+                false,
+                None,
+                None,
+                token_amount,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    assert_eq!(get_token_balance(&rpc_client, &tmp_native), sol_amount);
+
+    let taker_native =
+        create_native_account(&rpc_client, &recent_blockhash, &payer, &taker.pubkey());
+    let native_rent = rpc_client.get_balance(&taker_native).unwrap();
+    let taker_lamports = rpc_client.get_balance(&taker.pubkey()).unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[exchange_sol(
+            &prog_key,
+            &taker.pubkey(),
+            &taker_token1,
+            &taker_native,
+            &tmp_native,
+            &maker.pubkey(),
+            &maker_token1,
+            &escrow_info.pubkey(),
+            &pda_pubkey,
+            None,
+            false,
+            true,
+            sol_amount,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &taker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    // the taker's native account is gone, its rent and the SOL went to the taker
+    let balance = rpc_client.get_balance(&taker.pubkey()).unwrap();
+    assert_eq!(balance, taker_lamports + native_rent + sol_amount);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + token_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - token_amount);
+
+    // 2. the maker offers token0 for SOL, the taker pays in SOL
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        token_amount,
+    );
+    let maker_native =
+        create_native_account(&rpc_client, &recent_blockhash, &payer, &maker.pubkey());
+    let escrow_info = Keypair::new();
+    let state_space = (Escrow::packed_len(0, 0) + Escrow::UNWRAP_SECTION_LEN) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_sol_escrow(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &maker_native,
+                &escrow_info.pubkey(),
+                0,
+                true,
+                None,
+                None,
+                sol_amount,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    // the maker gets the rent of the vault, the escrow and the unwrapped account back
+    let maker_lamports = rpc_client.get_balance(&maker.pubkey()).unwrap()
+        + rpc_client.get_balance(&tmp_token0).unwrap()
+        + rpc_client.get_balance(&escrow_info.pubkey()).unwrap()
+        + rpc_client.get_balance(&maker_native).unwrap();
+    let taker_native =
+        create_native_account(&rpc_client, &recent_blockhash, &payer, &taker.pubkey());
+    let taker_lamports = rpc_client.get_balance(&taker.pubkey()).unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[exchange_sol(
+            &prog_key,
+            &taker.pubkey(),
+            &taker_native,
+            &taker_token0,
+            &tmp_token0,
+            &maker.pubkey(),
+            &maker_native,
+            &escrow_info.pubkey(),
+            &pda_pubkey,
+            None,
+            true,
+            false,
+            token_amount,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &taker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let balance = rpc_client.get_balance(&maker.pubkey()).unwrap();
+    assert_eq!(balance, maker_lamports + sol_amount);
+    let balance = rpc_client.get_balance(&taker.pubkey()).unwrap();
+    assert_eq!(balance, taker_lamports - sol_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + token_amount);
+}
+
+#[test]
+fn test_associated_token_accounts() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+
+    let (mint0, mint1, _mint_owner, maker_token0, _taker_token0, _maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+
+    // neither the maker's nor the taker's associated token account exists yet
+    let maker_ata1 = get_associated_token_address(&maker.pubkey(), &mint1, &spl_token::id());
+    let taker_ata0 = get_associated_token_address(&taker.pubkey(), &mint0, &spl_token::id());
+    assert!(rpc_client.get_account(&maker_ata1).is_err());
+    assert!(rpc_client.get_account(&taker_ata0).is_err());
+
+    let escrow_info = Keypair::new();
+    let state_space: u64 = Escrow::LEN as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_ata_escrow(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &mint1,
+                &escrow_info.pubkey(),
+                &spl_token::id(),
+                None,
+                None,
+                escrow_token1_amount,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let escrow_account = rpc_client.get_account(&escrow_info.pubkey()).unwrap();
+    let escrow = Escrow::unpack(escrow_account.data.as_slice()).unwrap();
+    assert_eq!(escrow.maker_token1_pubkey, maker_ata1);
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let mut transaction = Transaction::new_with_payer(
+        &exchange_with_atas(
+            &prog_key,
+            &payer.pubkey(),
+            &taker.pubkey(),
+            &taker_token1,
+            &tmp_token0,
+            &mint0,
+            &maker.pubkey(),
+            &mint1,
+            &escrow_info.pubkey(),
+            &spl_token::id(),
+            &pda_pubkey,
+            escrow_token0_amount,
+        )
+        .unwrap(),
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &taker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let balance = get_token_balance(&rpc_client, &maker_ata1);
+    assert_eq!(balance, escrow_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_ata0);
+    assert_eq!(balance, escrow_token0_amount);
+
+    // creating an existing recipient account is a no-op
+    let mut transaction = Transaction::new_with_payer(
+        &[create_recipient_account(
+            &prog_key,
+            &payer.pubkey(),
+            &maker.pubkey(),
+            &mint1,
+            &spl_token::id(),
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    let balance = get_token_balance(&rpc_client, &maker_ata1);
+    assert_eq!(balance, escrow_token1_amount);
+}
+
+#[test]
+fn test_delegated_escrow() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    // two offers of (token0, token1) amounts sharing the maker's token0 account
+    let offers: [(u64, u64); 2] = [(100, 200), (50, 120)];
+
+    let (_mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let state_space = (Escrow::packed_len(0, 0) + Escrow::DELEGATION_SECTION_LEN) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut escrows = Vec::new();
+    for (escrow_token0_amount, escrow_token1_amount) in offers.iter() {
+        let escrow_info = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &escrow_info.pubkey(),
+                    account_lamports,
+                    state_space,
+                    &prog_key,
+                ),
+                init_delegated_escrow(
+                    &prog_key,
+                    &maker.pubkey(),
+                    &maker_token0,
+                    &maker_token1,
+                    &escrow_info.pubkey(),
+                    None,
+                    *escrow_token0_amount,
+                    *escrow_token1_amount,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+        assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+        escrows.push(escrow_info.pubkey());
+    }
+
+    // the tokens stay with the maker, the PDA is approved for both offers
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let maker_token0_account = get_token_account(&rpc_client, &maker_token0);
+    assert_eq!(maker_token0_account.amount, INITIAL_MINT_AMOUNT);
+    assert_eq!(maker_token0_account.delegate, Some(pda_pubkey).into());
+    assert_eq!(
+        maker_token0_account.delegated_amount,
+        offers[0].0 + offers[1].0
+    );
+
+    let exchange_delegated = |escrow_info: &Pubkey, amount: u64| {
+        let mut transaction = Transaction::new_with_payer(
+            &[exchange(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                &maker_token0,
+                &maker.pubkey(),
+                &maker_token1,
+                escrow_info,
+                &spl_token::id(),
+                &pda_pubkey,
+                amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // the first offer is pulled from the maker's account
+    assert_matches!(exchange_delegated(&escrows[0], offers[0].0), Ok(_));
+    let maker_token0_account = get_token_account(&rpc_client, &maker_token0);
+    assert_eq!(
+        maker_token0_account.amount,
+        INITIAL_MINT_AMOUNT - offers[0].0
+    );
+    assert_eq!(maker_token0_account.delegated_amount, offers[1].0);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + offers[0].1);
+
+    // once the maker revokes, the second offer can no longer be taken
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            spl_token::instruction::revoke(&spl_token::id(), &maker_token0, &maker.pubkey(), &[])
+                .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    assert!(exchange_delegated(&escrows[1], offers[1].0).is_err());
+
+    // but cancelled, which leaves the maker's account alone
+    let mut transaction = Transaction::new_with_payer(
+        &[cancel_escrow(
+            &prog_key,
+            &maker.pubkey(),
+            &maker_token0,
+            &maker_token0,
+            &escrows[1],
+            &spl_token::id(),
+            &pda_pubkey,
+            None,
+            &[],
+            None,
+            None,
+            None,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    assert!(rpc_client.get_account(&escrows[1]).is_err());
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - offers[0].0);
+}
+
+#[test]
+fn test_counter_offers() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    // the maker asks 200 token1 for 100 token0, the taker counters with less
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+    let counter_amounts: [u64; 3] = [120, 150, 130];
+
+    let (mint0, mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+    let escrow_info = start_escrow(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &prog_key,
+        &maker,
+        &tmp_token0,
+        &maker_token1,
+        escrow_token1_amount,
+    );
+
+    let state_space: u64 = CounterOffer::LEN as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut counters = Vec::new();
+    for counter_amount in counter_amounts.iter() {
+        // the same helper locks the taker's token1
+        let tmp_token1 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint1,
+            &taker,
+            &taker_token1,
+            *counter_amount,
+        );
+        let counter_info = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &counter_info.pubkey(),
+                    account_lamports,
+                    state_space,
+                    &prog_key,
+                ),
+                propose_counter(
+                    &prog_key,
+                    &taker.pubkey(),
+                    &tmp_token1,
+                    &taker_token1,
+                    &taker_token0,
+                    &escrow_info,
+                    &counter_info.pubkey(),
+                    &spl_token::id(),
+                    escrow_token0_amount,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &counter_info, &taker], recent_blockhash);
+        assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+        counters.push((counter_info.pubkey(), tmp_token1));
+    }
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT - counter_amounts.iter().sum::<u64>()
+    );
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    // the maker rejects the first counter-offer, its token1 go back to the taker
+    let mut transaction = Transaction::new_with_payer(
+        &[reject_counter(
+            &prog_key,
+            &maker.pubkey(),
+            &escrow_info,
+            &taker.pubkey(),
+            &counters[0].0,
+            &counters[0].1,
+            &taker_token1,
+            &spl_token::id(),
+            &pda_pubkey,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    // accepting another amount than the locked one fails, the locked one settles
+    for (amount1, should_succeed) in
+        vec![(counter_amounts[1] + 1, false), (counter_amounts[1], true)]
+    {
+        let mut transaction = Transaction::new_with_payer(
+            &[accept_counter(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &maker_token1,
+                &escrow_info,
+                &counters[1].0,
+                &counters[1].1,
+                &taker_token0,
+                &taker.pubkey(),
+                &spl_token::id(),
+                &pda_pubkey,
+                None,
+                amount1,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &maker], recent_blockhash);
+        assert_eq!(
+            rpc_client
+                .send_and_confirm_transaction(&transaction)
+                .is_ok(),
+            should_succeed
+        );
+    }
+
+    // the escrow is gone, the taker still withdraws the last counter-offer
+    let mut transaction = Transaction::new_with_payer(
+        &[withdraw_counter(
+            &prog_key,
+            &taker.pubkey(),
+            &counters[2].0,
+            &counters[2].1,
+            &taker_token1,
+            &spl_token::id(),
+            &pda_pubkey,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &taker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    assert!(rpc_client.get_account(&escrow_info).is_err());
+    for (counter_info, _) in counters.iter() {
+        assert!(rpc_client.get_account(counter_info).is_err());
+    }
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + counter_amounts[1]);
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - counter_amounts[1]);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token0_amount);
+}
+
+#[test]
+fn test_oracle_escrow() {
+    let prog_key = Pubkey::new_unique();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    // stand-in feeds written into the genesis, 1.5 token1 per token0, the last two are too old
+    // or too uncertain for the quotes below
+    let feeds: [(Pubkey, PriceFeed); 3] = [
+        (
+            Pubkey::new_unique(),
+            PriceFeed {
+                price: 15,
+                confidence: 0,
+                exponent: -1,
+                publish_time: now,
+            },
+        ),
+        (
+            Pubkey::new_unique(),
+            PriceFeed {
+                price: 15,
+                confidence: 0,
+                exponent: -1,
+                publish_time: now - 3600,
+            },
+        ),
+        (
+            Pubkey::new_unique(),
+            PriceFeed {
+                price: 15,
+                confidence: 1,
+                exponent: -1,
+                publish_time: now,
+            },
+        ),
+    ];
+    let mut validator_genesis = clean_ledger_setup_validator(&prog_key);
+    for (feed_pubkey, feed) in feeds.iter() {
+        let mut data = vec![0; PriceFeed::LEN];
+        feed.pack_into_slice(&mut data);
+        validator_genesis.add_account(
+            *feed_pubkey,
+            AccountSharedData::from(Account {
+                lamports: 1_000_000_000,
+                data,
+                owner: Pubkey::new_unique(),
+                executable: false,
+                rent_epoch: 0,
+            }),
+        );
+    }
+    let (test_validator, payer) = validator_genesis.start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    // 2% above the feed's price, 100 * 1.5 * 1.02
+    let quoted_token1_amount: u64 = 153;
+
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let state_space = (Escrow::packed_len(0, 0) + Escrow::ORACLE_SECTION_LEN) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    let mut escrows = Vec::new();
+    for (feed_pubkey, _) in feeds.iter() {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            escrow_token0_amount,
+        );
+        let escrow_info = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &escrow_info.pubkey(),
+                    account_lamports,
+                    state_space,
+                    &prog_key,
+                ),
+                init_oracle_escrow(
+                    &prog_key,
+                    &maker.pubkey(),
+                    &tmp_token0,
+                    &maker_token1,
+                    &escrow_info.pubkey(),
+                    OracleQuote {
+                        price_feed_pubkey: *feed_pubkey,
+                        spread_bps: 200,
+                        max_age: 600,
+                        max_confidence_bps: 100,
+                    },
+                    None,
+                    100,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+        assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+        escrows.push((escrow_info.pubkey(), tmp_token0));
+    }
+
+    let take = |escrow_info: &Pubkey, tmp_token0: &Pubkey, feed_pubkey: &Pubkey, max: u64| {
+        let mut transaction = Transaction::new_with_payer(
+            &[exchange_oracle(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                tmp_token0,
+                &maker.pubkey(),
+                &maker_token1,
+                escrow_info,
+                &pda_pubkey,
+                feed_pubkey,
+                max,
+                escrow_token0_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // below the quote, with another feed than the stored one, on a stale or uncertain price
+    let (escrow_info, tmp_token0) = escrows[0];
+    assert!(take(
+        &escrow_info,
+        &tmp_token0,
+        &feeds[0].0,
+        quoted_token1_amount - 1
+    )
+    .is_err());
+    assert!(take(&escrow_info, &tmp_token0, &feeds[1].0, quoted_token1_amount).is_err());
+    for ((escrow_info, tmp_token0), (feed_pubkey, _)) in escrows.iter().zip(feeds.iter()).skip(1) {
+        assert!(take(escrow_info, tmp_token0, feed_pubkey, u64::MAX).is_err());
+    }
+
+    assert_matches!(
+        take(&escrow_info, &tmp_token0, &feeds[0].0, quoted_token1_amount),
+        Ok(_)
+    );
+    assert!(rpc_client.get_account(&escrow_info).is_err());
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + quoted_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - quoted_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token0_amount);
+}
+
+#[test]
+fn test_standing_order() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    // tranches of 100 token0 for 50 token1 each, two of which are refilled from maker_token0
+    let tranche_amount: u64 = 100;
+    let refill_amount: u64 = 200;
+    let maker_token1_expected_amount: u64 = 50;
+
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        tranche_amount,
+    );
+
+    let escrow_info = Keypair::new();
+    let state_space = (Escrow::packed_len(0, 0) + Escrow::REFILL_SECTION_LEN) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_standing_order(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &maker_token1,
+                &maker_token0,
+                &escrow_info.pubkey(),
+                refill_amount,
+                None,
+                maker_token1_expected_amount,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let take = || {
+        let mut transaction = Transaction::new_with_payer(
+            &[exchange_standing_order(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                &tmp_token0,
+                &maker.pubkey(),
+                &maker_token1,
+                &escrow_info.pubkey(),
+                &pda_pubkey,
+                &maker_token0,
+                tranche_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // the vault is refilled twice and the escrow stays open until the last tranche is taken
+    for taken in 1..=3u64 {
+        assert_matches!(take(), Ok(_));
+        let escrow_open = rpc_client.get_account(&escrow_info.pubkey()).is_ok();
+        assert_eq!(escrow_open, taken < 3);
+        if escrow_open {
+            let escrow_account = rpc_client.get_account(&escrow_info.pubkey()).unwrap();
+            let escrow = Escrow::unpack(escrow_account.data.as_slice()).unwrap();
+            let refill = escrow.refill.unwrap();
+            assert_eq!(refill.tranche_amount, tranche_amount);
+            assert_eq!(
+                refill.remaining_amount,
+                refill_amount - taken * tranche_amount
+            );
+            assert_eq!(get_token_balance(&rpc_client, &tmp_token0), tranche_amount);
+        }
+    }
+    assert!(rpc_client.get_account(&tmp_token0).is_err());
+    assert!(take().is_err());
+
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT - tranche_amount - refill_amount
+    );
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT + tranche_amount + refill_amount
+    );
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT + 3 * maker_token1_expected_amount
+    );
+}
+
+#[test]
+fn test_standing_order_closed() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let tranche_amount: u64 = 100;
+    let refill_amount: u64 = 200;
+    let maker_token1_expected_amount: u64 = 50;
+
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let state_space = (Escrow::packed_len(0, 0) + Escrow::REFILL_SECTION_LEN) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let init = |expires_at: Option<i64>| {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            tranche_amount,
+        );
+        let escrow_info = Keypair::new();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &escrow_info.pubkey(),
+                    account_lamports,
+                    state_space,
+                    &prog_key,
+                ),
+                init_standing_order(
+                    &prog_key,
+                    &maker.pubkey(),
+                    &tmp_token0,
+                    &maker_token1,
+                    &maker_token0,
+                    &escrow_info.pubkey(),
+                    refill_amount,
+                    expires_at,
+                    maker_token1_expected_amount,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+        assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+        (tmp_token0, escrow_info.pubkey())
+    };
+    let take = |tmp_token0: &Pubkey, escrow_info: &Pubkey| {
+        let mut transaction = Transaction::new_with_payer(
+            &[exchange_standing_order(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                tmp_token0,
+                &maker.pubkey(),
+                &maker_token1,
+                escrow_info,
+                &pda_pubkey,
+                &maker_token0,
+                tranche_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // a cancelled order takes the approval for the tranches not pulled yet back
+    let (tmp_token0, escrow_info) = init(None);
+    let mut transaction = Transaction::new_with_payer(
+        &[cancel_escrow(
+            &prog_key,
+            &maker.pubkey(),
+            &tmp_token0,
+            &maker_token0,
+            &escrow_info,
+            &spl_token::id(),
+            &pda_pubkey,
+            None,
+            &[],
+            None,
+            None,
+            Some(&maker_token0),
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    let maker_token0_account = get_token_account(&rpc_client, &maker_token0);
+    assert!(maker_token0_account.delegate.is_none());
+    assert_eq!(maker_token0_account.delegated_amount, 0);
+    assert!(take(&tmp_token0, &escrow_info).is_err());
+    assert_eq!(
+        get_token_balance(&rpc_client, &maker_token0),
+        INITIAL_MINT_AMOUNT
+    );
+
+    // an expired order is returned by anyone, the approval is left for the maker to revoke but
+    // nothing is pulled through it anymore
+    let (tmp_token0, escrow_info) = init(Some(now - 60));
+    let mut transaction = Transaction::new_with_payer(
+        &[expire_escrow(
+            &prog_key,
+            &maker.pubkey(),
+            &tmp_token0,
+            &maker_token0,
+            &escrow_info,
+            &spl_token::id(),
+            &pda_pubkey,
+            None,
+            &[],
+            None,
+            None,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    let maker_token0_account = get_token_account(&rpc_client, &maker_token0);
+    assert_eq!(maker_token0_account.delegate, Some(pda_pubkey).into());
+    assert_eq!(maker_token0_account.delegated_amount, refill_amount);
+    assert!(take(&tmp_token0, &escrow_info).is_err());
+    assert_eq!(
+        get_token_balance(&rpc_client, &maker_token0),
+        INITIAL_MINT_AMOUNT
+    );
+    assert_eq!(
+        get_token_balance(&rpc_client, &taker_token0),
+        INITIAL_MINT_AMOUNT
+    );
+}
+
+#[test]
+fn test_forged_standing_order() {
+    let prog_key = Pubkey::new_unique();
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    // a standing order's vault and funding account approved to the PDA, and a copy of its escrow
+    // refilling from that funding account that another program owns, written into the genesis
+    let attacker = Keypair::new();
+    let mint = Pubkey::new_unique();
+    let vault = Pubkey::new_unique();
+    let funding = Pubkey::new_unique();
+    let attacker_token0 = Pubkey::new_unique();
+    let attacker_token1 = Pubkey::new_unique();
+    let forged_escrow = Pubkey::new_unique();
+    let tranche_amount: u64 = 100;
+    let funding_amount: u64 = 1_000;
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: Some(Pubkey::new_unique()).into(),
+            supply: tranche_amount + funding_amount + 1,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: None.into(),
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    let token_account_data = |owner: &Pubkey, amount: u64, delegated_amount: u64| {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(
+            spl_token::state::Account {
+                mint,
+                owner: *owner,
+                amount,
+                delegate: if delegated_amount > 0 {
+                    Some(pda_pubkey).into()
+                } else {
+                    None.into()
+                },
+                state: spl_token::state::AccountState::Initialized,
+                delegated_amount,
+                ..spl_token::state::Account::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        data
+    };
+    let escrow = Escrow {
+        is_initialized: true,
+        maker_pubkey: attacker.pubkey(),
+        tmp_token0_pubkey: vault,
+        maker_token1_pubkey: attacker_token1,
+        maker_token1_expected_amount: 1,
+        refill: Some(Refill {
+            funding_pubkey: funding,
+            tranche_amount: funding_amount,
+            remaining_amount: funding_amount,
+        }),
+        ..Escrow::default()
+    };
+    let mut escrow_data = vec![0; escrow.get_packed_len()];
+    Escrow::pack(escrow, &mut escrow_data).unwrap();
+    let mut validator_genesis = clean_ledger_setup_validator(&prog_key);
+    for (pubkey, data, owner) in vec![
+        (mint, mint_data, spl_token::id()),
+        (
+            vault,
+            token_account_data(&pda_pubkey, tranche_amount, 0),
+            spl_token::id(),
+        ),
+        (
+            funding,
+            token_account_data(&Pubkey::new_unique(), funding_amount, funding_amount),
+            spl_token::id(),
+        ),
+        (
+            attacker_token0,
+            token_account_data(&attacker.pubkey(), 0, 0),
+            spl_token::id(),
+        ),
+        (
+            attacker_token1,
+            token_account_data(&attacker.pubkey(), 1, 0),
+            spl_token::id(),
+        ),
+        (forged_escrow, escrow_data, Pubkey::new_unique()),
+    ] {
+        validator_genesis.add_account(
+            pubkey,
+            AccountSharedData::from(Account {
+                lamports: 1_000_000_000,
+                data,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+            }),
+        );
+    }
+    let (test_validator, payer) = validator_genesis.start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    // taking the copy would empty the vault and refill it with the whole funding account
+    let mut transaction = Transaction::new_with_payer(
+        &[exchange_standing_order(
+            &prog_key,
+            &attacker.pubkey(),
+            &attacker_token1,
+            &attacker_token0,
+            &vault,
+            &attacker.pubkey(),
+            &attacker_token1,
+            &forged_escrow,
+            &pda_pubkey,
+            &funding,
+            tranche_amount,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &attacker], recent_blockhash);
+    assert!(rpc_client
+        .send_and_confirm_transaction(&transaction)
+        .is_err());
+
+    assert_eq!(get_token_balance(&rpc_client, &vault), tranche_amount);
+    let funding_account = get_token_account(&rpc_client, &funding);
+    assert_eq!(funding_account.amount, funding_amount);
+    assert_eq!(funding_account.delegated_amount, funding_amount);
+    assert_eq!(get_token_balance(&rpc_client, &attacker_token0), 0);
+}
+
+#[test]
+fn test_attestation_gated_exchange() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let issuer = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+
+    let escrow_info = Keypair::new();
+    let state_space = (Escrow::packed_len(0, 0) + Escrow::ATTESTATION_SECTION_LEN) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_gated_escrow(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &maker_token1,
+                &escrow_info.pubkey(),
+                &issuer.pubkey(),
+                escrow_token1_amount,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    // the issuer pays the attestations' rent
+    let mut transaction = Transaction::new_with_payer(
+        &[system_instruction::transfer(
+            &payer.pubkey(),
+            &issuer.pubkey(),
+            10_000_000,
+        )],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let take = || {
+        let mut transaction = Transaction::new_with_payer(
+            &[exchange_gated(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                &tmp_token0,
+                &maker.pubkey(),
+                &maker_token1,
+                &escrow_info.pubkey(),
+                &pda_pubkey,
+                &issuer.pubkey(),
+                escrow_token0_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    let issue = |expires_at: i64| {
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                issue_attestation(&prog_key, &issuer.pubkey(), &taker.pubkey(), expires_at)
+                    .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &issuer], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // without an attestation the taker is turned away
+    assert!(take().is_err());
+
+    // an expired attestation doesn't count
+    assert_matches!(issue(now - 3600), Ok(_));
+    assert!(take().is_err());
+
+    // a revoked one neither
+    assert_matches!(issue(now + 3600), Ok(_));
+    let mut transaction = Transaction::new_with_payer(
+        &[revoke_attestation(&prog_key, &issuer.pubkey(), &taker.pubkey()).unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &issuer], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    assert!(take().is_err());
+
+    // once attested again the taker can take the escrow
+    assert_matches!(issue(now + 7200), Ok(_));
+    assert_matches!(take(), Ok(_));
+
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token0_amount);
+}
+
+#[test]
+fn test_covered_options() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let writer = Keypair::new();
+    let holder = Keypair::new();
+    // a call on 100 token0 at a strike of 200 token1, sold for a premium of 10 token1
+    let underlying_amount: u64 = 100;
+    let strike_amount: u64 = 200;
+    let premium_amount: u64 = 10;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    let (mint0, mint1, _mint_owner, writer_token0, holder_token0, writer_token1, holder_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &writer, &holder);
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    let tmp_collateral = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &writer,
+        &writer_token0,
+        underlying_amount,
+    );
+    let (option_info, option_mint, writer_option_token) = write_covered_option(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &prog_key,
+        &writer,
+        &tmp_collateral,
+        &writer_token1,
+        OptionKind::Call,
+        strike_amount,
+        now + 3600,
+    )
+    .unwrap();
+
+    // the writer sells the option token through a regular escrow
+    let tmp_option_token = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &option_mint,
+        &writer,
+        &writer_option_token,
+        1,
+    );
+    let escrow_info = start_escrow(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &prog_key,
+        &writer,
+        &tmp_option_token,
+        &writer_token1,
+        premium_amount,
+    );
+    let holder_option_token = create_token_account(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &option_mint,
+        &holder.pubkey(),
+    );
+    finish_escrow(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &prog_key,
+        &escrow_info,
+        &holder,
+        &holder_option_token,
+        &holder_token1,
+        1,
+    );
+
+    // the collateral stays locked until the option expired
+    let mut transaction = Transaction::new_with_payer(
+        &[reclaim_option_collateral(
+            &prog_key,
+            &writer.pubkey(),
+            &tmp_collateral,
+            &writer_token0,
+            &option_info,
+            &pda_pubkey,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &writer], recent_blockhash);
+    assert!(rpc_client
+        .send_and_confirm_transaction(&transaction)
+        .is_err());
+
+    let mut transaction = Transaction::new_with_payer(
+        &[exercise_option(
+            &prog_key,
+            &holder.pubkey(),
+            &holder_option_token,
+            &holder_token1,
+            &holder_token0,
+            &option_mint,
+            &tmp_collateral,
+            &writer_token1,
+            &writer.pubkey(),
+            &option_info,
+            &pda_pubkey,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &holder], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    assert!(rpc_client.get_account(&option_info).is_err());
+    assert!(rpc_client.get_account(&tmp_collateral).is_err());
+    assert_eq!(get_token_balance(&rpc_client, &holder_option_token), 0);
+    let balance = get_token_balance(&rpc_client, &holder_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + underlying_amount);
+    let balance = get_token_balance(&rpc_client, &holder_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT - premium_amount - strike_amount
+    );
+    let balance = get_token_balance(&rpc_client, &writer_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT + premium_amount + strike_amount
+    );
+
+    // an already expired put on 100 token0 backed by its strike of 200 token1
+    let tmp_collateral = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint1,
+        &writer,
+        &writer_token1,
+        strike_amount,
+    );
+    let (option_info, option_mint, writer_option_token) = write_covered_option(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &prog_key,
+        &writer,
+        &tmp_collateral,
+        &writer_token0,
+        OptionKind::Put,
+        underlying_amount,
+        now - 60,
+    )
+    .unwrap();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[exercise_option(
+            &prog_key,
+            &writer.pubkey(),
+            &writer_option_token,
+            &writer_token0,
+            &writer_token1,
+            &option_mint,
+            &tmp_collateral,
+            &writer_token0,
+            &writer.pubkey(),
+            &option_info,
+            &pda_pubkey,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &writer], recent_blockhash);
+    assert!(rpc_client
+        .send_and_confirm_transaction(&transaction)
+        .is_err());
+
+    let mut transaction = Transaction::new_with_payer(
+        &[reclaim_option_collateral(
+            &prog_key,
+            &writer.pubkey(),
+            &tmp_collateral,
+            &writer_token1,
+            &option_info,
+            &pda_pubkey,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &writer], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    assert!(rpc_client.get_account(&option_info).is_err());
+    let balance = get_token_balance(&rpc_client, &writer_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT + premium_amount + strike_amount
+    );
+}
+
+#[test]
+fn test_basket_escrow() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    // maker puts up a basket of token0 and token1, wants token1 in return
+    let escrow_token0_amount: u64 = 100;
+    let basket_token1_amount: u64 = 50;
+    let escrow_token1_amount: u64 = 200;
+
+    let (mint0, mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+    let basket_vault = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint1,
+        &maker,
+        &maker_token1,
+        basket_token1_amount,
+    );
+
+    let escrow_info = Keypair::new();
+    let state_space: u64 = Escrow::packed_len(1, 0) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_basket_escrow(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &[basket_vault],
+                &maker_token1,
+                &escrow_info.pubkey(),
+                &spl_token::id(),
+                escrow_token1_amount,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let escrow_info_account = rpc_client.get_account(&escrow_info.pubkey()).unwrap();
+    let escrow_info_data = Escrow::unpack(escrow_info_account.data.as_slice()).unwrap();
+    assert_eq!(escrow_info_data.basket_vault_pubkeys, vec![basket_vault]);
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let mut transaction = Transaction::new_with_payer(
+        &[exchange_basket(
+            &prog_key,
+            &taker.pubkey(),
+            &taker_token1,
+            &taker_token0,
+            &tmp_token0,
+            &[(basket_vault, taker_token1)],
+            &maker.pubkey(),
+            &maker_token1,
+            &escrow_info.pubkey(),
+            &spl_token::id(),
+            &pda_pubkey,
+            escrow_token0_amount,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &taker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT - basket_token1_amount + escrow_token1_amount
+    );
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token0_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT + basket_token1_amount - escrow_token1_amount
+    );
+}
+
+#[test]
+fn test_extra_asks() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    // maker offers token0 for token1 and asks for more token0 and token1 on top
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+    let ask_token0_amount: u64 = 30;
+    let ask_token1_amount: u64 = 20;
+
+    let (mint0, mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+    let maker_ask_token1 = create_token_account(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint1,
+        &maker.pubkey(),
+    );
+
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        escrow_token0_amount,
+    );
+
+    let escrow_info = Keypair::new();
+    let state_space: u64 = Escrow::packed_len(0, 2) as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+
+    // no more than MAX_EXTRA_ASKS asks fit in an escrow
+    let too_many_asks = vec![(maker_token0, 1); MAX_EXTRA_ASKS + 1];
+    assert!(init_multi_asset_escrow(
+        &prog_key,
+        &maker.pubkey(),
+        &tmp_token0,
+        &[],
+        &maker_token1,
+        &too_many_asks,
+        &escrow_info.pubkey(),
+        &spl_token::id(),
+        None,
+        None,
+        None,
+        escrow_token1_amount,
+    )
+    .is_err());
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_multi_asset_escrow(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &[],
+                &maker_token1,
+                &[
+                    (maker_token0, ask_token0_amount),
+                    (maker_ask_token1, ask_token1_amount),
+                ],
+                &escrow_info.pubkey(),
+                &spl_token::id(),
+                None,
+                None,
+                None,
+                escrow_token1_amount,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    let escrow_info_account = rpc_client.get_account(&escrow_info.pubkey()).unwrap();
+    let escrow_info_data = Escrow::unpack(escrow_info_account.data.as_slice()).unwrap();
+    let asks: Vec<(Pubkey, u64, Pubkey)> = escrow_info_data
+        .extra_asks
+        .iter()
+        .map(|ask| (ask.mint, ask.amount, ask.recipient_pubkey))
+        .collect();
+    assert_eq!(
+        asks,
+        vec![
+            (mint0, ask_token0_amount, maker_token0),
+            (mint1, ask_token1_amount, maker_ask_token1),
+        ]
+    );
+
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    let take = |extra_asks: &[(Pubkey, Pubkey)]| {
+        let mut transaction = Transaction::new_with_payer(
+            &[exchange_multi_asset(
+                &prog_key,
+                &taker.pubkey(),
+                &taker_token1,
+                &taker_token0,
+                &tmp_token0,
+                &[],
+                &maker.pubkey(),
+                &maker_token1,
+                extra_asks,
+                &escrow_info.pubkey(),
+                &spl_token::id(),
+                &pda_pubkey,
+                None,
+                None,
+                None,
+                escrow_token0_amount,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // every ask has to be paid, the taker can't take the escrow paying only one of them
+    assert!(take(&[(taker_token0, maker_token0)]).is_err());
+    // the asks have to be paid to the recipients recorded on init, in their order
+    assert!(take(&[
+        (taker_token1, maker_ask_token1),
+        (taker_token0, maker_token0)
+    ])
+    .is_err());
+    assert!(take(&[(taker_token0, maker_token0), (taker_token1, taker_token1)]).is_err());
+    assert_matches!(
+        take(&[
+            (taker_token0, maker_token0),
+            (taker_token1, maker_ask_token1)
+        ]),
+        Ok(_)
+    );
+    assert!(rpc_client.get_account(&escrow_info.pubkey()).is_err());
+
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT - escrow_token0_amount + ask_token0_amount
+    );
+    let balance = get_token_balance(&rpc_client, &maker_token1);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token1_amount);
+    let balance = get_token_balance(&rpc_client, &maker_ask_token1);
+    assert_eq!(balance, ask_token1_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT + escrow_token0_amount - ask_token0_amount
+    );
+    let balance = get_token_balance(&rpc_client, &taker_token1);
+    assert_eq!(
+        balance,
+        INITIAL_MINT_AMOUNT - escrow_token1_amount - ask_token1_amount
+    );
+}
+
+#[test]
+fn test_htlc() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let htlc_token0_amount: u64 = 100;
+    let preimage = b"cross-chain secret".to_vec();
+
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, _maker_token1, _taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+
+    let tmp_token0 = create_tmp_maker_token0(
+        &rpc_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &maker,
+        &maker_token0,
+        htlc_token0_amount,
+    );
+
+    // 1. maker locks token0 for the taker behind the hash of the preimage
+    let htlc_info = Keypair::new();
+    let state_space: u64 = Htlc::LEN as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    // the validator's clock follows the wall clock, give the claim an hour
+    let timeout = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+        + 3600;
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &htlc_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_htlc(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &taker_token0,
+                &htlc_info.pubkey(),
+                &spl_token::id(),
+                HashKind::Sha256,
+                hashv(&[&preimage]).to_bytes(),
+                timeout,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &htlc_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    // 2. a wrong preimage is rejected, the correct one releases the tokens to the taker
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    for (preimage, should_succeed) in vec![(b"wrong".to_vec(), false), (preimage, true)] {
+        let mut transaction = Transaction::new_with_payer(
+            &[claim_htlc(
+                &prog_key,
+                &tmp_token0,
+                &taker_token0,
+                &maker.pubkey(),
+                &htlc_info.pubkey(),
+                &spl_token::id(),
+                &pda_pubkey,
+                preimage,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        assert_eq!(
+            rpc_client
+                .send_and_confirm_transaction(&transaction)
+                .is_ok(),
+            should_succeed
+        );
+    }
+
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - htlc_token0_amount);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + htlc_token0_amount);
+}
+
+#[test]
+fn test_arbiter_dispute() {
     let prog_key = Pubkey::new_unique();
     let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
     let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
 
+    // the maker pays the taker for a service, the arbiter settles their dispute
     let maker = Keypair::new();
     let taker = Keypair::new();
-    // maker puts up escrow_token0_amount, wants to trade for escrow_token1_amount
+    let arbiter = Keypair::new();
     let escrow_token0_amount: u64 = 100;
-    let escrow_token1_amount: u64 = 200;
+    let taker_share: u64 = 60;
 
-    // 0. create two tokens (mint0/1) and mint some tokens to maker & taker's wallet accounts
-    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, maker_token1, taker_token1) =
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, _maker_token1, _taker_token1) =
         create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
 
-    // 1. create maker's tmp token0 balance account and sends escrow amount to it
     let tmp_token0 = create_tmp_maker_token0(
         &rpc_client,
         &recent_blockhash,
@@ -48,46 +3568,373 @@ fn test_escrow() {
         escrow_token0_amount,
     );
 
-    // 2. create empty account owned by escrow program and call solana_escrow's InitEscrow entrypoint
-    let escrow_info_pubkey = start_escrow(
-        &rpc_client,
-        &recent_blockhash,
-        &payer,
-        &prog_key,
-        &maker,
-        &tmp_token0,
-        &maker_token1,
-        escrow_token1_amount,
+    let escrow_info = Keypair::new();
+    let state_space: u64 = ArbiterEscrow::LEN as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    // 1. maker deposits, then raises a dispute in the same transaction
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &escrow_info.pubkey(),
+                account_lamports,
+                state_space,
+                &prog_key,
+            ),
+            init_arbiter_escrow(
+                &prog_key,
+                &maker.pubkey(),
+                &tmp_token0,
+                &maker_token0,
+                &taker_token0,
+                &arbiter.pubkey(),
+                &escrow_info.pubkey(),
+                &spl_token::id(),
+            )
+            .unwrap(),
+            raise_dispute(&prog_key, &maker.pubkey(), &escrow_info.pubkey()).unwrap(),
+        ],
+        Some(&payer.pubkey()),
     );
+    transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
 
-    // 3. finish escrow by calling solana_escrow's Exchange entrypoint
-    finish_escrow(
-        &rpc_client,
-        &recent_blockhash,
-        &payer,
-        &prog_key,
-        &escrow_info_pubkey,
-        &taker,
-        &taker_token0,
-        &taker_token1,
-        escrow_token0_amount,
+    // 2. arbiter splits the deposit
+    let mut transaction = Transaction::new_with_payer(
+        &[resolve_dispute(
+            &prog_key,
+            &arbiter.pubkey(),
+            &tmp_token0,
+            &taker_token0,
+            &maker_token0,
+            &maker.pubkey(),
+            &escrow_info.pubkey(),
+            &spl_token::id(),
+            &pda_pubkey,
+            taker_share,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
     );
+    transaction.sign(&[&payer, &arbiter], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
 
-    // maker send token0 and received token1. check if all balances are correct
     let balance = get_token_balance(&rpc_client, &maker_token0);
-    assert_eq!(balance, INITIAL_MINT_AMOUNT - escrow_token0_amount);
-    let balance = get_token_balance(&rpc_client, &maker_token1);
-    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token1_amount);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - taker_share);
     let balance = get_token_balance(&rpc_client, &taker_token0);
-    assert_eq!(balance, INITIAL_MINT_AMOUNT + escrow_token0_amount);
-    let balance = get_token_balance(&rpc_client, &taker_token1);
-    assert_eq!(balance, INITIAL_MINT_AMOUNT - escrow_token1_amount);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + taker_share);
+}
+
+#[test]
+fn test_milestone_escrow() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    // maker pays taker in tranches, one per approved milestone
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, _maker_token1, _taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+
+    let init = |amounts: Vec<u64>| {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            amounts.iter().sum(),
+        );
+        let escrow_info = Keypair::new();
+        let state_space = MilestoneEscrow::LEN;
+        let account_lamports = rpc_client
+            .get_minimum_balance_for_rent_exemption(state_space)
+            .unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &escrow_info.pubkey(),
+                    account_lamports,
+                    state_space as u64,
+                    &prog_key,
+                ),
+                init_milestone_escrow(
+                    &prog_key,
+                    &maker.pubkey(),
+                    &tmp_token0,
+                    &maker_token0,
+                    &taker_token0,
+                    &escrow_info.pubkey(),
+                    &spl_token::id(),
+                    amounts,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &escrow_info, &maker], recent_blockhash);
+        assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+        (escrow_info.pubkey(), tmp_token0)
+    };
+    let approve = |(escrow_info, tmp_token0): &(Pubkey, Pubkey), index: u8| {
+        let mut transaction = Transaction::new_with_payer(
+            &[approve_milestone(
+                &prog_key,
+                &maker.pubkey(),
+                tmp_token0,
+                &taker_token0,
+                escrow_info,
+                &spl_token::id(),
+                &pda_pubkey,
+                index,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &maker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+
+    // 1. milestones are approved one at a time, each once
+    let escrow = init(vec![30, 70]);
+    assert!(approve(&escrow, 2).is_err());
+    assert_matches!(approve(&escrow, 0), Ok(_));
+    assert!(approve(&escrow, 0).is_err());
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + 30);
+
+    // 2. tokens sent to the vault don't keep the last approval from closing the escrow,
+    // they go to the payee with the last tranche
+    let mut transaction = Transaction::new_with_payer(
+        &[spl_token::instruction::transfer(
+            &spl_token::id(),
+            &taker_token0,
+            &escrow.1,
+            &taker.pubkey(),
+            &[],
+            1,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &taker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    assert_matches!(approve(&escrow, 1), Ok(_));
+    assert!(rpc_client.get_account(&escrow.0).is_err());
+    assert!(rpc_client.get_account(&escrow.1).is_err());
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + 100);
+
+    // 3. cancelling returns the unapproved remainder to the payer
+    let escrow = init(vec![40, 60]);
+    assert_matches!(approve(&escrow, 0), Ok(_));
+    let mut transaction = Transaction::new_with_payer(
+        &[cancel_milestone_escrow(
+            &prog_key,
+            &maker.pubkey(),
+            &escrow.1,
+            &maker_token0,
+            &escrow.0,
+            &spl_token::id(),
+            &pda_pubkey,
+        )
+        .unwrap()],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[&payer, &maker], recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    assert!(rpc_client.get_account(&escrow.0).is_err());
+    assert!(approve(&escrow, 1).is_err());
+
+    let balance = get_token_balance(&rpc_client, &maker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - 100 - 40);
+    let balance = get_token_balance(&rpc_client, &taker_token0);
+    assert_eq!(balance, INITIAL_MINT_AMOUNT + 100 + 40);
+}
+
+#[test]
+fn test_vesting() {
+    let prog_key = Pubkey::new_unique();
+    let (test_validator, payer) = clean_ledger_setup_validator(&prog_key).start();
+    let (rpc_client, recent_blockhash, _fee_calculator) = test_validator.rpc_client();
+
+    // maker funds vestings for taker
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let (mint0, _mint1, _mint_owner, maker_token0, taker_token0, _maker_token1, _taker_token1) =
+        create_tokens_and_mint(&rpc_client, &recent_blockhash, &payer, &maker, &taker);
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], &prog_key);
+    // the validator's clock follows the wall clock, schedules are placed hours around it
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    let init = |amount: u64, start_ts: i64, cliff_ts: i64, end_ts: i64, is_revocable: bool| {
+        let tmp_token0 = create_tmp_maker_token0(
+            &rpc_client,
+            &recent_blockhash,
+            &payer,
+            &mint0,
+            &maker,
+            &maker_token0,
+            amount,
+        );
+        let vesting_info = Keypair::new();
+        let state_space = Vesting::LEN;
+        let account_lamports = rpc_client
+            .get_minimum_balance_for_rent_exemption(state_space)
+            .unwrap();
+        let mut transaction = Transaction::new_with_payer(
+            &[
+                system_instruction::create_account(
+                    &payer.pubkey(),
+                    &vesting_info.pubkey(),
+                    account_lamports,
+                    state_space as u64,
+                    &prog_key,
+                ),
+                init_vesting(
+                    &prog_key,
+                    &maker.pubkey(),
+                    &tmp_token0,
+                    &maker_token0,
+                    &taker_token0,
+                    &vesting_info.pubkey(),
+                    &spl_token::id(),
+                    start_ts,
+                    cliff_ts,
+                    end_ts,
+                    is_revocable,
+                )
+                .unwrap(),
+            ],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &vesting_info, &maker], recent_blockhash);
+        assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+        (vesting_info.pubkey(), tmp_token0)
+    };
+    // anyone can withdraw for the beneficiary
+    let withdraw = |(vesting_info, tmp_token0): &(Pubkey, Pubkey)| {
+        let mut transaction = Transaction::new_with_payer(
+            &[withdraw_vested(
+                &prog_key,
+                tmp_token0,
+                &taker_token0,
+                &maker.pubkey(),
+                vesting_info,
+                &spl_token::id(),
+                &pda_pubkey,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    let revoke = |(vesting_info, tmp_token0): &(Pubkey, Pubkey)| {
+        let mut transaction = Transaction::new_with_payer(
+            &[revoke_vesting(
+                &prog_key,
+                &maker.pubkey(),
+                tmp_token0,
+                &taker_token0,
+                &maker_token0,
+                vesting_info,
+                &spl_token::id(),
+                &pda_pubkey,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &maker], recent_blockhash);
+        rpc_client.send_and_confirm_transaction(&transaction)
+    };
+    // tokens anyone can send to a vault
+    let send_dust = |tmp_token0: &Pubkey| {
+        let mut transaction = Transaction::new_with_payer(
+            &[spl_token::instruction::transfer(
+                &spl_token::id(),
+                &taker_token0,
+                tmp_token0,
+                &taker.pubkey(),
+                &[],
+                1,
+            )
+            .unwrap()],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &taker], recent_blockhash);
+        assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+    };
+    let balances = || {
+        (
+            get_token_balance(&rpc_client, &maker_token0),
+            get_token_balance(&rpc_client, &taker_token0),
+        )
+    };
+
+    // 1. nothing vests before the cliff, revoking then returns everything to the funder
+    let vesting = init(100, now - 3600, now + 3600, now + 7200, true);
+    let (maker_balance, taker_balance) = balances();
+    assert_matches!(withdraw(&vesting), Ok(_));
+    assert_eq!(balances(), (maker_balance, taker_balance));
+    assert_matches!(revoke(&vesting), Ok(_));
+    assert_eq!(balances(), (maker_balance + 100, taker_balance));
+    assert!(rpc_client.get_account(&vesting.0).is_err());
+    assert!(rpc_client.get_account(&vesting.1).is_err());
+
+    // 2. after the cliff the tokens vest linearly, one per second here, so about half are
+    // withdrawable halfway through. Revoking pays out what vested since and returns the rest
+    // of the vault, tokens sent to it included
+    let amount = 7200;
+    let vesting = init(amount, now - 3600, now - 3600, now + 3600, true);
+    let (maker_balance, taker_balance) = balances();
+    assert_matches!(withdraw(&vesting), Ok(_));
+    let withdrawn = get_token_balance(&rpc_client, &taker_token0) - taker_balance;
+    assert!(
+        withdrawn > amount / 2 - 600 && withdrawn < amount / 2 + 600,
+        "withdrew {}",
+        withdrawn
+    );
+    send_dust(&vesting.1);
+    assert_matches!(revoke(&vesting), Ok(_));
+    let (maker_returned, taker_vested) = balances();
+    let maker_returned = maker_returned - maker_balance;
+    let taker_vested = taker_vested + 1 - taker_balance;
+    assert!(taker_vested >= withdrawn && taker_vested < amount);
+    assert_eq!(maker_returned + taker_vested, amount + 1);
+    assert!(rpc_client.get_account(&vesting.0).is_err());
+    assert!(rpc_client.get_account(&vesting.1).is_err());
+
+    // 3. once the schedule ended everything is withdrawn at once, tokens sent to the vault
+    // included, and an irrevocable vesting can't be revoked
+    let vesting = init(100, now - 7200, now - 7200, now - 3600, false);
+    let (maker_balance, taker_balance) = balances();
+    assert!(revoke(&vesting).is_err());
+    send_dust(&vesting.1);
+    assert_matches!(withdraw(&vesting), Ok(_));
+    assert_eq!(balances(), (maker_balance, taker_balance + 100));
+    assert!(rpc_client.get_account(&vesting.0).is_err());
+    assert!(rpc_client.get_account(&vesting.1).is_err());
+}
+
+pub fn get_token_account(rpc_client: &RpcClient, pubkey: &Pubkey) -> spl_token::state::Account {
+    let token_account = rpc_client.get_account(pubkey).unwrap();
+    spl_token::state::Account::unpack(token_account.data.as_slice()).unwrap()
 }
 
 pub fn get_token_balance(rpc_client: &RpcClient, pubkey: &Pubkey) -> u64 {
-    let account = rpc_client.get_account(pubkey).unwrap();
-    let account_info = spl_token::state::Account::unpack(account.data.as_slice()).unwrap();
-    account_info.amount
+    get_token_account(rpc_client, pubkey).amount
 }
 
 fn create_tokens_and_mint(
@@ -272,6 +4119,208 @@ fn create_tmp_maker_token0(
     tmp_token0.pubkey()
 }
 
+/// creates an empty token account of mint
+fn create_token_account(
+    rpc_client: &RpcClient,
+    recent_blockhash: &Hash,
+    payer: &Keypair,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Pubkey {
+    let token_account = Keypair::new();
+
+    let state_space: u64 = spl_token::state::Account::LEN as u64;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space as usize)
+        .unwrap();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &token_account.pubkey(),
+                account_lamports,
+                state_space,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                &token_account.pubkey(),
+                mint,
+                owner,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[payer, &token_account], *recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    token_account.pubkey()
+}
+
+/// creates an spl-token multisig of the signers, m of which have to sign
+fn create_multisig(
+    rpc_client: &RpcClient,
+    recent_blockhash: &Hash,
+    payer: &Keypair,
+    signers: &[&Pubkey],
+    m: u8,
+) -> Pubkey {
+    let multisig = Keypair::new();
+
+    let state_space = spl_token::state::Multisig::LEN;
+    let account_lamports = rpc_client
+        .get_minimum_balance_for_rent_exemption(state_space)
+        .unwrap();
+
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &multisig.pubkey(),
+                account_lamports,
+                state_space as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_multisig(
+                &spl_token::id(),
+                &multisig.pubkey(),
+                signers,
+                m,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(&[payer, &multisig], *recent_blockhash);
+    assert_matches!(rpc_client.send_and_confirm_transaction(&transaction), Ok(_));
+
+    multisig.pubkey()
+}
+
+/// creates an empty token account of the native mint, which holds wrapped SOL
+fn create_native_account(
+    rpc_client: &RpcClient,
+    recent_blockhash: &Hash,
+    payer: &Keypair,
+    owner: &Pubkey,
+) -> Pubkey {
+    create_token_account(
+        rpc_client,
+        recent_blockhash,
+        payer,
+        &spl_token::native_mint::id(),
+        owner,
+    )
+}
+
+/// creates the option mint with the PDA as mint authority and the writer's account of it and
+/// writes the option, returns the option info, the option mint and the writer's option token account
+/// or the error the transaction failed with
+fn write_covered_option(
+    rpc_client: &RpcClient,
+    recent_blockhash: &Hash,
+    payer: &Keypair,
+    escrow_program_id: &Pubkey,
+    writer: &Keypair,
+    tmp_collateral: &Pubkey,
+    writer_payment: &Pubkey,
+    kind: OptionKind,
+    exercise_amount: u64,
+    expires_at: i64,
+) -> Result<(Pubkey, Pubkey, Pubkey), ClientError> {
+    let option_info = Keypair::new();
+    let option_mint = Keypair::new();
+    let writer_option_token = Keypair::new();
+    let (pda_pubkey, _) = Pubkey::find_program_address(&[b"escrow"], escrow_program_id);
+
+    let account_lamports = |space: usize| {
+        rpc_client
+            .get_minimum_balance_for_rent_exemption(space)
+            .unwrap()
+    };
+    let mut transaction = Transaction::new_with_payer(
+        &[
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &option_mint.pubkey(),
+                account_lamports(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint(
+                &spl_token::id(),
+                &option_mint.pubkey(),
+                &pda_pubkey,
+                None,
+                0,
+            )
+            .unwrap(),
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &writer_option_token.pubkey(),
+                account_lamports(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                &writer_option_token.pubkey(),
+                &option_mint.pubkey(),
+                &writer.pubkey(),
+            )
+            .unwrap(),
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &option_info.pubkey(),
+                account_lamports(CoveredOption::LEN),
+                CoveredOption::LEN as u64,
+                escrow_program_id,
+            ),
+            write_option(
+                escrow_program_id,
+                &writer.pubkey(),
+                tmp_collateral,
+                writer_payment,
+                &option_mint.pubkey(),
+                &writer_option_token.pubkey(),
+                &option_info.pubkey(),
+                &pda_pubkey,
+                kind,
+                exercise_amount,
+                expires_at,
+            )
+            .unwrap(),
+        ],
+        Some(&payer.pubkey()),
+    );
+    transaction.sign(
+        &[
+            payer,
+            &option_mint,
+            &writer_option_token,
+            &option_info,
+            writer,
+        ],
+        *recent_blockhash,
+    );
+    rpc_client.send_and_confirm_transaction(&transaction)?;
+
+    let mint = spl_token::state::Mint::unpack(
+        &rpc_client.get_account(&option_mint.pubkey()).unwrap().data,
+    )
+    .unwrap();
+    assert_eq!(mint.supply, 1);
+    assert!(mint.mint_authority.is_none());
+
+    Ok((
+        option_info.pubkey(),
+        option_mint.pubkey(),
+        writer_option_token.pubkey(),
+    ))
+}
+
 fn start_escrow(
     rpc_client: &RpcClient,
     recent_blockhash: &Hash,
@@ -0,0 +1,518 @@
+#![cfg(feature = "test-bpf")]
+
+use solana_escrow::{
+    error::EscrowError,
+    instruction::{exchange_multi_asset, init_multi_asset_escrow},
+    state::Escrow,
+    token::token_2022,
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction,
+};
+use solana_program_test::{BanksClient, ProgramTest};
+use solana_sdk::{
+    hash::Hash,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+const INITIAL_MINT_AMOUNT: u64 = 10_000;
+const DECIMALS: u8 = 6;
+
+/// length of Token-2022 mints and accounts with extensions before the first extension
+const EXTENSIONS_START: usize = spl_token::state::Account::LEN + 1;
+/// length of an extension's type and length
+const EXTENSION_HEADER_LEN: usize = 4;
+
+/// The Token-2022 program isn't checked in, the tests skip themselves until it is dumped to
+/// tests/fixtures as the README describes
+fn token_2022_missing() -> bool {
+    let missing = solana_program_test::find_file("spl_token_2022.so").is_none();
+    if missing {
+        eprintln!(
+            "skipping: tests/fixtures/spl_token_2022.so not found, dump it with \
+             `solana program dump -u m {} tests/fixtures/spl_token_2022.so`",
+            token_2022::id()
+        );
+    }
+    missing
+}
+
+fn program_test(program_id: &Pubkey) -> ProgramTest {
+    let mut program_test = ProgramTest::new("solana_escrow", *program_id, None);
+    program_test.add_program("spl_token_2022", token_2022::id(), None);
+    program_test
+}
+
+/// Token-2022 shares the classic instruction layouts
+fn for_token_2022(mut instruction: Instruction) -> Instruction {
+    instruction.program_id = token_2022::id();
+    instruction
+}
+
+async fn process(
+    banks_client: &mut BanksClient,
+    recent_blockhash: &Hash,
+    payer: &Keypair,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let mut transaction = Transaction::new_with_payer(instructions, Some(&payer.pubkey()));
+    let mut all_signers = vec![payer];
+    all_signers.extend_from_slice(signers);
+    transaction.sign(&all_signers, *recent_blockhash);
+    banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|err| err.unwrap())
+}
+
+/// Creates a Token-2022 mint, the extension instructions run before the mint is initialized
+async fn create_mint(
+    banks_client: &mut BanksClient,
+    recent_blockhash: &Hash,
+    payer: &Keypair,
+    mint_owner: &Keypair,
+    space: usize,
+    extensions: &dyn Fn(&Pubkey) -> Vec<Instruction>,
+) -> Pubkey {
+    let mint = Keypair::new();
+    let rent = banks_client.get_rent().await.unwrap();
+    let mut instructions = vec![system_instruction::create_account(
+        &payer.pubkey(),
+        &mint.pubkey(),
+        rent.minimum_balance(space),
+        space as u64,
+        &token_2022::id(),
+    )];
+    instructions.extend(extensions(&mint.pubkey()));
+    instructions.push(for_token_2022(
+        spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &mint_owner.pubkey(),
+            None,
+            DECIMALS,
+        )
+        .unwrap(),
+    ));
+    process(
+        banks_client,
+        recent_blockhash,
+        payer,
+        &instructions,
+        &[&mint],
+    )
+    .await
+    .unwrap();
+    mint.pubkey()
+}
+
+/// Creates a Token-2022 account of mint owned by owner and mints amount to it
+async fn create_account(
+    banks_client: &mut BanksClient,
+    recent_blockhash: &Hash,
+    payer: &Keypair,
+    mint: &Pubkey,
+    mint_owner: &Keypair,
+    owner: &Keypair,
+    space: usize,
+    amount: u64,
+) -> Pubkey {
+    let account = Keypair::new();
+    let rent = banks_client.get_rent().await.unwrap();
+    let instructions = vec![
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &account.pubkey(),
+            rent.minimum_balance(space),
+            space as u64,
+            &token_2022::id(),
+        ),
+        for_token_2022(
+            spl_token::instruction::initialize_account(
+                &spl_token::id(),
+                &account.pubkey(),
+                mint,
+                &owner.pubkey(),
+            )
+            .unwrap(),
+        ),
+        for_token_2022(
+            spl_token::instruction::mint_to(
+                &spl_token::id(),
+                mint,
+                &account.pubkey(),
+                &mint_owner.pubkey(),
+                &[],
+                amount,
+            )
+            .unwrap(),
+        ),
+    ];
+    process(
+        banks_client,
+        recent_blockhash,
+        payer,
+        &instructions,
+        &[&account, mint_owner],
+    )
+    .await
+    .unwrap();
+    account.pubkey()
+}
+
+async fn get_token_balance(banks_client: &mut BanksClient, pubkey: &Pubkey) -> u64 {
+    let account = banks_client.get_account(*pubkey).await.unwrap().unwrap();
+    spl_token::state::Account::unpack_from_slice(&account.data[..spl_token::state::Account::LEN])
+        .unwrap()
+        .amount
+}
+
+/// InitializeTransferFeeConfig of 1% capped at 1000 tokens
+fn initialize_transfer_fee_config(mint: &Pubkey, authority: &Pubkey) -> Instruction {
+    let mut data = vec![26, 0];
+    for _ in 0..2 {
+        data.push(1);
+        data.extend_from_slice(authority.as_ref());
+    }
+    data.extend_from_slice(&100u16.to_le_bytes());
+    data.extend_from_slice(&1000u64.to_le_bytes());
+    Instruction {
+        program_id: token_2022::id(),
+        accounts: vec![AccountMeta::new(*mint, false)],
+        data,
+    }
+}
+
+fn initialize_non_transferable_mint(mint: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: token_2022::id(),
+        accounts: vec![AccountMeta::new(*mint, false)],
+        data: vec![32],
+    }
+}
+
+fn enable_required_transfer_memos(account: &Pubkey, owner: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: token_2022::id(),
+        accounts: vec![
+            AccountMeta::new(*account, false),
+            AccountMeta::new_readonly(*owner, true),
+        ],
+        data: vec![30, 0],
+    }
+}
+
+/// Creates the escrow account and calls InitEscrow for a plain Token-2022 escrow
+async fn start_escrow(
+    banks_client: &mut BanksClient,
+    recent_blockhash: &Hash,
+    payer: &Keypair,
+    program_id: &Pubkey,
+    maker: &Keypair,
+    tmp_token0: &Pubkey,
+    mint0: &Pubkey,
+    maker_token1: &Pubkey,
+    mint1: &Pubkey,
+    amount: u64,
+) -> Result<Pubkey, TransactionError> {
+    let escrow_info = Keypair::new();
+    let rent = banks_client.get_rent().await.unwrap();
+    let instructions = vec![
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &escrow_info.pubkey(),
+            rent.minimum_balance(Escrow::LEN),
+            Escrow::LEN as u64,
+            program_id,
+        ),
+        init_multi_asset_escrow(
+            program_id,
+            &maker.pubkey(),
+            tmp_token0,
+            &[],
+            maker_token1,
+            &[],
+            &escrow_info.pubkey(),
+            &token_2022::id(),
+            Some((mint0, mint1)),
+            None,
+            None,
+            amount,
+        )
+        .unwrap(),
+    ];
+    process(
+        banks_client,
+        recent_blockhash,
+        payer,
+        &instructions,
+        &[&escrow_info, maker],
+    )
+    .await?;
+    Ok(escrow_info.pubkey())
+}
+
+#[tokio::test]
+async fn test_token_2022_escrow() {
+    if token_2022_missing() {
+        return;
+    }
+    let program_id = Pubkey::new_unique();
+    let (mut banks_client, payer, recent_blockhash) = program_test(&program_id).start().await;
+
+    let mint_owner = Keypair::new();
+    let maker = Keypair::new();
+    let taker = Keypair::new();
+    let escrow_token0_amount: u64 = 100;
+    let escrow_token1_amount: u64 = 200;
+
+    let no_extensions = |_: &Pubkey| Vec::new();
+    let mint0 = create_mint(
+        &mut banks_client,
+        &recent_blockhash,
+        &payer,
+        &mint_owner,
+        spl_token::state::Mint::LEN,
+        &no_extensions,
+    )
+    .await;
+    let mint1 = create_mint(
+        &mut banks_client,
+        &recent_blockhash,
+        &payer,
+        &mint_owner,
+        spl_token::state::Mint::LEN,
+        &no_extensions,
+    )
+    .await;
+
+    let account_len = spl_token::state::Account::LEN;
+    let mut accounts = Vec::new();
+    for (mint, owner, amount) in vec![
+        (&mint0, &maker, escrow_token0_amount),
+        (&mint1, &maker, 0),
+        (&mint0, &taker, 0),
+        (&mint1, &taker, INITIAL_MINT_AMOUNT),
+    ] {
+        accounts.push(
+            create_account(
+                &mut banks_client,
+                &recent_blockhash,
+                &payer,
+                mint,
+                &mint_owner,
+                owner,
+                account_len,
+                amount,
+            )
+            .await,
+        );
+    }
+    let (tmp_token0, maker_token1, taker_token0, taker_token1) =
+        (accounts[0], accounts[1], accounts[2], accounts[3]);
+
+    let escrow_info = start_escrow(
+        &mut banks_client,
+        &recent_blockhash,
+        &payer,
+        &program_id,
+        &maker,
+        &tmp_token0,
+        &mint0,
+        &maker_token1,
+        &mint1,
+        escrow_token1_amount,
+    )
+    .await
+    .unwrap();
+
+    let (pda, _) = Pubkey::find_program_address(&[b"escrow"], &program_id);
+    let instruction = exchange_multi_asset(
+        &program_id,
+        &taker.pubkey(),
+        &taker_token1,
+        &taker_token0,
+        &tmp_token0,
+        &[],
+        &maker.pubkey(),
+        &maker_token1,
+        &[],
+        &escrow_info,
+        &token_2022::id(),
+        &pda,
+        Some((&mint0, &mint1)),
+        None,
+        None,
+        escrow_token0_amount,
+    )
+    .unwrap();
+    process(
+        &mut banks_client,
+        &recent_blockhash,
+        &payer,
+        &[instruction],
+        &[&taker],
+    )
+    .await
+    .unwrap();
+
+    let balance = get_token_balance(&mut banks_client, &maker_token1).await;
+    assert_eq!(balance, escrow_token1_amount);
+    let balance = get_token_balance(&mut banks_client, &taker_token0).await;
+    assert_eq!(balance, escrow_token0_amount);
+    let balance = get_token_balance(&mut banks_client, &taker_token1).await;
+    assert_eq!(balance, INITIAL_MINT_AMOUNT - escrow_token1_amount);
+}
+
+/// Tries to start an escrow offering mint0, created with the given extensions, for a plain mint1
+async fn start_escrow_with_extensions(
+    mint0_len: usize,
+    mint0_extensions: &dyn Fn(&Pubkey) -> Vec<Instruction>,
+    account0_len: usize,
+    require_memos_on_maker_token1: bool,
+) -> Result<Pubkey, TransactionError> {
+    let program_id = Pubkey::new_unique();
+    let (mut banks_client, payer, recent_blockhash) = program_test(&program_id).start().await;
+
+    let mint_owner = Keypair::new();
+    let maker = Keypair::new();
+
+    let mint0 = create_mint(
+        &mut banks_client,
+        &recent_blockhash,
+        &payer,
+        &mint_owner,
+        mint0_len,
+        mint0_extensions,
+    )
+    .await;
+    let mint1 = create_mint(
+        &mut banks_client,
+        &recent_blockhash,
+        &payer,
+        &mint_owner,
+        spl_token::state::Mint::LEN,
+        &|_| Vec::new(),
+    )
+    .await;
+
+    let tmp_token0 = create_account(
+        &mut banks_client,
+        &recent_blockhash,
+        &payer,
+        &mint0,
+        &mint_owner,
+        &maker,
+        account0_len,
+        100,
+    )
+    .await;
+    // room for the memo transfer extension, a single bool
+    let maker_token1 = create_account(
+        &mut banks_client,
+        &recent_blockhash,
+        &payer,
+        &mint1,
+        &mint_owner,
+        &maker,
+        EXTENSIONS_START + EXTENSION_HEADER_LEN + 1,
+        0,
+    )
+    .await;
+    if require_memos_on_maker_token1 {
+        process(
+            &mut banks_client,
+            &recent_blockhash,
+            &payer,
+            &[enable_required_transfer_memos(
+                &maker_token1,
+                &maker.pubkey(),
+            )],
+            &[&maker],
+        )
+        .await
+        .unwrap();
+    }
+
+    start_escrow(
+        &mut banks_client,
+        &recent_blockhash,
+        &payer,
+        &program_id,
+        &maker,
+        &tmp_token0,
+        &mint0,
+        &maker_token1,
+        &mint1,
+        200,
+    )
+    .await
+}
+
+fn init_escrow_error(error: EscrowError) -> TransactionError {
+    // the escrow account is created first, InitEscrow is the second instruction
+    TransactionError::InstructionError(1, InstructionError::Custom(error as u32))
+}
+
+#[tokio::test]
+async fn test_token_2022_rejects_transfer_fee_mint() {
+    if token_2022_missing() {
+        return;
+    }
+    let authority = Pubkey::new_unique();
+    // the transfer fee config holds 2 authorities, the withheld amount and 2 fees
+    let result = start_escrow_with_extensions(
+        EXTENSIONS_START + EXTENSION_HEADER_LEN + 108,
+        &|mint| vec![initialize_transfer_fee_config(mint, &authority)],
+        // accounts of the mint carry their withheld fees
+        EXTENSIONS_START + EXTENSION_HEADER_LEN + 8,
+        false,
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err(),
+        init_escrow_error(EscrowError::TransferFeeMint)
+    );
+}
+
+#[tokio::test]
+async fn test_token_2022_rejects_non_transferable_mint() {
+    if token_2022_missing() {
+        return;
+    }
+    let result = start_escrow_with_extensions(
+        EXTENSIONS_START + EXTENSION_HEADER_LEN,
+        &|mint| vec![initialize_non_transferable_mint(mint)],
+        // accounts of the mint are non-transferable and have an immutable owner, both empty
+        EXTENSIONS_START + 2 * EXTENSION_HEADER_LEN,
+        false,
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err(),
+        init_escrow_error(EscrowError::NonTransferableMint)
+    );
+}
+
+#[tokio::test]
+async fn test_token_2022_rejects_memo_required_recipient() {
+    if token_2022_missing() {
+        return;
+    }
+    let result = start_escrow_with_extensions(
+        spl_token::state::Mint::LEN,
+        &|_| Vec::new(),
+        spl_token::state::Account::LEN,
+        true,
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err(),
+        init_escrow_error(EscrowError::MemoTransferRequired)
+    );
+}